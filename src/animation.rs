@@ -4,17 +4,21 @@ use std::collections::HashMap;
 use tunnels::animation::{Animation, EmitStateChange as EmitAnimationStateChange};
 
 use crate::{
-    control::EmitScopedControlMessage,
+    control::{EmitScopedControlMessage, SurfaceId},
     fixture::{
         animation_target::{AnimationTargetIndex, ControllableTargetedAnimation, N_ANIM},
         FixtureGroup,
     },
-    osc::{GroupControlMap, OscControlMessage},
+    osc::{GroupControlMap, OscControlMessage, TalkbackMode},
     show::ChannelId,
 };
 
 pub struct AnimationUIState {
+    /// The animator selected for each channel, for any control surface that
+    /// hasn't selected one of its own.
     selected_animator_by_channel: HashMap<ChannelId, usize>,
+    /// The animator selected for each channel by each control surface.
+    selected_animator_by_surface: HashMap<(SurfaceId, ChannelId), usize>,
     clipboard: Animation,
     controls: GroupControlMap<ControlMessage>,
 }
@@ -25,6 +29,7 @@ impl AnimationUIState {
         Self::map_controls(&mut controls);
         let mut state = Self {
             selected_animator_by_channel: Default::default(),
+            selected_animator_by_surface: Default::default(),
             clipboard: Default::default(),
             controls,
         };
@@ -34,14 +39,21 @@ impl AnimationUIState {
         state
     }
 
-    /// Emit all current animation state, including target and selection.
+    /// Return the names of all OSC controls.
+    pub fn control_names(&self) -> impl Iterator<Item = &str> {
+        self.controls.control_names()
+    }
+
+    /// Emit all current animation state, including target and selection, for
+    /// the animator selected by the provided control surface.
     pub fn emit_state(
         &self,
         channel: ChannelId,
+        surface: Option<&SurfaceId>,
         group: &FixtureGroup,
         emitter: &dyn EmitScopedControlMessage,
     ) -> anyhow::Result<()> {
-        let (ta, index) = self.current_animation_with_index(channel, group)?;
+        let (ta, index) = self.current_animation_with_index(channel, surface, group)?;
         ta.anim().emit_state(&mut InnerAnimationEmitter(emitter));
         Self::emit_osc_state_change(StateChange::Target(ta.target()), emitter);
         Self::emit_osc_state_change(StateChange::SelectAnimation(index), emitter);
@@ -50,21 +62,25 @@ impl AnimationUIState {
     }
 
     /// Handle a control message.
+    ///
+    /// Animator selection made from a control surface only applies to that
+    /// surface, and the resulting state is only sent back to it.
     pub fn control(
         &mut self,
         msg: ControlMessage,
         channel: ChannelId,
+        surface: Option<&SurfaceId>,
         group: &mut FixtureGroup,
         emitter: &dyn EmitScopedControlMessage,
     ) -> anyhow::Result<()> {
         match msg {
             ControlMessage::Animation(msg) => {
-                self.current_animation(channel, group)?
+                self.current_animation(channel, surface, group)?
                     .anim_mut()
                     .control(msg, &mut InnerAnimationEmitter(emitter));
             }
             ControlMessage::Target(msg) => {
-                let anim = self.current_animation(channel, group)?;
+                let anim = self.current_animation(channel, surface, group)?;
                 if anim.target() == msg {
                     return Ok(());
                 }
@@ -72,18 +88,28 @@ impl AnimationUIState {
                 Self::emit_osc_state_change(StateChange::Target(msg), emitter);
             }
             ControlMessage::SelectAnimation(n) => {
-                if self.animation_index_for_channel(channel) == n {
+                if self.animation_index(channel, surface) == n {
                     return Ok(());
                 }
-                self.set_current_animation(channel, n)?;
-                self.emit_state(channel, group, emitter)?;
+                match surface {
+                    Some(surface) => {
+                        self.select_animation(channel, *surface, n)?;
+                        emitter.set_talkback(TalkbackMode::SenderOnly);
+                    }
+                    None => self.set_current_animation(channel, n)?,
+                }
+                self.emit_state(channel, surface, group, emitter)?;
             }
             ControlMessage::Copy => {
-                self.clipboard = self.current_animation(channel, group)?.anim().clone();
+                self.clipboard = self
+                    .current_animation(channel, surface, group)?
+                    .anim()
+                    .clone();
             }
             ControlMessage::Paste => {
-                *self.current_animation(channel, group)?.anim_mut() = self.clipboard.clone();
-                self.emit_state(channel, group, emitter)?;
+                *self.current_animation(channel, surface, group)?.anim_mut() =
+                    self.clipboard.clone();
+                self.emit_state(channel, surface, group, emitter)?;
             }
         }
         Ok(())
@@ -94,21 +120,24 @@ impl AnimationUIState {
         &mut self,
         msg: &OscControlMessage,
         channel: ChannelId,
+        surface: Option<&SurfaceId>,
         group: &mut FixtureGroup,
         emitter: &dyn EmitScopedControlMessage,
     ) -> anyhow::Result<()> {
-        let Some((ctl, _)) = self.controls.handle(msg)? else {
+        let Some((ctl, talkback)) = self.controls.handle(msg)? else {
             return Ok(());
         };
-        self.control(ctl, channel, group, emitter)
+        emitter.set_talkback(talkback);
+        self.control(ctl, channel, surface, group, emitter)
     }
 
     fn current_animation_with_index_mut<'a>(
         &self,
         channel: ChannelId,
+        surface: Option<&SurfaceId>,
         group: &'a mut FixtureGroup,
     ) -> Result<(&'a mut dyn ControllableTargetedAnimation, usize)> {
-        let animation_index = self.animation_index_for_channel(channel);
+        let animation_index = self.animation_index(channel, surface);
         let key = group.key().clone();
         if let Some(anim) = group.get_animation_mut(animation_index) {
             return Ok((anim, animation_index));
@@ -119,9 +148,10 @@ impl AnimationUIState {
     fn current_animation_with_index<'a>(
         &self,
         channel: ChannelId,
+        surface: Option<&SurfaceId>,
         group: &'a FixtureGroup,
     ) -> Result<(&'a dyn ControllableTargetedAnimation, usize)> {
-        let animation_index = self.animation_index_for_channel(channel);
+        let animation_index = self.animation_index(channel, surface);
         let key = group.key().clone();
         if let Some(anim) = group.get_animation(animation_index) {
             return Ok((anim, animation_index));
@@ -132,19 +162,34 @@ impl AnimationUIState {
     fn current_animation<'a>(
         &self,
         channel: ChannelId,
+        surface: Option<&SurfaceId>,
         group: &'a mut FixtureGroup,
     ) -> Result<&'a mut dyn ControllableTargetedAnimation> {
-        let (ta, _) = self.current_animation_with_index_mut(channel, group)?;
+        let (ta, _) = self.current_animation_with_index_mut(channel, surface, group)?;
         Ok(ta)
     }
 
-    fn animation_index_for_channel(&self, channel: ChannelId) -> usize {
+    /// Return the index of the animator selected for the provided channel.
+    pub fn animation_index_for_channel(&self, channel: ChannelId) -> usize {
         self.selected_animator_by_channel
             .get(&channel)
             .cloned()
             .unwrap_or_default()
     }
 
+    /// Return the index of the animator selected for the provided channel by
+    /// the provided control surface.
+    /// Fall back to the channel's animator if the surface hasn't selected one.
+    pub fn animation_index(&self, channel: ChannelId, surface: Option<&SurfaceId>) -> usize {
+        surface
+            .and_then(|surface| {
+                self.selected_animator_by_surface
+                    .get(&(*surface, channel))
+                    .copied()
+            })
+            .unwrap_or_else(|| self.animation_index_for_channel(channel))
+    }
+
     /// Set the current animation for the current channel to the provided value.
     pub fn set_current_animation(&mut self, channel: ChannelId, n: usize) -> anyhow::Result<()> {
         if n > N_ANIM {
@@ -153,6 +198,27 @@ impl AnimationUIState {
         self.selected_animator_by_channel.insert(channel, n);
         Ok(())
     }
+
+    /// Drop every animator selection of a control surface that has gone away.
+    pub fn forget_surface(&mut self, surface: &SurfaceId) {
+        self.selected_animator_by_surface
+            .retain(|(s, _), _| s != surface);
+    }
+
+    /// Set the animation selected by a control surface for the provided channel.
+    fn select_animation(
+        &mut self,
+        channel: ChannelId,
+        surface: SurfaceId,
+        n: usize,
+    ) -> anyhow::Result<()> {
+        if n > N_ANIM {
+            bail!("animator index {n} out of range");
+        }
+        self.selected_animator_by_surface
+            .insert((surface, channel), n);
+        Ok(())
+    }
 }
 
 struct InnerAnimationEmitter<'a>(&'a dyn EmitScopedControlMessage);
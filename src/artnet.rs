@@ -0,0 +1,245 @@
+//! Send DMX universes over the network as Art-Net ArtDmx packets.
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use anyhow::{ensure, Context, Result};
+use log::info;
+use serde::Deserialize;
+
+use crate::dmx::{DmxBuffer, DmxOutput, UniverseIdx};
+
+/// The standard Art-Net UDP port.
+pub const ARTNET_PORT: u16 = 6454;
+
+/// Resend unchanged frames this often so nodes don't time out.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
+
+const ARTNET_ID: &[u8; 8] = b"Art-Net\0";
+const OP_DMX: u16 = 0x5000;
+const PROTOCOL_VERSION: u16 = 14;
+const HEADER_LEN: usize = 18;
+
+/// Send a show universe to an Art-Net port-address.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ArtNetConfig {
+    /// The show universe to send.
+    pub universe: UniverseIdx,
+    /// Art-Net net, 0-127.
+    #[serde(default)]
+    pub net: u8,
+    /// Art-Net subnet, 0-15.
+    #[serde(default)]
+    pub subnet: u8,
+    /// Art-Net universe within the subnet, 0-15.
+    #[serde(default)]
+    pub artnet_universe: u8,
+    /// The address to send to.
+    /// Defaults to the limited broadcast address.
+    #[serde(default = "default_destination")]
+    pub destination: IpAddr,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// If true, number packets so receivers can discard out-of-order frames.
+    #[serde(default = "default_sequence")]
+    pub sequence: bool,
+}
+
+impl ArtNetConfig {
+    /// Return the 15-bit Art-Net port-address.
+    pub fn port_address(&self) -> Result<u16> {
+        ensure!(self.net < 128, "Art-Net net {} is out of range", self.net);
+        ensure!(
+            self.subnet < 16,
+            "Art-Net subnet {} is out of range",
+            self.subnet
+        );
+        ensure!(
+            self.artnet_universe < 16,
+            "Art-Net universe {} is out of range",
+            self.artnet_universe
+        );
+        Ok(((self.net as u16) << 8) | ((self.subnet as u16) << 4) | self.artnet_universe as u16)
+    }
+}
+
+/// Send a single universe as ArtDmx.
+pub struct ArtNetOutput {
+    socket: UdpSocket,
+    destination: SocketAddr,
+    port_address: u16,
+    /// The next sequence number to send, or None if sequencing is disabled.
+    sequence: Option<u8>,
+    last_frame: Option<DmxBuffer>,
+    last_send: Instant,
+}
+
+impl ArtNetOutput {
+    pub fn new(cfg: &ArtNetConfig) -> Result<Self> {
+        let port_address = cfg.port_address()?;
+        let socket =
+            UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).context("binding Art-Net socket")?;
+        socket
+            .set_broadcast(true)
+            .context("enabling broadcast on Art-Net socket")?;
+        let destination = SocketAddr::new(cfg.destination, cfg.port);
+        info!(
+            "Sending universe {} to Art-Net {}:{}:{} at {destination}.",
+            cfg.universe, cfg.net, cfg.subnet, cfg.artnet_universe
+        );
+        Ok(Self {
+            socket,
+            destination,
+            port_address,
+            sequence: cfg.sequence.then_some(1),
+            last_frame: None,
+            last_send: Instant::now(),
+        })
+    }
+
+    /// Return the next sequence number to send, advancing the counter.
+    /// Zero is reserved to mean "not sequenced", so the counter wraps to 1.
+    fn next_sequence(&mut self) -> u8 {
+        let Some(seq) = self.sequence.as_mut() else {
+            return 0;
+        };
+        let current = *seq;
+        *seq = if current == u8::MAX { 1 } else { current + 1 };
+        current
+    }
+
+    fn packet(&mut self, frame: &DmxBuffer) -> [u8; HEADER_LEN + 512] {
+        let mut packet = [0; HEADER_LEN + 512];
+        packet[0..8].copy_from_slice(ARTNET_ID);
+        packet[8..10].copy_from_slice(&OP_DMX.to_le_bytes());
+        packet[10..12].copy_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+        packet[12] = self.next_sequence();
+        packet[13] = 0; // physical input port; informational only
+        packet[14..16].copy_from_slice(&self.port_address.to_le_bytes());
+        packet[16..18].copy_from_slice(&(frame.len() as u16).to_be_bytes());
+        packet[HEADER_LEN..].copy_from_slice(frame);
+        packet
+    }
+}
+
+impl DmxOutput for ArtNetOutput {
+    /// Send the frame if it has changed, or if the keepalive interval has
+    /// elapsed since the last send.
+    fn write(&mut self, frame: &DmxBuffer) -> Result<()> {
+        let changed = self.last_frame.as_ref() != Some(frame);
+        if !changed && self.last_send.elapsed() < KEEPALIVE_INTERVAL {
+            return Ok(());
+        }
+        let packet = self.packet(frame);
+        self.socket
+            .send_to(&packet, self.destination)
+            .with_context(|| format!("sending Art-Net to {}", self.destination))?;
+        self.last_frame = Some(*frame);
+        self.last_send = Instant::now();
+        Ok(())
+    }
+}
+
+/// Parse an ArtDmx packet, returning its port-address and DMX data.
+/// Return None if the packet is not ArtDmx.
+pub fn parse_artdmx(packet: &[u8]) -> Option<(u16, &[u8])> {
+    if packet.len() < HEADER_LEN || &packet[0..8] != ARTNET_ID {
+        return None;
+    }
+    if u16::from_le_bytes([packet[8], packet[9]]) != OP_DMX {
+        return None;
+    }
+    let port_address = u16::from_le_bytes([packet[14], packet[15]]);
+    let len = u16::from_be_bytes([packet[16], packet[17]]) as usize;
+    let data = packet.get(HEADER_LEN..HEADER_LEN + len.min(512))?;
+    Some((port_address, data))
+}
+
+fn default_destination() -> IpAddr {
+    IpAddr::V4(Ipv4Addr::BROADCAST)
+}
+
+const fn default_port() -> u16 {
+    ARTNET_PORT
+}
+
+const fn default_sequence() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn listen() -> (UdpSocket, ArtNetConfig) {
+        let listener = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let cfg = ArtNetConfig {
+            universe: 0,
+            net: 1,
+            subnet: 2,
+            artnet_universe: 3,
+            destination: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: listener.local_addr().unwrap().port(),
+            sequence: true,
+        };
+        (listener, cfg)
+    }
+
+    fn recv(listener: &UdpSocket) -> Option<Vec<u8>> {
+        let mut buf = [0; 1024];
+        let (len, _) = listener.recv_from(&mut buf).ok()?;
+        Some(buf[..len].to_vec())
+    }
+
+    #[test]
+    fn test_send_artdmx() {
+        let (listener, cfg) = listen();
+        let mut output = ArtNetOutput::new(&cfg).unwrap();
+        let mut frame = [0; 512];
+        frame[0] = 255;
+        frame[511] = 7;
+        output.write(&frame).unwrap();
+
+        let packet = recv(&listener).unwrap();
+        assert_eq!(HEADER_LEN + 512, packet.len());
+        assert_eq!(ARTNET_ID, &packet[0..8]);
+        assert_eq!([0x00, 0x50], packet[8..10]);
+        assert_eq!([0, 14], packet[10..12]);
+        assert_eq!(1, packet[12]);
+        assert_eq!(0x23, packet[14]);
+        assert_eq!(1, packet[15]);
+        assert_eq!([2, 0], packet[16..18]);
+        assert_eq!(255, packet[HEADER_LEN]);
+        assert_eq!(7, packet[HEADER_LEN + 511]);
+
+        // Unchanged frames are held back until the keepalive is due.
+        output.write(&frame).unwrap();
+        assert!(recv(&listener).is_none());
+
+        frame[0] = 0;
+        output.write(&frame).unwrap();
+        let packet = recv(&listener).unwrap();
+        assert_eq!(2, packet[12]);
+        assert_eq!(0, packet[HEADER_LEN]);
+
+        let (port_address, data) = parse_artdmx(&packet).unwrap();
+        assert_eq!(0x0123, port_address);
+        assert_eq!(&frame[..], data);
+    }
+
+    #[test]
+    fn test_sequence_wraps_past_zero() {
+        let (_listener, cfg) = listen();
+        let mut output = ArtNetOutput::new(&cfg).unwrap();
+        output.sequence = Some(u8::MAX);
+        assert_eq!(u8::MAX, output.next_sequence());
+        assert_eq!(1, output.next_sequence());
+
+        output.sequence = None;
+        assert_eq!(0, output.next_sequence());
+    }
+}
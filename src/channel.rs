@@ -5,18 +5,25 @@ use std::{collections::HashMap, fmt::Display};
 use anyhow::{anyhow, bail, Context, Result};
 use log::{debug, error};
 use number::{BipolarFloat, UnipolarFloat};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     animation::AnimationUIState,
-    control::EmitControlMessage,
+    control::{EmitControlMessage, SurfaceId},
     fixture::{FixtureGroup, FixtureGroupKey, Patch},
-    osc::{EmitOscMessage, GroupControlMap, OscControlMessage, ScopedControlEmitter},
+    osc::{
+        ControlKind, EmitOscMessage, GroupControlMap, OscControlMessage, ScopedControlEmitter,
+        TalkbackMode,
+    },
     wled::EmitWledControlMessage,
 };
 
+/// The number of channels in a bank; control surfaces show one bank of
+/// channels at a time.
+pub const BANK_SIZE: usize = 8;
+
 /// The index of a channel.
-#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Deserialize)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct ChannelId(usize);
 
 impl ChannelId {
@@ -42,8 +49,13 @@ pub struct Channels {
     channel_index: Vec<FixtureGroupKey>,
     /// Reverse-lookup from fixture group key to channel index.
     fixture_channel_index: HashMap<FixtureGroupKey, ChannelId>,
-    /// The channel ID that is currently selected.
+    /// The channel ID that is currently selected, for any control surface
+    /// that hasn't selected a channel of its own.
     current_channel: Option<ChannelId>,
+    /// The channel selected by each control surface.
+    selected_by_surface: HashMap<SurfaceId, ChannelId>,
+    /// The index of the bank of channels shown on every control surface.
+    bank: usize,
     controls: GroupControlMap<ControlMessage>,
 }
 
@@ -55,6 +67,8 @@ impl Channels {
             channel_index: Default::default(),
             fixture_channel_index: Default::default(),
             current_channel: Default::default(),
+            selected_by_surface: Default::default(),
+            bank: 0,
             controls,
         }
     }
@@ -91,6 +105,46 @@ impl Channels {
         }
     }
 
+    /// Return the index of the visible bank of channels.
+    pub fn bank(&self) -> usize {
+        self.bank
+    }
+
+    /// Return the number of banks needed to reach every channel.
+    pub fn bank_count(&self) -> usize {
+        self.channel_index.len().div_ceil(BANK_SIZE).max(1)
+    }
+
+    /// Show the provided bank of channels, limited to the last bank.
+    pub fn set_bank(&mut self, bank: usize) {
+        self.bank = bank.min(self.bank_count() - 1);
+    }
+
+    /// Return the position of a channel relative to the start of the visible
+    /// bank, as used by control surfaces.
+    /// Return None if the channel is outside of the visible bank.
+    pub fn visible(&self, channel: ChannelId) -> Option<ChannelId> {
+        channel
+            .0
+            .checked_sub(self.bank * BANK_SIZE)
+            .filter(|channel| *channel < BANK_SIZE)
+            .map(ChannelId)
+    }
+
+    /// Look up the channel in the visible bank assigned to a fixture group.
+    pub fn visible_channel_for_fixture(&self, group: &FixtureGroupKey) -> Option<ChannelId> {
+        self.channel_for_fixture(group)
+            .and_then(|channel| self.visible(channel))
+    }
+
+    /// Validate a channel index relative to the start of the visible bank.
+    fn validate_channel_in_bank(&self, channel: usize) -> Result<ChannelId> {
+        if channel >= BANK_SIZE {
+            bail!("channel selector {channel} out of range, banks have {BANK_SIZE} channels");
+        }
+        self.validate_channel(self.bank * BANK_SIZE + channel)
+    }
+
     /// Look up a channel ID by fixture group key.
     pub fn channel_for_fixture(&self, group: &FixtureGroupKey) -> Option<ChannelId> {
         self.fixture_channel_index.get(group).cloned()
@@ -145,10 +199,58 @@ impl Channels {
         self.current_channel
     }
 
-    /// Emit all current channel state.
+    /// Return the channel selected by the provided control surface.
+    /// Fall back to the current channel if the surface hasn't selected one.
+    pub fn selected_channel(&self, surface: Option<&SurfaceId>) -> Option<ChannelId> {
+        surface
+            .and_then(|surface| self.selected_by_surface.get(surface).copied())
+            .or(self.current_channel)
+    }
+
+    /// Drop the channel selection of a control surface that has gone away.
+    pub fn forget_surface(&mut self, surface: &SurfaceId) {
+        self.selected_by_surface.remove(surface);
+    }
+
+    /// Return every control surface that has selected a channel of its own.
+    pub fn surfaces_with_selection(&self) -> impl Iterator<Item = SurfaceId> + '_ {
+        self.selected_by_surface.keys().copied()
+    }
+
+    /// Select a channel for the provided control surface, or for every surface
+    /// without a selection of its own if none is provided.
+    /// Return false if the channel was already selected.
+    fn select(&mut self, channel: ChannelId, surface: Option<&SurfaceId>) -> bool {
+        if self.selected_channel(surface) == Some(channel) {
+            return false;
+        }
+        match surface {
+            Some(surface) => {
+                self.selected_by_surface.insert(*surface, channel);
+            }
+            None => self.current_channel = Some(channel),
+        }
+        true
+    }
+
+    /// Select the channel assigned to the provided fixture group, if it has one.
+    pub fn select_fixture(&mut self, group: &FixtureGroupKey) {
+        if let Some(channel) = self.channel_for_fixture(group) {
+            self.current_channel = Some(channel);
+        }
+    }
+
+    /// Return the names of all OSC controls.
+    pub fn control_names(&self) -> impl Iterator<Item = &str> {
+        self.controls.control_names()
+    }
+
+    /// Emit all current channel state for the visible bank, using the channel
+    /// selected by the provided control surface.
     pub fn emit_state(
         &self,
         selected_fixture_only: bool,
+        surface: Option<&SurfaceId>,
         patch: &Patch,
         emitter: &dyn EmitControlMessage,
     ) {
@@ -156,20 +258,29 @@ impl Channels {
             entity: crate::osc::channels::GROUP,
             emitter,
         };
-        if let Some(channel) = self.current_channel {
-            let sc = StateChange::SelectChannel(channel);
+        if !selected_fixture_only {
+            let sc = StateChange::Bank {
+                bank: self.bank,
+                count: self.bank_count(),
+            };
             emitter.emit_midi_channel_message(&sc);
             Self::emit_osc_state_change(sc, &scoped_emitter);
         }
+        self.emit_selection(surface, emitter);
         Self::emit_osc_state_change(
-            StateChange::ChannelLabels(self.channel_labels(patch).collect()),
+            StateChange::ChannelLabels(
+                self.channel_labels(patch)
+                    .skip(self.bank * BANK_SIZE)
+                    .take(BANK_SIZE)
+                    .collect(),
+            ),
             &scoped_emitter,
         );
         if selected_fixture_only {
-            if let Some(channel_id) = self.current_channel {
+            if let Some(channel_id) = self.selected_channel(surface) {
                 match self.group_by_channel(patch, channel_id) {
                     Ok(f) => f.emit_state(ChannelStateEmitter {
-                        channel_id: Some(channel_id),
+                        channel_id: self.visible(channel_id),
                         emitter,
                     }),
                     Err(err) => error!("Failed to emit channel {channel_id} state: {err}."),
@@ -179,7 +290,7 @@ impl Channels {
             for channel_id in self.channel_ids() {
                 match self.group_by_channel(patch, channel_id) {
                     Ok(f) => f.emit_state(ChannelStateEmitter {
-                        channel_id: Some(channel_id),
+                        channel_id: self.visible(channel_id),
                         emitter,
                     }),
                     Err(err) => error!("Failed to emit channel {channel_id} state: {err}."),
@@ -188,24 +299,73 @@ impl Channels {
         }
     }
 
+    /// Emit the channel selected by the provided control surface, if it is in
+    /// the visible bank.
+    fn emit_selection(&self, surface: Option<&SurfaceId>, emitter: &dyn EmitControlMessage) {
+        let sc = StateChange::SelectChannel(
+            self.selected_channel(surface)
+                .and_then(|channel| self.visible(channel)),
+        );
+        emitter.emit_midi_channel_message(&sc);
+        Self::emit_osc_state_change(
+            sc,
+            &ScopedControlEmitter {
+                entity: crate::osc::channels::GROUP,
+                emitter,
+            },
+        );
+    }
+
+    /// Show a different bank of channels, and emit the state of the channels
+    /// now visible.
+    fn show_bank(
+        &mut self,
+        bank: usize,
+        surface: Option<&SurfaceId>,
+        patch: &Patch,
+        emitter: &dyn EmitControlMessage,
+    ) {
+        let previous = self.bank;
+        self.set_bank(bank);
+        if self.bank == previous {
+            return;
+        }
+        // Every surface shows the same bank, so the new bank goes to everyone.
+        self.emit_state(false, None, patch, emitter);
+        // If the sender has its own selection, correct its view of it.
+        if self.selected_channel(surface) != self.current_channel {
+            emitter.set_talkback(TalkbackMode::SenderOnly);
+            self.emit_selection(surface, emitter);
+        }
+    }
+
     /// Handle a OSC control message.
     pub fn control_osc(
         &mut self,
         msg: &OscControlMessage,
+        surface: Option<&SurfaceId>,
         patch: &mut Patch,
         animation_ui: &AnimationUIState,
         emitter: &dyn EmitControlMessage,
     ) -> anyhow::Result<()> {
-        let Some((ctl, _)) = self.controls.handle(msg)? else {
+        let Some((ctl, talkback)) = self.controls.handle(msg)? else {
             return Ok(());
         };
-        self.control(&ctl, patch, animation_ui, emitter)
+        emitter.set_talkback(talkback);
+        self.control(&ctl, surface, patch, animation_ui, emitter)
     }
 
     /// Handle a typed control message.
+    ///
+    /// Channel indices in control messages are relative to the start of the
+    /// visible bank.
+    ///
+    /// Channel selection made from a control surface only applies to that
+    /// surface, and the resulting state is only sent back to it.
     pub fn control(
         &mut self,
         ctl: &ControlMessage,
+        surface: Option<&SurfaceId>,
         patch: &mut Patch,
         animation_ui: &AnimationUIState,
         emitter: &dyn EmitControlMessage,
@@ -213,16 +373,19 @@ impl Channels {
         match ctl {
             ControlMessage::SelectChannel(g) => {
                 // Validate the channel.
-                let channel = self.validate_channel(*g)?;
-                if self.current_channel == Some(channel) {
+                let channel = self.validate_channel_in_bank(*g)?;
+                if !self.select(channel, surface) {
                     // Channel is not changed, ignore.
                     return Ok(());
                 }
-                self.current_channel = Some(channel);
-                self.emit_state(true, patch, emitter);
+                if surface.is_some() {
+                    emitter.set_talkback(TalkbackMode::SenderOnly);
+                }
+                self.emit_state(true, surface, patch, emitter);
                 // FIXME this is so goddamn inside out, I hate it.
                 animation_ui.emit_state(
                     channel,
+                    surface,
                     self.group_by_channel(patch, channel)?,
                     &ScopedControlEmitter {
                         entity: crate::osc::animation::GROUP,
@@ -232,9 +395,9 @@ impl Channels {
             }
             ControlMessage::Control { channel_id, msg } => {
                 let channel_id = if let Some(id) = channel_id {
-                    self.validate_channel(*id)?
+                    self.validate_channel_in_bank(*id)?
                 } else {
-                    self.current_channel.ok_or_else(||
+                    self.selected_channel(surface).ok_or_else(||
                             anyhow!("no channel ID provided or selected for channel control message {msg:?}")
                         )?
                 };
@@ -243,7 +406,7 @@ impl Channels {
                     .control_from_channel(
                         msg,
                         ChannelStateEmitter {
-                            channel_id: Some(channel_id),
+                            channel_id: self.visible(channel_id),
                             emitter,
                         },
                     )?;
@@ -251,12 +414,17 @@ impl Channels {
                     debug!("Fixture in channel {channel_id} did not handle channel control message {msg:?}.");
                 }
             }
+            ControlMessage::BankUp => self.show_bank(self.bank + 1, surface, patch, emitter),
+            ControlMessage::BankDown => {
+                self.show_bank(self.bank.saturating_sub(1), surface, patch, emitter)
+            }
         }
         Ok(())
     }
 }
 
 /// Provide methods to emit channel control state changes for a specific channel.
+/// The channel is relative to the start of the visible bank.
 /// If no channel is set, no state change events will be emitted.
 pub struct ChannelStateEmitter<'a> {
     channel_id: Option<ChannelId>,
@@ -293,6 +461,14 @@ impl<'a> EmitOscMessage for ChannelStateEmitter<'a> {
     fn emit_osc(&self, msg: rosc::OscMessage) {
         self.emitter.emit_osc(msg);
     }
+
+    fn emit_osc_with_kind(&self, msg: rosc::OscMessage, kind: ControlKind) {
+        self.emitter.emit_osc_with_kind(msg, kind);
+    }
+
+    fn set_talkback(&self, talkback: TalkbackMode) {
+        self.emitter.set_talkback(talkback);
+    }
 }
 
 impl<'a> EmitWledControlMessage for ChannelStateEmitter<'a> {
@@ -308,12 +484,21 @@ pub enum ControlMessage {
         channel_id: Option<usize>,
         msg: ChannelControlMessage,
     },
+    BankUp,
+    BankDown,
 }
 
-#[derive(Clone, Debug)]
+/// Channel state changes, as shown on control surfaces.
+/// Channel IDs are relative to the start of the visible bank.
+#[derive(Clone, Debug, Serialize)]
 pub enum StateChange {
-    SelectChannel(ChannelId),
+    /// The selected channel, if it is in the visible bank.
+    SelectChannel(Option<ChannelId>),
     ChannelLabels(Vec<String>),
+    Bank {
+        bank: usize,
+        count: usize,
+    },
     State {
         channel_id: ChannelId,
         msg: ChannelStateChange,
@@ -322,13 +507,13 @@ pub enum StateChange {
 
 pub type KnobIndex = u8;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize)]
 pub enum ChannelStateChange {
     Level(UnipolarFloat),
     Knob { index: KnobIndex, value: KnobValue },
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize)]
 pub enum KnobValue {
     Unipolar(UnipolarFloat),
     Bipolar(BipolarFloat),
@@ -359,3 +544,71 @@ impl KnobValue {
 }
 
 pub type ChannelControlMessage = ChannelStateChange;
+
+#[cfg(test)]
+mod test {
+    use std::net::SocketAddr;
+
+    use super::*;
+    use crate::{
+        fixture::{prelude::FixtureType, GroupName},
+        osc::OscClientId,
+    };
+
+    #[test]
+    fn test_selection_per_surface() {
+        let mut channels = Channels::new();
+        let ids: Vec<_> = ["Comet", "Venus", "Rush"]
+            .into_iter()
+            .map(|fixture| {
+                channels.add(FixtureGroupKey {
+                    fixture: FixtureType(fixture),
+                    group: None,
+                })
+            })
+            .collect();
+        let left = SurfaceId::Osc(OscClientId::new(SocketAddr::from(([10, 0, 0, 1], 9000))));
+        let right = SurfaceId::Osc(OscClientId::new(SocketAddr::from(([10, 0, 0, 2], 9000))));
+
+        // Surfaces without a selection follow the default.
+        assert_eq!(Some(ids[0]), channels.selected_channel(Some(&left)));
+
+        assert!(channels.select(ids[1], Some(&left)));
+        assert!(!channels.select(ids[1], Some(&left)));
+        assert!(channels.select(ids[2], Some(&right)));
+        assert_eq!(Some(ids[1]), channels.selected_channel(Some(&left)));
+        assert_eq!(Some(ids[2]), channels.selected_channel(Some(&right)));
+        assert_eq!(Some(ids[0]), channels.current_channel());
+
+        assert!(channels.select(ids[2], None));
+        assert_eq!(Some(ids[1]), channels.selected_channel(Some(&left)));
+        assert_eq!(Some(ids[2]), channels.selected_channel(None));
+    }
+
+    #[test]
+    fn test_banks() {
+        let mut channels = Channels::new();
+        assert_eq!(1, channels.bank_count());
+        let ids: Vec<_> = (0..10)
+            .map(|i| {
+                channels.add(FixtureGroupKey {
+                    fixture: FixtureType("Comet"),
+                    group: Some(GroupName::new(i.to_string())),
+                })
+            })
+            .collect();
+        assert_eq!(2, channels.bank_count());
+        assert_eq!(Some(ids[3]), channels.visible(ids[3]));
+        assert_eq!(ids[1], channels.validate_channel_in_bank(1).unwrap());
+        // Channels past the end of the bank aren't visible.
+        assert_eq!(None, channels.visible(ids[8]));
+        assert!(channels.validate_channel_in_bank(8).is_err());
+
+        channels.set_bank(5);
+        assert_eq!(1, channels.bank());
+        assert_eq!(None, channels.visible(ids[3]));
+        assert_eq!(Some(ids[1]), channels.visible(ids[9]));
+        assert_eq!(ids[9], channels.validate_channel_in_bank(1).unwrap());
+        assert!(channels.validate_channel_in_bank(2).is_err());
+    }
+}
@@ -28,6 +28,13 @@ pub fn prompt_start_clock_service(ctx: Context) -> Result<Option<ClockService>>
     if !prompt_bool("Run clock service?")? {
         return Ok(None);
     }
+    start_clock_service(ctx, None).map(Some)
+}
+
+/// Browse briefly for clock services and connect to one.
+/// If a provider name is provided, connect to it; otherwise present options
+/// and prompt the user to select one.
+pub fn start_clock_service(ctx: Context, provider: Option<&str>) -> Result<ClockService> {
     println!("Browsing for clock providers...");
     let service = clock_subscriber(ctx);
     thread::sleep(Duration::from_secs(2));
@@ -35,14 +42,21 @@ pub fn prompt_start_clock_service(ctx: Context) -> Result<Option<ClockService>>
     if providers.is_empty() {
         bail!("No clock providers found.");
     }
-    println!("Available clock providers:");
-    for provider in &providers {
-        println!("{provider}");
-    }
-    let provider = if providers.len() == 1 {
-        providers[0].clone()
+    let provider = if let Some(provider) = provider {
+        if !providers.iter().any(|p| p == provider) {
+            bail!("Clock provider \"{provider}\" not found.");
+        }
+        provider.to_string()
     } else {
-        prompt_parse("Select a provider", |s| Ok(s.to_string()))?
+        println!("Available clock providers:");
+        for provider in &providers {
+            println!("{provider}");
+        }
+        if providers.len() == 1 {
+            providers[0].clone()
+        } else {
+            prompt_parse("Select a provider", |s| Ok(s.to_string()))?
+        }
     };
     let mut receiver = service.subscribe(&provider, None)?;
     let storage = Arc::new(Mutex::new(SharedClockData::default()));
@@ -62,5 +76,5 @@ pub fn prompt_start_clock_service(ctx: Context) -> Result<Option<ClockService>>
         *clock_state = msg;
     });
     println!("Connected to {provider}.");
-    Ok(Some(ClockService(storage)))
+    Ok(ClockService(storage))
 }
@@ -1,28 +1,166 @@
+use crate::artnet::ArtNetConfig;
 use crate::dmx::DmxAddr;
 use crate::fixture::GroupName;
-use crate::midi::Device;
+use crate::merge::DmxInputConfig;
+use crate::midi::{Device, MidiMap, PickupMode};
+use crate::midi_clock::MidiClockConfig;
 use crate::osc::OscClientId;
-use anyhow::Result;
+use crate::sacn::SacnConfig;
+use crate::virtual_dmx::VirtualDmxConfig;
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::File;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
 use tunnels::midi::DeviceSpec;
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
+    /// The path this config was loaded from.
+    #[serde(skip)]
+    pub path: PathBuf,
     #[serde(default = "default_receive_port")]
     pub receive_port: u16,
     #[serde(default)]
     pub wled_addr: Option<String>,
+    /// Serve the HTTP API on this port.
+    /// If not provided, the HTTP API is not served.
+    #[serde(default)]
+    pub http_port: Option<u16>,
+    /// Stream show state to, and accept control from, WebSocket clients on this port.
+    /// If not provided, WebSockets are not served.
+    #[serde(default)]
+    pub websocket_port: Option<u16>,
+    /// The address to serve the HTTP API and WebSockets on.
+    /// Neither is authenticated, so they are only served to this machine
+    /// unless a wider address, such as 0.0.0.0, is provided.
+    #[serde(default = "default_api_bind_addr")]
+    pub api_bind_addr: IpAddr,
+    /// Advertise the OSC namespace using OSCQuery on this port.
+    /// If not provided, OSCQuery is not served.
+    #[serde(default)]
+    pub oscquery_port: Option<u16>,
+    /// OSC clients to always send to.
+    /// Any other client is sent to once it sends us a message.
     #[serde(default)]
     pub controllers: Vec<OscClientId>,
+    /// Stop sending to OSC clients that haven't sent a message in this many
+    /// seconds. Clients listed in controllers are never dropped.
+    /// If not provided, clients are never dropped.
+    #[serde(default)]
+    pub osc_client_timeout: Option<f64>,
     #[serde(skip)]
     pub midi_devices: Vec<DeviceSpec<Device>>,
+    /// MIDI devices to connect to at startup.
+    /// If not provided, prompt for MIDI devices.
+    #[serde(default)]
+    pub midi: Option<Vec<MidiDeviceConfig>>,
+    /// How MIDI faders and knobs take over a value that doesn't match their
+    /// position: jump, cross or scale.
+    #[serde(default)]
+    pub midi_pickup: PickupMode,
+    /// Clock source for the show.
+    /// If not provided, prompt for clock configuration.
+    #[serde(default)]
+    pub clock: Option<ClockConfig>,
+    /// Names of the DMX ports to assign to each universe, in universe order.
+    /// Any universe without a port provided here will be prompted for.
+    #[serde(default)]
+    pub dmx_ports: Vec<String>,
+    /// Universes to send over Art-Net rather than a DMX port.
+    #[serde(default)]
+    pub artnet: Vec<ArtNetConfig>,
+    /// Universes to send over sACN rather than a DMX port.
+    #[serde(default)]
+    pub sacn: Option<SacnConfig>,
+    /// Receive DMX from the network and merge it into the show's output.
+    #[serde(default)]
+    pub dmx_input: Option<DmxInputConfig>,
+    /// File to periodically save show state to, and restore it from at startup.
+    /// If not provided, show state is not persisted.
+    /// Relative paths are resolved against the directory of the config file.
+    #[serde(default)]
+    pub state_file: Option<PathBuf>,
+    /// File to record the rendered DMX output to, for later playback.
+    /// Relative paths are resolved against the directory of the config file.
+    #[serde(default)]
+    pub record_file: Option<PathBuf>,
+    /// File to journal every incoming control message to, for later replay.
+    /// Relative paths are resolved against the directory of the config file.
+    #[serde(default)]
+    pub journal_file: Option<PathBuf>,
+    /// YAML file to load the cue list from.
+    /// Relative paths are resolved against the directory of the config file.
+    #[serde(default)]
+    pub cue_file: Option<PathBuf>,
+    /// Send any universe without a configured output to a virtual output
+    /// rather than prompting for a DMX port.
+    #[serde(default)]
+    pub virtual_dmx: Option<VirtualDmxConfig>,
+    /// Never prompt for configuration; anything not configured is left out.
+    /// Universes without a configured output use a virtual output, in memory
+    /// unless otherwise configured.
+    #[serde(default)]
+    pub headless: bool,
     #[serde(default)]
     pub debug: bool,
     pub fixtures: Vec<FixtureGroupConfig>,
 }
 
+/// Select the source of clocks for the show.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockConfig {
+    /// Subscribe to the named remote clock provider.
+    Service { provider: String },
+    /// Run clocks internally.
+    /// If no audio device is provided, prompt for one.
+    Internal {
+        #[serde(default)]
+        audio_device: Option<String>,
+        /// Slave clocks to MIDI beat clock and timecode from a MIDI input.
+        #[serde(default)]
+        midi_clock: Option<MidiClockConfig>,
+    },
+}
+
+/// A MIDI device to connect to at startup.
+#[derive(Clone, Debug, Deserialize)]
+pub struct MidiDeviceConfig {
+    /// The device model name, such as "Launch Control XL".
+    /// Generic devices can use any name.
+    pub model: String,
+    pub input_port: String,
+    pub output_port: String,
+    /// Offset of the first show channel this device controls.
+    #[serde(default)]
+    pub channel_offset: usize,
+    /// YAML file mapping the controls of a generic device.
+    /// If provided, the device is generic rather than a known model.
+    /// A file that doesn't exist yet is an empty mapping; MIDI learn creates
+    /// it when it saves.
+    /// Relative paths are resolved against the directory of the config file.
+    #[serde(default)]
+    pub mapping: Option<PathBuf>,
+}
+
+impl MidiDeviceConfig {
+    pub fn device_spec(&self) -> Result<DeviceSpec<Device>> {
+        let device = if self.mapping.is_some() {
+            Device::generic(&self.model, self.channel_offset)
+        } else {
+            Device::from_model(&self.model, self.channel_offset)
+                .with_context(|| format!("MIDI device on port \"{}\"", self.input_port))?
+        };
+        Ok(DeviceSpec {
+            device,
+            input_port_name: self.input_port.clone(),
+            output_port_name: self.output_port.clone(),
+        })
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AnimationGroup {
     pub fixture_type: String,
@@ -32,12 +170,43 @@ pub struct AnimationGroup {
 impl Config {
     pub fn load(path: &str) -> Result<Self> {
         let config_file = File::open(path)?;
-        let cfg: Config = serde_yaml::from_reader(config_file)?;
+        let mut cfg: Config = serde_yaml::from_reader(config_file)?;
+        cfg.path = PathBuf::from(path);
+        if let Some(config_dir) = Path::new(path).parent() {
+            let resolve = |file: &mut Option<PathBuf>| {
+                if let Some(file) = file {
+                    *file = config_dir.join(&*file);
+                }
+            };
+            resolve(&mut cfg.state_file);
+            resolve(&mut cfg.record_file);
+            resolve(&mut cfg.journal_file);
+            resolve(&mut cfg.cue_file);
+            if let Some(VirtualDmxConfig::File(file)) = &mut cfg.virtual_dmx {
+                *file = config_dir.join(&*file);
+            }
+            for device in cfg.midi.iter_mut().flatten() {
+                resolve(&mut device.mapping);
+            }
+        }
         Ok(cfg)
     }
+
+    /// Load the control mappings of all configured generic MIDI devices.
+    pub fn midi_maps(&self) -> Result<HashMap<Device, MidiMap>> {
+        self.midi
+            .iter()
+            .flatten()
+            .filter_map(|device_cfg| {
+                let path = device_cfg.mapping.as_ref()?;
+                let device = Device::generic(&device_cfg.model, device_cfg.channel_offset);
+                Some(MidiMap::load(path).map(|map| (device, map)))
+            })
+            .collect()
+    }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum DmxAddrConfig {
     /// A contiguous block of fixtures.
@@ -46,7 +215,7 @@ pub enum DmxAddrConfig {
     Single(DmxAddr),
 }
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FixtureGroupConfig {
     pub name: String,
     /// The DMX address configuration to patch this fixture at.
@@ -127,3 +296,7 @@ pub type Options = HashMap<String, String>;
 const fn default_receive_port() -> u16 {
     8000
 }
+
+const fn default_api_bind_addr() -> IpAddr {
+    IpAddr::V4(Ipv4Addr::LOCALHOST)
+}
@@ -1,6 +1,8 @@
 //! Top-level traits and types for control events.
 
 use std::{
+    cell::{Cell, RefCell},
+    net::SocketAddr,
     sync::mpsc::{channel, Receiver, RecvTimeoutError},
     time::Duration,
 };
@@ -11,13 +13,16 @@ use tunnels::midi::CreateControlEvent;
 
 use crate::{
     config::Config,
+    http::HttpRequest,
     midi::{
         Device, EmitMidiChannelMessage, EmitMidiMasterMessage, MidiControlMessage, MidiController,
     },
     osc::{
-        EmitOscMessage, EmitScopedOscMessage, OscClientId, OscControlMessage, OscControlResponse,
-        OscController, ScopedControlEmitter, TalkbackMode,
+        ControlKind, EmitOscMessage, EmitScopedOscMessage, OscClientId, OscControlMessage,
+        OscControlResponse, OscController, ScopedControlEmitter, TalkbackMode,
     },
+    show::ShowControlMessage,
+    websocket::{StateMessage, WebSocketController, WebSocketResponse},
     wled::{EmitWledControlMessage, WledController, WledResponse},
 };
 
@@ -39,12 +44,22 @@ impl<T> EmitControlMessage for T where
 {
 }
 
+/// A control surface, which keeps its own channel and animator selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SurfaceId {
+    Osc(OscClientId),
+    Midi(Device),
+}
+
 /// Handle receiving and responding to show control messages.
 pub struct Controller {
     osc: OscController,
     midi: MidiController,
     wled: Option<WledController>,
+    websocket: Option<WebSocketController>,
     recv: Receiver<ControlMessage>,
+    /// OSC talkback captured while handling an HTTP control request.
+    talkback: RefCell<Option<Vec<OscMessage>>>,
 }
 
 impl Controller {
@@ -55,11 +70,35 @@ impl Controller {
             .as_ref()
             .map(|addr| WledController::run(addr, send.clone()))
             .transpose()?;
+        if let Some(port) = cfg.http_port {
+            crate::http::start_server(SocketAddr::new(cfg.api_bind_addr, port), send.clone())?;
+        }
+        if let Some(port) = cfg.oscquery_port {
+            crate::oscquery::start_server(port, cfg.receive_port, send.clone())?;
+        }
+        let websocket = cfg
+            .websocket_port
+            .map(|port| {
+                WebSocketController::run(SocketAddr::new(cfg.api_bind_addr, port), send.clone())
+            })
+            .transpose()?;
         Ok(Self {
-            osc: OscController::new(cfg.receive_port, cfg.controllers.clone(), send.clone())?,
-            midi: MidiController::new(cfg.midi_devices.clone(), send)?,
+            osc: OscController::new(
+                cfg.receive_port,
+                cfg.controllers.clone(),
+                cfg.osc_client_timeout.map(Duration::from_secs_f64),
+                send.clone(),
+            )?,
+            midi: MidiController::new(
+                cfg.midi_devices.clone(),
+                cfg.midi_maps()?,
+                cfg.midi_pickup,
+                send,
+            )?,
             wled,
+            websocket,
             recv,
+            talkback: Default::default(),
         })
     }
 
@@ -73,44 +112,96 @@ impl Controller {
         }
     }
 
+    /// Interpret an incoming MIDI message as a show control message.
+    pub fn interpret_midi(&self, msg: &MidiControlMessage) -> Option<ShowControlMessage> {
+        self.midi.interpret(msg)
+    }
+
+    /// Return every MIDI device with an output.
+    pub fn midi_devices(&self) -> Vec<Device> {
+        self.midi.devices()
+    }
+
+    /// Track MIDI pickup state for the provided devices, whether or not they
+    /// are connected.
+    pub fn track_midi_pickup(&self, devices: &[Device]) {
+        self.midi.track_pickup(devices);
+    }
+
+    /// Start or stop learning mappings for generic MIDI devices.
+    pub fn set_midi_learn(&self, learn: bool) {
+        self.midi.set_learning(learn);
+    }
+
+    /// While learning, select the control that sent a MIDI message to be
+    /// mapped. Return true if the message was consumed.
+    pub fn learn_midi_control(&self, msg: &MidiControlMessage) -> bool {
+        self.midi.learn_control(msg)
+    }
+
+    /// While learning, map the last MIDI control moved to an OSC address.
+    pub fn learn_midi_target(&self, addr: &str) -> Result<()> {
+        self.midi.learn_target(addr)
+    }
+
+    /// Capture all OSC talkback, in addition to sending it, until the
+    /// captured talkback is taken.
+    pub fn capture_talkback(&mut self) {
+        *self.talkback.get_mut() = Some(Vec::new());
+    }
+
+    /// Stop capturing OSC talkback and return everything captured.
+    pub fn take_talkback(&mut self) -> Vec<OscMessage> {
+        self.talkback.get_mut().take().unwrap_or_default()
+    }
+
     /// Return a decorated version of self that will include the provided
-    /// metadata when sending OSC response messages.
+    /// metadata when sending response messages.
     pub fn sender_with_metadata<'a>(
         &'a mut self,
-        sender_id: Option<&'a OscClientId>,
+        sender: Option<&'a SurfaceId>,
     ) -> ControlMessageWithMetadataSender<'_> {
         ControlMessageWithMetadataSender {
-            sender_id,
+            sender,
+            talkback: Default::default(),
+            echo_addr: None,
             controller: self,
         }
     }
 }
 
 impl tunnels::audio::EmitStateChange for Controller {
+    fn emit_audio_state_change(&mut self, sc: tunnels::audio::StateChange) {
+        self.sender_with_metadata(None).emit_audio_state_change(sc);
+    }
+}
+
+impl tunnels::clock_bank::EmitStateChange for Controller {
+    fn emit_clock_bank_state_change(&mut self, sc: tunnels::clock_bank::StateChange) {
+        self.sender_with_metadata(None)
+            .emit_clock_bank_state_change(sc);
+    }
+}
+
+impl tunnels::audio::EmitStateChange for StateCapture {
     fn emit_audio_state_change(&mut self, sc: tunnels::audio::StateChange) {
         crate::osc::audio::emit_osc_state_change(
             &sc,
             &ScopedControlEmitter {
                 entity: crate::osc::audio::GROUP,
-                emitter: &ControlMessageWithMetadataSender {
-                    sender_id: None,
-                    controller: self,
-                },
+                emitter: &*self,
             },
         );
     }
 }
 
-impl tunnels::clock_bank::EmitStateChange for Controller {
+impl tunnels::clock_bank::EmitStateChange for StateCapture {
     fn emit_clock_bank_state_change(&mut self, sc: tunnels::clock_bank::StateChange) {
         crate::osc::clock::emit_osc_state_change(
             &sc,
             &ScopedControlEmitter {
                 entity: crate::osc::clock::GROUP,
-                emitter: &ControlMessageWithMetadataSender {
-                    sender_id: None,
-                    controller: self,
-                },
+                emitter: &*self,
             },
         );
     }
@@ -118,29 +209,118 @@ impl tunnels::clock_bank::EmitStateChange for Controller {
 
 /// Decorate the Controller to add message metedata to control responses.
 pub struct ControlMessageWithMetadataSender<'a> {
-    pub sender_id: Option<&'a OscClientId>,
+    /// The control surface that sent the message being handled, if any.
+    pub sender: Option<&'a SurfaceId>,
+    /// How responses are sent back to the sender, as declared by the control
+    /// handling the message.
+    pub talkback: Cell<TalkbackMode>,
+    /// The OSC address of the control being handled, if any.
+    /// If set, turning talkback off only holds back the echo of this control;
+    /// any other state it changes is still sent to the sender.
+    pub echo_addr: Option<String>,
     pub controller: &'a mut Controller,
 }
 
+impl<'a> ControlMessageWithMetadataSender<'a> {
+    /// The OSC client that sent the message being handled, if any.
+    fn sender_id(&self) -> Option<OscClientId> {
+        match self.sender {
+            Some(SurfaceId::Osc(client_id)) => Some(*client_id),
+            _ => None,
+        }
+    }
+
+    /// Return the talkback mode for a response sent to the provided OSC
+    /// address, if it has one.
+    fn talkback_for(&self, addr: Option<&str>) -> TalkbackMode {
+        match self.talkback.get() {
+            TalkbackMode::Off if self.echo_addr.is_some() && addr != self.echo_addr.as_deref() => {
+                TalkbackMode::All
+            }
+            talkback => talkback,
+        }
+    }
+
+    /// Send a state change to MIDI devices, respecting talkback.
+    /// Responses meant only for the sender go only to the sending device, if
+    /// the sender is a MIDI device.
+    fn emit_midi(&self, emit: impl Fn(&MidiController, Option<Device>)) {
+        match (self.talkback.get(), self.sender) {
+            (TalkbackMode::SenderOnly, Some(SurfaceId::Midi(device))) => {
+                emit(&self.controller.midi, Some(*device))
+            }
+            (TalkbackMode::SenderOnly, Some(_)) => (),
+            _ => emit(&self.controller.midi, None),
+        }
+    }
+
+    /// Send a state change to WebSocket clients, if any are being served.
+    fn emit_websocket(&self, msg: StateMessage, talkback: TalkbackMode) {
+        if let Some(websocket) = self.controller.websocket.as_ref() {
+            websocket.send(WebSocketResponse {
+                sender_id: self.sender_id(),
+                talkback,
+                msg,
+            });
+        }
+    }
+}
+
 impl<'a> EmitOscMessage for ControlMessageWithMetadataSender<'a> {
     fn emit_osc(&self, msg: OscMessage) {
+        if let Some(talkback) = self.controller.talkback.borrow_mut().as_mut() {
+            talkback.push(msg.clone());
+        }
+        let talkback = self.talkback_for(Some(&msg.addr));
+        self.emit_websocket((&msg).into(), talkback);
+        self.emit_midi(|midi, only| midi.emit_osc(&msg, only));
         self.controller.osc.send(OscControlResponse {
-            sender_id: self.sender_id.cloned(),
-            talkback: TalkbackMode::All, // FIXME: hardcoded talkback
+            sender_id: self.sender_id(),
+            talkback,
             msg,
         });
     }
+
+    fn set_talkback(&self, talkback: TalkbackMode) {
+        self.talkback.set(talkback);
+    }
+}
+
+impl<'a> tunnels::audio::EmitStateChange for ControlMessageWithMetadataSender<'a> {
+    fn emit_audio_state_change(&mut self, sc: tunnels::audio::StateChange) {
+        crate::osc::audio::emit_osc_state_change(
+            &sc,
+            &ScopedControlEmitter {
+                entity: crate::osc::audio::GROUP,
+                emitter: &*self,
+            },
+        );
+    }
+}
+
+impl<'a> tunnels::clock_bank::EmitStateChange for ControlMessageWithMetadataSender<'a> {
+    fn emit_clock_bank_state_change(&mut self, sc: tunnels::clock_bank::StateChange) {
+        crate::osc::clock::emit_osc_state_change(
+            &sc,
+            &ScopedControlEmitter {
+                entity: crate::osc::clock::GROUP,
+                emitter: &*self,
+            },
+        );
+    }
 }
 
 impl<'a> EmitMidiChannelMessage for ControlMessageWithMetadataSender<'a> {
     fn emit_midi_channel_message(&self, msg: &crate::channel::StateChange) {
-        self.controller.midi.emit_channel_control(msg);
+        self.emit_websocket(StateMessage::Channel(msg.clone()), self.talkback_for(None));
+        self.emit_midi(|midi, only| midi.emit_channel_control(msg, only));
     }
 }
 
 impl<'a> EmitMidiMasterMessage for ControlMessageWithMetadataSender<'a> {
     fn emit_midi_master_message(&self, msg: &crate::master::StateChange) {
-        self.controller.midi.emit_master_control(msg);
+        self.emit_websocket(StateMessage::Master(msg.clone()), self.talkback_for(None));
+        self.emit_midi(|midi, only| midi.emit_master_control(msg, only));
     }
 }
 
@@ -152,10 +332,64 @@ impl<'a> EmitWledControlMessage for ControlMessageWithMetadataSender<'a> {
     }
 }
 
+/// Record emitted OSC messages rather than sending them anywhere.
+/// All other control output is discarded.
+/// Used to inspect the current state of a piece of the show.
+#[derive(Default)]
+pub struct StateCapture(RefCell<Vec<CapturedMessage>>);
+
+/// An OSC message recorded by a StateCapture.
+pub struct CapturedMessage {
+    pub msg: OscMessage,
+    /// The kind of value carried by the message, if the control declared one.
+    pub kind: Option<ControlKind>,
+}
+
+impl StateCapture {
+    /// Return all of the messages captured so far.
+    pub fn into_messages(self) -> Vec<CapturedMessage> {
+        self.0.into_inner()
+    }
+}
+
+impl EmitOscMessage for StateCapture {
+    fn emit_osc(&self, msg: OscMessage) {
+        self.0
+            .borrow_mut()
+            .push(CapturedMessage { msg, kind: None });
+    }
+
+    fn emit_osc_with_kind(&self, msg: OscMessage, kind: ControlKind) {
+        self.0.borrow_mut().push(CapturedMessage {
+            msg,
+            kind: Some(kind),
+        });
+    }
+}
+
+impl EmitMidiChannelMessage for StateCapture {
+    fn emit_midi_channel_message(&self, _msg: &crate::channel::StateChange) {}
+}
+
+impl EmitMidiMasterMessage for StateCapture {
+    fn emit_midi_master_message(&self, _msg: &crate::master::StateChange) {}
+}
+
+impl EmitWledControlMessage for StateCapture {
+    fn emit_wled(&self, _msg: crate::wled::WledControlMessage) {}
+}
+
 pub enum ControlMessage {
     Osc(OscControlMessage),
+    /// A new OSC client sent us its first message, or a WebSocket client
+    /// connected.
+    OscClientRegistered(OscClientId),
+    /// An OSC client was dropped after going silent, or a WebSocket client
+    /// disconnected.
+    OscClientExpired(OscClientId),
     Midi(MidiControlMessage),
     Wled(WledResponse),
+    Http(HttpRequest),
 }
 
 impl CreateControlEvent<Device> for ControlMessage {
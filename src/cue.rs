@@ -0,0 +1,558 @@
+//! An ordered stack of cues, each recalling presets with timed fades.
+//!
+//! Cues are loaded from a YAML file. Playback is driven by GO/BACK/RELEASE
+//! controls, and cues can optionally advance on their own after a delay.
+use std::{fs::File, path::Path, time::Duration};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use log::{error, info};
+use serde::Deserialize;
+
+use crate::{
+    channel::Channels,
+    control::EmitControlMessage,
+    fixture::{FixtureGroupKey, GroupName, Patch},
+    osc::{GroupControlMap, OscControlMessage, ScopedControlEmitter},
+    preset::Presets,
+};
+
+pub struct CueList {
+    cues: Vec<Cue>,
+    /// The index of the most recently triggered cue.
+    /// None if playback has not started or has been released.
+    current: Option<usize>,
+    /// Time since the current cue was triggered.
+    elapsed: Duration,
+    controls: GroupControlMap<ControlMessage>,
+}
+
+/// A single cue.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Cue {
+    pub name: String,
+    /// Presets to recall when this cue is triggered.
+    #[serde(default)]
+    pub recall: Vec<Recall>,
+    /// If set, trigger the next cue this many seconds after this cue's fades
+    /// have completed.
+    #[serde(default)]
+    pub follow: Option<f64>,
+    /// If set, trigger the next cue this many seconds after this cue was
+    /// triggered, regardless of fades.
+    #[serde(default)]
+    pub auto_advance: Option<f64>,
+}
+
+/// Recall a single preset as part of a cue.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Recall {
+    pub preset: PresetRef,
+    /// Fade-in time, in seconds.
+    #[serde(default)]
+    pub fade: f64,
+    /// Only recall the preset into this fixture type.
+    /// If not provided, recall into every group the preset holds state for.
+    #[serde(default)]
+    pub fixture: Option<String>,
+    /// The group name of the fixture to recall into, if it has one.
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+/// Refer to a preset by slot number, counting from 1, or by name.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum PresetRef {
+    Slot(usize),
+    Name(String),
+}
+
+impl CueList {
+    pub fn new(cues: Vec<Cue>) -> Self {
+        let mut controls = GroupControlMap::default();
+        Self::map_controls(&mut controls);
+        Self {
+            cues,
+            current: None,
+            elapsed: Duration::ZERO,
+            controls,
+        }
+    }
+
+    /// Load a cue list from a YAML file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+        let cues: Vec<Cue> = serde_yaml::from_reader(file)
+            .with_context(|| format!("parsing cue list from {}", path.display()))?;
+        for (i, cue) in cues.iter().enumerate() {
+            cue.validate()
+                .with_context(|| format!("cue {} (\"{}\")", i + 1, cue.name))?;
+        }
+        info!("Loaded {} cues from {}.", cues.len(), path.display());
+        Ok(Self::new(cues))
+    }
+
+    /// Handle a OSC control message.
+    pub fn control_osc(
+        &mut self,
+        msg: &OscControlMessage,
+        presets: &mut Presets,
+        patch: &mut Patch,
+        channels: &Channels,
+        emitter: &dyn EmitControlMessage,
+    ) -> Result<()> {
+        let Some((ctl, talkback)) = self.controls.handle(msg)? else {
+            return Ok(());
+        };
+        emitter.set_talkback(talkback);
+        self.control(&ctl, presets, patch, channels, emitter)
+    }
+
+    /// Handle a typed control message.
+    pub fn control(
+        &mut self,
+        msg: &ControlMessage,
+        presets: &mut Presets,
+        patch: &mut Patch,
+        channels: &Channels,
+        emitter: &dyn EmitControlMessage,
+    ) -> Result<()> {
+        match msg {
+            ControlMessage::Go => {
+                let next = self.current.map(|i| i + 1).unwrap_or_default();
+                ensure!(next < self.cues.len(), "no more cues to GO to");
+                self.trigger(next, presets, patch, channels, emitter);
+            }
+            ControlMessage::Back => {
+                let Some(previous) = self.current.and_then(|i| i.checked_sub(1)) else {
+                    bail!("no previous cue to go BACK to");
+                };
+                self.trigger(previous, presets, patch, channels, emitter);
+            }
+            ControlMessage::Release => {
+                // The current look is left in place, frozen mid-fade if
+                // need be; only playback stops. Fades started from the
+                // presets themselves carry on.
+                info!("Releasing cue list.");
+                presets.cancel_cue_fades();
+                self.current = None;
+                self.elapsed = Duration::ZERO;
+            }
+        }
+        self.emit_state(emitter);
+        Ok(())
+    }
+
+    /// Advance the cue clock, triggering the next cue if it is due.
+    /// Return true if a cue was triggered.
+    pub fn update(
+        &mut self,
+        delta_t: Duration,
+        presets: &mut Presets,
+        patch: &mut Patch,
+        channels: &Channels,
+        emitter: &dyn EmitControlMessage,
+    ) -> bool {
+        let Some(current) = self.current else {
+            return false;
+        };
+        self.elapsed += delta_t;
+        let next = current + 1;
+        if next >= self.cues.len() {
+            return false;
+        }
+        let Some(wait) = self.cues[current].advance_after() else {
+            return false;
+        };
+        if self.elapsed < wait {
+            return false;
+        }
+        self.trigger(next, presets, patch, channels, emitter);
+        self.emit_state(emitter);
+        true
+    }
+
+    /// Trigger the cue at the provided index.
+    /// Recalls that fail are logged and skipped, so one bad recall doesn't
+    /// prevent the rest of the cue from running.
+    fn trigger(
+        &mut self,
+        index: usize,
+        presets: &mut Presets,
+        patch: &mut Patch,
+        channels: &Channels,
+        emitter: &dyn EmitControlMessage,
+    ) {
+        let cue = &self.cues[index];
+        info!("Cue {}: {}.", index + 1, cue.name);
+        for recall in &cue.recall {
+            if let Err(err) = recall.apply(presets, patch, channels, emitter) {
+                error!("Cue {} (\"{}\"): {err:#}.", index + 1, cue.name);
+            }
+        }
+        self.current = Some(index);
+        self.elapsed = Duration::ZERO;
+    }
+
+    /// Return the names of all OSC controls.
+    pub fn control_names(&self) -> impl Iterator<Item = &str> {
+        self.controls.control_names()
+    }
+
+    /// Emit all current cue list state.
+    pub fn emit_state(&self, emitter: &dyn EmitControlMessage) {
+        let emitter = &ScopedControlEmitter {
+            entity: crate::osc::cues::GROUP,
+            emitter,
+        };
+        let label = |index: usize| {
+            self.cues
+                .get(index)
+                .map(|cue| format!("{} {}", index + 1, cue.name))
+                .unwrap_or_default()
+        };
+        let next = self.current.map(|i| i + 1).unwrap_or_default();
+        Self::emit_osc_state_change(
+            StateChange::Current(self.current.map(&label).unwrap_or_default()),
+            emitter,
+        );
+        Self::emit_osc_state_change(StateChange::Next(label(next)), emitter);
+    }
+}
+
+impl Cue {
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            self.follow.is_none() || self.auto_advance.is_none(),
+            "only one of follow or auto_advance may be set"
+        );
+        for t in [self.follow, self.auto_advance].into_iter().flatten() {
+            ensure!(t >= 0.0, "advance time {t} is negative");
+        }
+        for recall in &self.recall {
+            recall.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Return the longest fade time of any recall in this cue.
+    fn fade_time(&self) -> Duration {
+        self.recall
+            .iter()
+            .map(Recall::fade_time)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Return how long after triggering this cue the next cue should be
+    /// triggered, if this cue advances on its own.
+    fn advance_after(&self) -> Option<Duration> {
+        if let Some(follow) = self.follow {
+            return Some(self.fade_time() + Duration::from_secs_f64(follow));
+        }
+        self.auto_advance.map(Duration::from_secs_f64)
+    }
+}
+
+impl Recall {
+    fn validate(&self) -> Result<()> {
+        ensure!(self.fade >= 0.0, "fade time {} is negative", self.fade);
+        if let PresetRef::Slot(slot) = self.preset {
+            ensure!(slot > 0, "preset slots are numbered from 1");
+        }
+        ensure!(
+            self.group.is_none() || self.fixture.is_some(),
+            "a group was provided without a fixture type"
+        );
+        Ok(())
+    }
+
+    fn fade_time(&self) -> Duration {
+        Duration::from_secs_f64(self.fade)
+    }
+
+    /// Recall this preset.
+    fn apply(
+        &self,
+        presets: &mut Presets,
+        patch: &mut Patch,
+        channels: &Channels,
+        emitter: &dyn EmitControlMessage,
+    ) -> Result<()> {
+        let slot = match &self.preset {
+            PresetRef::Slot(slot) => slot - 1,
+            PresetRef::Name(name) => presets
+                .slot_by_name(name)
+                .ok_or_else(|| anyhow!("no preset named \"{name}\""))?,
+        };
+        let Some(fixture) = &self.fixture else {
+            return presets.recall_all(slot, self.fade_time(), true, patch, channels, emitter);
+        };
+        let key = FixtureGroupKey {
+            fixture: patch
+                .lookup_fixture_type(fixture)
+                .ok_or_else(|| anyhow!("fixture type \"{fixture}\" is not patched"))?,
+            group: self.group.as_deref().map(GroupName::new),
+        };
+        if !presets.recall(slot, &key, self.fade_time(), true, patch, channels, emitter)? {
+            bail!("preset {} has nothing stored for {key}", slot + 1);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ControlMessage {
+    Go,
+    Back,
+    Release,
+}
+
+#[derive(Debug, Clone)]
+pub enum StateChange {
+    /// The label of the most recently triggered cue.
+    Current(String),
+    /// The label of the cue that GO will trigger.
+    Next(String),
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        config::FixtureGroupConfig,
+        control::StateCapture,
+        fixture::prelude::FixtureType,
+        osc::fixture_control_addr,
+        persist::{ControlValue, GroupState},
+        show::build_patch,
+    };
+
+    fn key() -> FixtureGroupKey {
+        FixtureGroupKey {
+            fixture: FixtureType("Dimmer"),
+            group: None,
+        }
+    }
+
+    /// Patch a single dimmer, with its level stored as 0 in preset 1 and as
+    /// 1 in preset 2.
+    fn setup() -> Result<(Patch, Channels, Presets)> {
+        let (mut patch, channels) = build_patch(vec![FixtureGroupConfig {
+            name: "Dimmer".to_string(),
+            addr: Some(serde_yaml::from_str("1")?),
+            universe: 0,
+            mirror: false,
+            group: None,
+            options: Default::default(),
+            channel: true,
+        }])?;
+        let mut presets = Presets::new();
+        for (slot, value) in [(0, 0.0), (1, 1.0)] {
+            set_level(&mut patch, value)?;
+            presets.store(slot, None, patch.iter())?;
+        }
+        Ok((patch, channels, presets))
+    }
+
+    fn set_level(patch: &mut Patch, value: f32) -> Result<()> {
+        let group = patch.get_mut(&key())?;
+        let msg = ControlValue {
+            control: "Level".to_string(),
+            value,
+            kind: None,
+        }
+        .to_control_message(fixture_control_addr(group.key(), "Level"))?;
+        group.control(
+            &msg,
+            crate::channel::ChannelStateEmitter::new(None, &StateCapture::default()),
+        )
+    }
+
+    fn level(patch: &Patch) -> f32 {
+        GroupState::capture(patch.get(&key()).unwrap())
+            .controls
+            .into_iter()
+            .find(|cv| cv.control == "Level")
+            .unwrap()
+            .value
+    }
+
+    fn cue(slot: usize, fade: f64) -> Cue {
+        Cue {
+            name: format!("Preset {slot}"),
+            recall: vec![Recall {
+                preset: PresetRef::Slot(slot),
+                fade,
+                fixture: None,
+                group: None,
+            }],
+            follow: None,
+            auto_advance: None,
+        }
+    }
+
+    /// Run the cue list and preset fades for the provided time, in steps of
+    /// 100 ms. Return the number of cues triggered by the cue list itself.
+    fn run(
+        cues: &mut CueList,
+        secs: f64,
+        presets: &mut Presets,
+        patch: &mut Patch,
+        channels: &Channels,
+    ) -> usize {
+        let step = Duration::from_millis(100);
+        let emitter = &StateCapture::default();
+        let mut triggered = 0;
+        for _ in 0..(secs * 10.0).round() as usize {
+            if cues.update(step, presets, patch, channels, emitter) {
+                triggered += 1;
+            }
+            presets.update(step, patch, channels, emitter);
+        }
+        triggered
+    }
+
+    #[test]
+    fn test_go_back_release() -> Result<()> {
+        let (mut patch, channels, mut presets) = setup()?;
+        let mut cues = CueList::new(vec![cue(1, 0.0), cue(2, 0.0)]);
+        let emitter = &StateCapture::default();
+        let mut control = |cues: &mut CueList, msg: ControlMessage, patch: &mut Patch| {
+            cues.control(&msg, &mut presets, patch, &channels, emitter)
+        };
+
+        assert!(control(&mut cues, ControlMessage::Back, &mut patch).is_err());
+        control(&mut cues, ControlMessage::Go, &mut patch)?;
+        assert_eq!(Some(0), cues.current);
+        assert_eq!(0.0, level(&patch));
+        control(&mut cues, ControlMessage::Go, &mut patch)?;
+        assert_eq!(Some(1), cues.current);
+        assert_eq!(1.0, level(&patch));
+        assert!(control(&mut cues, ControlMessage::Go, &mut patch).is_err());
+
+        control(&mut cues, ControlMessage::Back, &mut patch)?;
+        assert_eq!(Some(0), cues.current);
+        assert_eq!(0.0, level(&patch));
+
+        // Release leaves the look in place and starts over from the top.
+        set_level(&mut patch, 0.5)?;
+        control(&mut cues, ControlMessage::Release, &mut patch)?;
+        assert_eq!(None, cues.current);
+        assert_eq!(0.5, level(&patch));
+        control(&mut cues, ControlMessage::Go, &mut patch)?;
+        assert_eq!(Some(0), cues.current);
+        Ok(())
+    }
+
+    #[test]
+    fn test_follow_and_auto_advance() -> Result<()> {
+        let (mut patch, channels, mut presets) = setup()?;
+        let mut cues = CueList::new(vec![
+            // Follows one second after its two second fade completes.
+            Cue {
+                follow: Some(1.0),
+                ..cue(2, 2.0)
+            },
+            // Advances half a second after it is triggered.
+            Cue {
+                auto_advance: Some(0.5),
+                ..cue(1, 0.0)
+            },
+            cue(2, 0.0),
+        ]);
+        set_level(&mut patch, 0.0)?;
+        cues.control(
+            &ControlMessage::Go,
+            &mut presets,
+            &mut patch,
+            &channels,
+            &StateCapture::default(),
+        )?;
+
+        assert_eq!(0, run(&mut cues, 1.0, &mut presets, &mut patch, &channels));
+        assert!((level(&patch) - 0.5).abs() < 1e-6);
+        assert_eq!(0, run(&mut cues, 1.9, &mut presets, &mut patch, &channels));
+        assert_eq!(1.0, level(&patch));
+        assert_eq!(Some(0), cues.current);
+
+        assert_eq!(1, run(&mut cues, 0.1, &mut presets, &mut patch, &channels));
+        assert_eq!(Some(1), cues.current);
+        assert_eq!(0.0, level(&patch));
+
+        assert_eq!(0, run(&mut cues, 0.4, &mut presets, &mut patch, &channels));
+        assert_eq!(1, run(&mut cues, 0.1, &mut presets, &mut patch, &channels));
+        assert_eq!(Some(2), cues.current);
+        assert_eq!(1.0, level(&patch));
+
+        // The last cue has nowhere to advance to.
+        assert_eq!(0, run(&mut cues, 5.0, &mut presets, &mut patch, &channels));
+        assert_eq!(Some(2), cues.current);
+        Ok(())
+    }
+
+    #[test]
+    fn test_release_cancels_fades() -> Result<()> {
+        let (mut patch, channels, mut presets) = setup()?;
+        let mut cues = CueList::new(vec![
+            Cue {
+                auto_advance: Some(1.5),
+                ..cue(2, 2.0)
+            },
+            cue(1, 0.0),
+        ]);
+        set_level(&mut patch, 0.0)?;
+        let emitter = &StateCapture::default();
+        cues.control(
+            &ControlMessage::Go,
+            &mut presets,
+            &mut patch,
+            &channels,
+            emitter,
+        )?;
+        run(&mut cues, 1.0, &mut presets, &mut patch, &channels);
+        assert!((level(&patch) - 0.5).abs() < 1e-6);
+
+        cues.control(
+            &ControlMessage::Release,
+            &mut presets,
+            &mut patch,
+            &channels,
+            emitter,
+        )?;
+        assert_eq!(0, run(&mut cues, 2.0, &mut presets, &mut patch, &channels));
+        assert!((level(&patch) - 0.5).abs() < 1e-6);
+        assert_eq!(None, cues.current);
+        Ok(())
+    }
+
+    #[test]
+    fn test_release_keeps_preset_fades() -> Result<()> {
+        let (mut patch, channels, mut presets) = setup()?;
+        let mut cues = CueList::new(vec![cue(1, 0.0)]);
+        set_level(&mut patch, 0.0)?;
+        let emitter = &StateCapture::default();
+        presets.recall(
+            1,
+            &key(),
+            Duration::from_secs(2),
+            false,
+            &mut patch,
+            &channels,
+            emitter,
+        )?;
+        run(&mut cues, 1.0, &mut presets, &mut patch, &channels);
+        assert!((level(&patch) - 0.5).abs() < 1e-6);
+
+        cues.control(
+            &ControlMessage::Release,
+            &mut presets,
+            &mut patch,
+            &channels,
+            emitter,
+        )?;
+        run(&mut cues, 1.0, &mut presets, &mut patch, &channels);
+        assert_eq!(1.0, level(&patch));
+        Ok(())
+    }
+}
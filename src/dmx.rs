@@ -1,5 +1,7 @@
 use std::{fmt::Display, ops::Add};
 
+use anyhow::{anyhow, Result};
+use rust_dmx::{available_ports, DmxPort};
 use serde::{Deserialize, Serialize};
 
 /// A DMX address, indexed from 1.
@@ -31,3 +33,28 @@ pub type DmxBuffer = [u8; 512];
 
 /// Index into the DMX universes.
 pub type UniverseIdx = usize;
+
+/// A destination for rendered frames of a single DMX universe.
+pub trait DmxOutput {
+    fn write(&mut self, frame: &DmxBuffer) -> Result<()>;
+
+    /// Called once when the show shuts down.
+    fn close(&mut self) {}
+}
+
+impl DmxOutput for Box<dyn DmxPort> {
+    fn write(&mut self, frame: &DmxBuffer) -> Result<()> {
+        DmxPort::write(self.as_mut(), frame)?;
+        Ok(())
+    }
+}
+
+/// Open the available DMX port with the provided name.
+pub fn open_port(name: &str) -> Result<Box<dyn DmxPort>> {
+    let mut port = available_ports()?
+        .into_iter()
+        .find(|port| port.to_string() == name)
+        .ok_or_else(|| anyhow!("DMX port \"{name}\" not found"))?;
+    port.open()?;
+    Ok(port)
+}
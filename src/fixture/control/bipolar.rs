@@ -5,7 +5,7 @@ use number::{BipolarFloat, UnipolarFloat};
 
 use crate::{
     channel::KnobIndex,
-    osc::{EmitScopedOscMessage, OscControlMessage},
+    osc::{ControlKind, EmitScopedOscMessage, OscControlMessage, TalkbackMode},
     util::{bipolar_fader_with_detent, unipolar_to_range},
 };
 
@@ -130,7 +130,7 @@ impl<R: RenderToDmx<BipolarFloat>> OscControl<BipolarFloat> for Bipolar<R> {
         emitter: &dyn EmitScopedOscMessage,
     ) -> anyhow::Result<()> {
         self.val = val;
-        emitter.emit_float(&self.name, self.val.into());
+        emitter.emit_float_with_kind(&self.name, self.val.into(), ControlKind::Bipolar);
         Ok(())
     }
 
@@ -142,6 +142,8 @@ impl<R: RenderToDmx<BipolarFloat>> OscControl<BipolarFloat> for Bipolar<R> {
         if msg.control() != self.name {
             return Ok(false);
         }
+        // Don't fight the sender's fader while it is being dragged.
+        emitter.set_talkback(TalkbackMode::Off);
         self.control_direct(
             msg.get_bipolar().with_context(|| self.name.clone())?,
             emitter,
@@ -164,7 +166,7 @@ impl<R: RenderToDmx<BipolarFloat>> OscControl<BipolarFloat> for Bipolar<R> {
     }
 
     fn emit_state(&self, emitter: &dyn EmitScopedOscMessage) {
-        emitter.emit_float(&self.name, self.val.into());
+        emitter.emit_float_with_kind(&self.name, self.val.into(), ControlKind::Bipolar);
     }
 
     fn emit_state_with_callback(
@@ -2,7 +2,7 @@
 
 use anyhow::Context;
 
-use crate::osc::{EmitScopedOscMessage, OscControlMessage};
+use crate::osc::{ControlKind, EmitScopedOscMessage, OscControlMessage};
 
 use super::{
     ChannelControl, ChannelLevelBool, ChannelLevelHandler, OscControl, RenderToDmx,
@@ -15,6 +15,8 @@ pub struct Bool<R: RenderToDmx<bool>> {
     val: bool,
     name: String,
     render: R,
+    /// If true, render as off while the show is blacked out.
+    master: bool,
 }
 
 /// A bool control that renders into a single DMX channel at full range.
@@ -28,6 +30,7 @@ impl<R: RenderToDmx<bool>> Bool<R> {
             val: false,
             name: name.into(),
             render,
+            master: false,
         }
     }
 
@@ -38,6 +41,7 @@ impl<R: RenderToDmx<bool>> Bool<R> {
             val: true,
             name: name.into(),
             render,
+            master: false,
         }
     }
 
@@ -45,6 +49,14 @@ impl<R: RenderToDmx<bool>> Bool<R> {
         self.val
     }
 
+    /// Render this control as off when the master level is all the way down,
+    /// when rendering with group controls.
+    /// Use this for shutters and lamps that cannot be dimmed.
+    pub fn with_master(mut self) -> Self {
+        self.master = true;
+        self
+    }
+
     pub fn with_channel_level(self) -> ChannelLevelBool<Self> {
         ChannelControl::wrap(self, "Level".to_string(), true, ChannelLevelHandler)
     }
@@ -76,7 +88,7 @@ impl<R: RenderToDmx<bool>> OscControl<bool> for Bool<R> {
         emitter: &dyn EmitScopedOscMessage,
     ) -> anyhow::Result<()> {
         self.val = val;
-        emitter.emit_float(&self.name, self.val.into());
+        emitter.emit_float_with_kind(&self.name, self.val.into(), ControlKind::Bool);
         Ok(())
     }
 
@@ -107,7 +119,7 @@ impl<R: RenderToDmx<bool>> OscControl<bool> for Bool<R> {
     }
 
     fn emit_state(&self, emitter: &dyn EmitScopedOscMessage) {
-        emitter.emit_float(&self.name, self.val.into());
+        emitter.emit_float_with_kind(&self.name, self.val.into(), ControlKind::Bool);
     }
 
     fn emit_state_with_callback(
@@ -124,6 +136,16 @@ impl<R: RenderToDmx<bool>> RenderToDmxWithAnimations for Bool<R> {
     fn render(&self, _animations: impl Iterator<Item = f64>, dmx_buf: &mut [u8]) {
         self.render.render(&self.val, dmx_buf);
     }
+
+    fn render_with_group(
+        &self,
+        group_controls: &crate::fixture::FixtureGroupControls,
+        _animations: impl Iterator<Item = f64>,
+        dmx_buf: &mut [u8],
+    ) {
+        let val = self.val && !(self.master && group_controls.blacked_out());
+        self.render.render(&val, dmx_buf);
+    }
 }
 
 /// Render a bool float to fixed values.
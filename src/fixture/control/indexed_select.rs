@@ -3,7 +3,7 @@
 use anyhow::{anyhow, ensure, Result};
 use rosc::OscType;
 
-use crate::osc::{EmitScopedOscMessage, OscControlMessage, ScopedOscMessage};
+use crate::osc::{ControlKind, EmitScopedOscMessage, OscControlMessage, ScopedOscMessage};
 
 use super::{OscControl, RenderToDmx, RenderToDmxWithAnimations};
 
@@ -169,10 +169,13 @@ impl<R: RenderToDmx<usize>> OscControl<usize> for IndexedSelect<R> {
             } else {
                 (1, i + 1)
             };
-            emitter.emit_osc(ScopedOscMessage {
-                control: &format!("{}/{}/{}", self.name, x, y),
-                arg: OscType::Float(val),
-            })
+            emitter.emit_osc_with_kind(
+                ScopedOscMessage {
+                    control: &format!("{}/{}/{}", self.name, x, y),
+                    arg: OscType::Float(val),
+                },
+                ControlKind::Select,
+            )
         }
     }
 
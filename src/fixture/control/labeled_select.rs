@@ -6,7 +6,7 @@ use anyhow::bail;
 use itertools::Itertools;
 use rosc::OscType;
 
-use crate::osc::ScopedOscMessage;
+use crate::osc::{ControlKind, ScopedOscMessage};
 
 use super::{Bool, OscControl, RenderToDmxWithAnimations};
 
@@ -115,6 +115,10 @@ impl OscControl<&str> for LabeledSelect {
                 self.labels().join(", ")
             );
         };
+        // Ignore button release messages.
+        if msg.arg == OscType::Float(0.0) {
+            return Ok(true);
+        }
         // If selected is same as current, do nothing.
         if i == self.selected {
             return Ok(true);
@@ -131,10 +135,13 @@ impl OscControl<&str> for LabeledSelect {
         for (i, label) in self.labels().enumerate() {
             // TODO: consider caching outgoing addresses
             // We could also do this for matching incoming addresses.
-            emitter.emit_osc(ScopedOscMessage {
-                control: &format!("{}/{}", self.name, label),
-                arg: OscType::Float(if i == self.selected { 1.0 } else { 0.0 }),
-            });
+            emitter.emit_osc_with_kind(
+                ScopedOscMessage {
+                    control: &format!("{}/{}", self.name, label),
+                    arg: OscType::Float(if i == self.selected { 1.0 } else { 0.0 }),
+                },
+                ControlKind::Select,
+            );
         }
     }
 }
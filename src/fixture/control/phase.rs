@@ -5,7 +5,7 @@ use number::{Phase, UnipolarFloat};
 
 use crate::{
     channel::KnobIndex,
-    osc::{EmitScopedOscMessage, OscControlMessage},
+    osc::{ControlKind, EmitScopedOscMessage, OscControlMessage, TalkbackMode},
     util::unipolar_to_range,
 };
 
@@ -78,7 +78,7 @@ impl<R: RenderToDmx<Phase>> OscControl<Phase> for PhaseControl<R> {
         emitter: &dyn EmitScopedOscMessage,
     ) -> anyhow::Result<()> {
         self.val = val;
-        emitter.emit_float(&self.name, self.val.into());
+        emitter.emit_float_with_kind(&self.name, self.val.into(), ControlKind::Phase);
         Ok(())
     }
 
@@ -90,6 +90,8 @@ impl<R: RenderToDmx<Phase>> OscControl<Phase> for PhaseControl<R> {
         if msg.control() != self.name {
             return Ok(false);
         }
+        // Don't fight the sender's fader while it is being dragged.
+        emitter.set_talkback(TalkbackMode::Off);
         self.control_direct(msg.get_phase().with_context(|| self.name.clone())?, emitter)?;
         Ok(true)
     }
@@ -109,7 +111,7 @@ impl<R: RenderToDmx<Phase>> OscControl<Phase> for PhaseControl<R> {
     }
 
     fn emit_state(&self, emitter: &dyn EmitScopedOscMessage) {
-        emitter.emit_float(&self.name, self.val.into());
+        emitter.emit_float_with_kind(&self.name, self.val.into(), ControlKind::Phase);
     }
 
     fn emit_state_with_callback(
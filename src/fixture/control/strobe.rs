@@ -256,10 +256,15 @@ impl<S: OscControl<T> + RenderToDmxWithAnimations, R: RenderToDmx<Option<Unipola
         animations: impl Iterator<Item = f64>,
         dmx_buf: &mut [u8],
     ) {
-        if let Some(rate) = self.strobe.val_with_master(&group_controls.strobe()) {
+        // Never strobe through a blackout.
+        let strobe = (!group_controls.blacked_out())
+            .then(|| self.strobe.val_with_master(&group_controls.strobe()))
+            .flatten();
+        if let Some(rate) = strobe {
             self.strobe.render.render(&Some(rate), dmx_buf);
         } else {
-            self.shutter.render(animations, dmx_buf);
+            self.shutter
+                .render_with_group(group_controls, animations, dmx_buf);
         }
     }
 }
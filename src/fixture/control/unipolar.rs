@@ -5,7 +5,7 @@ use number::UnipolarFloat;
 
 use crate::{
     channel::KnobIndex,
-    osc::{EmitScopedOscMessage, OscControlMessage},
+    osc::{ControlKind, EmitScopedOscMessage, OscControlMessage, TalkbackMode},
     util::unipolar_to_range,
 };
 
@@ -20,6 +20,8 @@ pub struct Unipolar<R: RenderToDmx<UnipolarFloat>> {
     val: UnipolarFloat,
     name: String,
     render: R,
+    /// If true, scale the rendered value by the master level.
+    master: bool,
 }
 
 /// A unipolar control that renders into a single DMX channel over a range.
@@ -32,6 +34,7 @@ impl<R: RenderToDmx<UnipolarFloat>> Unipolar<R> {
             val: UnipolarFloat::ZERO,
             name: name.into(),
             render,
+            master: false,
         }
     }
 
@@ -41,6 +44,13 @@ impl<R: RenderToDmx<UnipolarFloat>> Unipolar<R> {
         self
     }
 
+    /// Scale this control by the grand master when rendering with group controls.
+    /// Use this for controls that set a fixture's intensity.
+    pub fn with_master(mut self) -> Self {
+        self.master = true;
+        self
+    }
+
     /// Decorate this control with channel level control.
     pub fn with_channel_level(self) -> ChannelLevelUnipolar<Self> {
         ChannelControl::wrap(self, "Level".to_string(), true, ChannelLevelHandler)
@@ -93,7 +103,7 @@ impl<R: RenderToDmx<UnipolarFloat>> OscControl<UnipolarFloat> for Unipolar<R> {
         emitter: &dyn EmitScopedOscMessage,
     ) -> anyhow::Result<()> {
         self.val = val;
-        emitter.emit_float(&self.name, self.val.into());
+        emitter.emit_float_with_kind(&self.name, self.val.into(), ControlKind::Unipolar);
         Ok(())
     }
 
@@ -105,6 +115,8 @@ impl<R: RenderToDmx<UnipolarFloat>> OscControl<UnipolarFloat> for Unipolar<R> {
         if msg.control() != self.name {
             return Ok(false);
         }
+        // Don't fight the sender's fader while it is being dragged.
+        emitter.set_talkback(TalkbackMode::Off);
         self.control_direct(
             msg.get_unipolar().with_context(|| self.name.clone())?,
             emitter,
@@ -127,7 +139,7 @@ impl<R: RenderToDmx<UnipolarFloat>> OscControl<UnipolarFloat> for Unipolar<R> {
     }
 
     fn emit_state(&self, emitter: &dyn EmitScopedOscMessage) {
-        emitter.emit_float(&self.name, self.val.into());
+        emitter.emit_float_with_kind(&self.name, self.val.into(), ControlKind::Unipolar);
     }
 
     fn emit_state_with_callback(
@@ -144,6 +156,19 @@ impl<R: RenderToDmx<UnipolarFloat>> RenderToDmxWithAnimations for Unipolar<R> {
     fn render(&self, animations: impl Iterator<Item = f64>, dmx_buf: &mut [u8]) {
         self.render.render(&self.val_with_anim(animations), dmx_buf);
     }
+
+    fn render_with_group(
+        &self,
+        group_controls: &crate::fixture::FixtureGroupControls,
+        animations: impl Iterator<Item = f64>,
+        dmx_buf: &mut [u8],
+    ) {
+        let mut val = self.val_with_anim(animations);
+        if self.master {
+            val = UnipolarFloat::new(val.val() * group_controls.master_level().val());
+        }
+        self.render.render(&val, dmx_buf);
+    }
 }
 
 /// Render a unipolar float to a continuous range.
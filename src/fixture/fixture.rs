@@ -61,6 +61,17 @@ pub trait Control {
 pub trait ControllableFixture: EmitState + Control {
     #[allow(unused)]
     fn update(&mut self, master_controls: &MasterControls, dt: Duration) {}
+
+    /// Respond to a change in the master controls.
+    /// Fixtures that aren't rendered to DMX can use this to apply the master
+    /// level to their output.
+    #[allow(unused)]
+    fn master_controls_changed(
+        &mut self,
+        master_controls: &MasterControls,
+        emitter: &FixtureStateEmitter,
+    ) {
+    }
 }
 
 pub trait NonAnimatedFixture: ControllableFixture {
@@ -172,6 +183,15 @@ impl<F: AnimatedFixture> ControllableFixture for FixtureWithAnimations<F> {
             ta.animation.update_state(dt, UnipolarFloat::ZERO);
         }
     }
+
+    fn master_controls_changed(
+        &mut self,
+        master_controls: &MasterControls,
+        emitter: &FixtureStateEmitter,
+    ) {
+        self.fixture
+            .master_controls_changed(master_controls, emitter);
+    }
 }
 
 impl<F: AnimatedFixture> Fixture for FixtureWithAnimations<F> {
@@ -115,6 +115,18 @@ impl FixtureGroup {
             .control_from_channel(msg, &FixtureStateEmitter::new(&self.key, channel_emitter))
     }
 
+    /// Notify the fixture that the master controls have changed.
+    pub fn master_controls_changed(
+        &mut self,
+        master_controls: &MasterControls,
+        emitter: ChannelStateEmitter,
+    ) {
+        self.fixture.master_controls_changed(
+            master_controls,
+            &FixtureStateEmitter::new(&self.key, emitter),
+        );
+    }
+
     /// The master controls are provided to potentially alter the update.
     pub fn update(
         &mut self,
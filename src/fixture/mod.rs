@@ -1,3 +1,5 @@
+use number::UnipolarFloat;
+
 use crate::master::{MasterControls, Strobe};
 
 pub mod animation_target;
@@ -26,6 +28,17 @@ impl<'a> FixtureGroupControls<'a> {
     pub fn strobe(&self) -> Strobe {
         self.master_controls.strobe()
     }
+
+    /// The level that fixture intensities should be scaled by.
+    pub fn master_level(&self) -> UnipolarFloat {
+        self.master_controls.master_level()
+    }
+
+    /// Return true if the master level is all the way down.
+    /// Fixtures that cannot dim should close their shutter.
+    pub fn blacked_out(&self) -> bool {
+        self.master_level().val() == 0.0
+    }
 }
 
 pub mod prelude {
@@ -39,6 +39,9 @@ use crate::fixture::group::GroupFixtureConfig;
 use crate::fixture::rug_doctor::RugDoctor;
 use crate::fixture::wizlet::Wizlet;
 
+#[cfg(test)]
+mod golden;
+
 type UsedAddrs = HashMap<(UniverseIdx, usize), FixtureConfig>;
 
 #[derive(Default)]
@@ -216,6 +219,12 @@ impl Patch {
         Ok(used_addrs)
     }
 
+    /// Return the config of the fixture patched at the provided universe and
+    /// DMX buffer index, if there is one.
+    pub fn fixture_at(&self, universe: UniverseIdx, index: usize) -> Option<&FixtureConfig> {
+        self.used_addrs.get(&(universe, index))
+    }
+
     /// Look up the static version of a fixture type registered with the patch.
     pub fn lookup_fixture_type(&self, t: &str) -> Option<FixtureType> {
         self.fixture_type_lookup.get(t).copied()
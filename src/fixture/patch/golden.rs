@@ -0,0 +1,245 @@
+//! Golden-file render tests for every patchable fixture profile.
+//!
+//! Each profile has a script in testdata/render that drives an unmirrored
+//! and a mirrored instance of the fixture, patched as a single group, through
+//! OSC control messages and update ticks. The DMX rendered at each render step
+//! is compared to the profile's checked-in golden file. After an intentional
+//! change to a profile's output, run the tests with UPDATE_GOLDEN=1 set to
+//! rewrite the golden files, and review the diff.
+//!
+//! Each script line is one of the following; blank lines and lines starting
+//! with # are ignored.
+//! - option <key> <value>: a patch option, before any other step
+//! - set <control> <value>: send a float to one of the fixture's controls
+//! - anim <control> <value>: send a float to one of the animation controls
+//! - update <n>: update the fixture n times
+//! - render: render both fixtures into the golden output
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use itertools::Itertools;
+use number::UnipolarFloat;
+use rosc::{OscMessage, OscType};
+
+use super::{get_candidate, Patch, PATCHERS};
+use crate::{
+    animation::AnimationUIState,
+    channel::{ChannelId, ChannelStateEmitter, Channels},
+    config::{DmxAddrConfig, FixtureGroupConfig, Options},
+    control::StateCapture,
+    dmx::DmxAddr,
+    fixture::FixtureGroupKey,
+    master::MasterControls,
+    osc::{fixture_control_addr, OscClientId, OscControlMessage, ScopedControlEmitter},
+    show::UPDATE_INTERVAL,
+};
+
+const RENDER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/render");
+
+/// A fixture group patched with one unmirrored and one mirrored fixture.
+struct Harness {
+    patch: Patch,
+    key: FixtureGroupKey,
+    channel: Option<ChannelId>,
+    animation_ui_state: AnimationUIState,
+    /// Held fixed for the whole script.
+    master_controls: MasterControls,
+    channel_count: usize,
+    output: String,
+}
+
+impl Harness {
+    fn new(name: &str, options: Options) -> Result<Self> {
+        let candidate = get_candidate(name, &options)?;
+        let animated = candidate.fixture.is_animated();
+        let channel_count = candidate.channel_count;
+        let start: DmxAddr = serde_yaml::from_str("1")?;
+
+        let mut patch = Patch::default();
+        let mut channels = Channels::new();
+        for (addr, mirror) in [(start, false), (start + channel_count, true)] {
+            patch.patch(
+                &mut channels,
+                FixtureGroupConfig {
+                    name: name.to_string(),
+                    addr: Some(DmxAddrConfig::Single(addr)),
+                    universe: 0,
+                    mirror,
+                    group: None,
+                    options: options.clone(),
+                    channel: animated,
+                },
+            )?;
+        }
+        Ok(Self {
+            patch,
+            key: FixtureGroupKey {
+                fixture: candidate.fixture_type,
+                group: None,
+            },
+            channel: channels.current_channel(),
+            animation_ui_state: AnimationUIState::new(channels.current_channel()),
+            master_controls: MasterControls::new(),
+            channel_count,
+            output: String::new(),
+        })
+    }
+
+    fn step(&mut self, line_number: usize, line: &str) -> Result<()> {
+        let words: Vec<_> = line.split_whitespace().collect();
+        let capture = StateCapture::default();
+        let group = self.patch.get_mut(&self.key)?;
+        match words[..] {
+            ["set", control, value] => {
+                let msg = control_message(fixture_control_addr(&self.key, control), value)?;
+                group.control(&msg, ChannelStateEmitter::new(self.channel, &capture))?;
+            }
+            ["anim", control, value] => {
+                let Some(channel) = self.channel else {
+                    bail!("{} is not animated", self.key);
+                };
+                let msg = control_message(format!("/Animation/{control}"), value)?;
+                self.animation_ui_state.control_osc(
+                    &msg,
+                    channel,
+                    None,
+                    group,
+                    &ScopedControlEmitter {
+                        entity: "Animation",
+                        emitter: &capture,
+                    },
+                )?;
+            }
+            ["update", n] => {
+                for _ in 0..n.parse::<usize>()? {
+                    group.update(&self.master_controls, UPDATE_INTERVAL, UnipolarFloat::ZERO);
+                }
+            }
+            ["render"] => {
+                let mut buffers = vec![[0u8; 512]];
+                group.render(&self.master_controls, &mut buffers);
+                writeln!(self.output, "render at line {line_number}")?;
+                for (i, label) in ["unmirrored", "mirrored"].into_iter().enumerate() {
+                    let start = i * self.channel_count;
+                    let vals = &buffers[0][start..start + self.channel_count];
+                    writeln!(self.output, "  {label}: {}", vals.iter().join(" "))?;
+                }
+            }
+            _ => bail!("unknown step"),
+        }
+        Ok(())
+    }
+}
+
+/// Create an OSC control message carrying a single float.
+fn control_message(addr: String, value: &str) -> Result<OscControlMessage> {
+    Ok(OscControlMessage::new(
+        OscMessage {
+            addr,
+            args: vec![OscType::Float(value.parse()?)],
+        },
+        OscClientId::internal(),
+    )?)
+}
+
+/// Run the script for a fixture and return the rendered output.
+fn run_script(name: &str, script: &str) -> Result<String> {
+    let mut options = Options::new();
+    let mut harness = None;
+    for (i, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(option) = line.strip_prefix("option ") {
+            ensure!(harness.is_none(), "line {}: options must come first", i + 1);
+            let (key, value) = option
+                .split_once(' ')
+                .with_context(|| format!("line {}: option is missing a value", i + 1))?;
+            options.insert(key.to_string(), value.trim().to_string());
+            continue;
+        }
+        if harness.is_none() {
+            harness = Some(Harness::new(name, options.clone())?);
+        }
+        harness
+            .as_mut()
+            .unwrap()
+            .step(i + 1, line)
+            .with_context(|| format!("line {}: {line}", i + 1))?;
+    }
+    let Some(harness) = harness else {
+        bail!("script has no steps");
+    };
+    Ok(harness.output)
+}
+
+/// Return the fixture name and path of every script.
+fn scripts() -> Vec<(String, PathBuf)> {
+    let mut scripts: Vec<_> = fs::read_dir(RENDER_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "script"))
+        .map(|path| {
+            let name = path.file_stem().unwrap().to_string_lossy().to_string();
+            (name, path)
+        })
+        .collect();
+    scripts.sort();
+    scripts
+}
+
+/// Check the output of a script against its golden file, or write the golden
+/// file if UPDATE_GOLDEN is set.
+fn check_golden(name: &str, script: &Path) -> Result<()> {
+    let output = run_script(name, &fs::read_to_string(script)?)?;
+    let golden_path = script.with_extension("golden");
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&golden_path, output)?;
+        return Ok(());
+    }
+    ensure!(
+        golden_path.exists(),
+        "{} is missing; run with UPDATE_GOLDEN=1 set to create it",
+        golden_path.display()
+    );
+    let golden = fs::read_to_string(&golden_path)?;
+    if let Some((expected, actual)) = golden
+        .lines()
+        .zip(output.lines())
+        .find(|(expected, actual)| expected != actual)
+    {
+        bail!("expected\n{expected}\nbut rendered\n{actual}");
+    }
+    ensure!(
+        golden.lines().count() == output.lines().count(),
+        "rendered {} lines but expected {}",
+        output.lines().count(),
+        golden.lines().count()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_golden_renders() {
+    let failures: Vec<_> = scripts()
+        .into_iter()
+        .filter_map(|(name, path)| {
+            check_golden(&name, &path)
+                .err()
+                .map(|err| format!("{name}: {err:#}"))
+        })
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
+
+#[test]
+fn test_every_profile_has_a_script() {
+    // Every script patches exactly one profile by name, so one script per
+    // patcher means every profile is covered.
+    assert_eq!(PATCHERS.len(), scripts().len());
+}
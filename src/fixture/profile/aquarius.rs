@@ -13,7 +13,9 @@ pub struct Aquarius {
 impl Default for Aquarius {
     fn default() -> Self {
         Self {
-            lamp_on: Bool::full_channel("LampOn", 1).with_channel_level(),
+            lamp_on: Bool::full_channel("LampOn", 1)
+                .with_master()
+                .with_channel_level(),
             rotation: Bipolar::split_channel("Rotation", 0, 130, 8, 132, 255, 0)
                 .with_detent()
                 .with_mirroring(true)
@@ -28,7 +28,7 @@ impl Default for Astroscan {
         Self {
             lamp_on: Bool::full_channel("LampOn", 2),
             shutter: ShutterStrobe::new(
-                Unipolar::channel("Dimmer", 3, 0, 139),
+                Unipolar::channel("Dimmer", 3, 0, 139).with_master(),
                 Strobe::channel("Strobe", 3, 140, 243, 0),
             )
             .with_channel_level(),
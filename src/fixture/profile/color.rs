@@ -80,7 +80,7 @@ impl AnimatedFixture for Color {
     type Target = AnimationTarget;
     fn render_with_animations(
         &self,
-        _group_controls: &FixtureGroupControls,
+        group_controls: &FixtureGroupControls,
         animation_vals: TargetedAnimationValues<Self::Target>,
         dmx_buf: &mut [u8],
     ) {
@@ -100,7 +100,7 @@ impl AnimatedFixture for Color {
             dmx_buf,
             Phase::new(hue),
             UnipolarFloat::new(sat),
-            UnipolarFloat::new(val),
+            UnipolarFloat::new(val.clamp(0.0, 1.0) * group_controls.master_level().val()),
         );
     }
 }
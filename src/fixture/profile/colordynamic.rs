@@ -23,7 +23,7 @@ impl Default for Colordynamic {
     fn default() -> Self {
         Colordynamic {
             shutter: ShutterStrobe::new(
-                Bool::full_channel("ShutterOpen", 3),
+                Bool::full_channel("ShutterOpen", 3).with_master(),
                 Strobe::channel("Strobe", 3, 16, 239, 255),
             )
             .with_channel_level(),
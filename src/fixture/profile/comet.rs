@@ -16,7 +16,7 @@ pub struct Comet {
 impl Default for Comet {
     fn default() -> Self {
         Self {
-            shutter_open: Bool::full_channel("Shutter", 0),
+            shutter_open: Bool::full_channel("Shutter", 0).with_master(),
             trigger_state: TriggerState::default(),
             // FIXME: need to make strobe rate a quadratic fader
             strobe: Strobe::channel("Strobe", 0, 151, 255, 75),
@@ -38,9 +38,10 @@ impl PatchFixture for Comet {
 }
 
 impl NonAnimatedFixture for Comet {
-    fn render(&self, _group_controls: &FixtureGroupControls, dmx_buf: &mut [u8]) {
-        if !self.shutter_open.val() {
-            self.shutter_open.render_no_anim(dmx_buf);
+    fn render(&self, group_controls: &FixtureGroupControls, dmx_buf: &mut [u8]) {
+        if !self.shutter_open.val() || group_controls.blacked_out() {
+            self.shutter_open
+                .render_with_group(group_controls, std::iter::empty(), dmx_buf);
         } else if self.shutter_sound_active.val() {
             self.shutter_sound_active.render_no_anim(dmx_buf);
         } else {
@@ -14,7 +14,9 @@ pub struct CosmicBurst {
 impl Default for CosmicBurst {
     fn default() -> Self {
         Self {
-            dimmer: Unipolar::full_channel("Dimmer", 2).with_channel_level(),
+            dimmer: Unipolar::full_channel("Dimmer", 2)
+                .with_master()
+                .with_channel_level(),
             strobe: Strobe::channel("Strobe", 1, 64, 95, 32),
             rotation: Bipolar::split_channel("Rotation", 0, 125, 8, 130, 247, 0)
                 .with_detent()
@@ -39,8 +41,11 @@ impl AnimatedFixture for CosmicBurst {
         animation_vals: TargetedAnimationValues<Self::Target>,
         dmx_buf: &mut [u8],
     ) {
-        self.dimmer
-            .render(animation_vals.filter(&AnimationTarget::Dimmer), dmx_buf);
+        self.dimmer.render_with_group(
+            group_controls,
+            animation_vals.filter(&AnimationTarget::Dimmer),
+            dmx_buf,
+        );
         self.strobe
             .render_with_group(group_controls, std::iter::empty(), dmx_buf);
         self.rotation.render_with_group(
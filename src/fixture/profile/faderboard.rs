@@ -3,6 +3,7 @@
 use log::error;
 
 use crate::fixture::prelude::*;
+use crate::osc::EmitScopedOscMessage;
 
 #[derive(Debug)]
 pub struct Faderboard {
@@ -43,15 +44,16 @@ impl Faderboard {
         Self::emit(sc, emitter);
     }
 
-    fn emit(_sc: StateChange, _emitter: &FixtureStateEmitter) {
-        // FIXME: no talkback
+    fn emit((chan, val): StateChange, emitter: &FixtureStateEmitter) {
+        CONTROLS.set(chan, val, emitter);
     }
 }
 
 impl NonAnimatedFixture for Faderboard {
-    fn render(&self, _group_controls: &FixtureGroupControls, dmx_buf: &mut [u8]) {
+    fn render(&self, group_controls: &FixtureGroupControls, dmx_buf: &mut [u8]) {
+        let master = group_controls.master_level().val();
         for (i, v) in self.vals.iter().enumerate() {
-            dmx_buf[i] = unipolar_to_range(0, 255, *v);
+            dmx_buf[i] = unipolar_to_range(0, 255, UnipolarFloat::new(v.val() * master));
         }
     }
 }
@@ -70,9 +72,10 @@ impl crate::fixture::Control for Faderboard {
         msg: &OscControlMessage,
         emitter: &FixtureStateEmitter,
     ) -> anyhow::Result<bool> {
-        let Some((ctl, _)) = self.controls.handle(msg)? else {
+        let Some((ctl, talkback)) = self.controls.handle(msg)? else {
             return Ok(true);
         };
+        emitter.set_talkback(talkback);
         self.handle_state_change(ctl, emitter);
         Ok(true)
     }
@@ -23,7 +23,9 @@ pub struct FreedomFries {
 impl Default for FreedomFries {
     fn default() -> Self {
         Self {
-            dimmer: Unipolar::full_channel("Dimmer", 0).with_channel_level(),
+            dimmer: Unipolar::full_channel("Dimmer", 0)
+                .with_master()
+                .with_channel_level(),
             color: Default::default(),
             speed: Unipolar::full_channel("Speed", 7).with_channel_knob(0),
             strobe: Strobe::channel("Strobe", 5, 11, 255, 0),
@@ -48,8 +50,11 @@ impl AnimatedFixture for FreedomFries {
         animation_vals: TargetedAnimationValues<Self::Target>,
         dmx_buf: &mut [u8],
     ) {
-        self.dimmer
-            .render(animation_vals.filter(&AnimationTarget::Dimmer), dmx_buf);
+        self.dimmer.render_with_group(
+            group_controls,
+            animation_vals.filter(&AnimationTarget::Dimmer),
+            dmx_buf,
+        );
         self.speed
             .render(animation_vals.filter(&AnimationTarget::Speed), dmx_buf);
         self.color.render_without_animations(&mut dmx_buf[1..4]);
@@ -28,7 +28,9 @@ impl Default for FreqStrobe {
     fn default() -> Self {
         let flasher = Flasher::default();
         Self {
-            dimmer: Unipolar::channel("Dimmer", 16, 1, 255).with_channel_level(),
+            dimmer: Unipolar::channel("Dimmer", 16, 1, 255)
+                .with_master()
+                .with_channel_level(),
             // strobe: Strobe::channel("Strobe", 17, 9, 131, 0),
             run: Bool::new_off("Run", ()),
             rate: Unipolar::new("Rate", ()).with_channel_knob(0),
@@ -47,7 +47,9 @@ impl Default for FusionRoll {
                 ],
             )
             .with_split(56),
-            dimmer: Unipolar::full_channel("Dimmer", 4).with_channel_level(),
+            dimmer: Unipolar::full_channel("Dimmer", 4)
+                .with_master()
+                .with_channel_level(),
 
             laser_rotation: Bipolar::split_channel("LaserRotation", 5, 10, 120, 136, 245, 0)
                 .with_detent()
@@ -55,7 +57,7 @@ impl Default for FusionRoll {
                 .with_channel_knob(2),
             led_strobe: Strobe::channel("LEDStrobe", 3, 16, 131, 8),
             laser: FullShutterStrobe::new(
-                Bool::channel("LaserOn", 6, 0, 8),
+                Bool::channel("LaserOn", 6, 0, 8).with_master(),
                 Strobe::channel("LaserStrobe", 6, 16, 131, 8),
             ),
         }
@@ -19,7 +19,9 @@ pub struct H2O {
 impl Default for H2O {
     fn default() -> Self {
         Self {
-            dimmer: Unipolar::full_channel("Dimmer", 0).with_channel_level(),
+            dimmer: Unipolar::full_channel("Dimmer", 0)
+                .with_master()
+                .with_channel_level(),
             rotation: Bipolar::split_channel("Rotation", 1, 120, 10, 135, 245, 0)
                 .with_detent()
                 .with_mirroring(true)
@@ -55,6 +55,9 @@ impl AnimatedFixture for Hypnotic {
             (false, true, true) => 98,
             (true, true, true) => 188,
         };
+        if group_controls.blacked_out() {
+            dmx_buf[0] = 0;
+        }
         self.rotation
             .render_with_group(group_controls, animation_vals.all(), dmx_buf);
     }
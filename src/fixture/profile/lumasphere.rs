@@ -1,6 +1,7 @@
 use std::time::Duration;
 
 use crate::fixture::prelude::*;
+use crate::osc::{ControlKind, EmitScopedOscMessage};
 
 /// DMX 255 is too fast; restrict to a reasonable value.
 const MAX_ROTATION_SPEED: u8 = 100;
@@ -104,19 +105,35 @@ impl Lumasphere {
         Self::emit(sc, emitter);
     }
 
-    fn emit(_sc: StateChange, _emitter: &FixtureStateEmitter) {
-        // FIXME: no talkback
+    fn emit(sc: StateChange, emitter: &FixtureStateEmitter) {
+        use ControlKind::*;
+        use StateChange::*;
+        match sc {
+            Lamp1Intensity(v) => {
+                emitter.emit_float_with_kind("lamp_1_intensity", v.val(), Unipolar)
+            }
+            Lamp2Intensity(v) => {
+                emitter.emit_float_with_kind("lamp_2_intensity", v.val(), Unipolar)
+            }
+            BallRotation(v) => emitter.emit_float_with_kind("ball_rotation", v.val(), Bipolar),
+            BallStart(v) => BALL_START.send(v, emitter),
+            ColorRotation(v) => emitter.emit_float_with_kind("color_rotation", v.val(), Unipolar),
+            ColorStart(v) => COLOR_START.send(v, emitter),
+            Strobe1(sc) => Strobe::emit(1, sc, emitter),
+            Strobe2(sc) => Strobe::emit(2, sc, emitter),
+        }
     }
 }
 
 impl NonAnimatedFixture for Lumasphere {
-    fn render(&self, _group_controls: &FixtureGroupControls, dmx_buf: &mut [u8]) {
+    fn render(&self, group_controls: &FixtureGroupControls, dmx_buf: &mut [u8]) {
+        let master = group_controls.master_level();
         self.render_ball_rotation(&mut dmx_buf[0..2]);
         dmx_buf[2] = self.render_color_rotation();
-        self.strobe_1.render(&mut dmx_buf[3..5]);
-        self.strobe_2.render(&mut dmx_buf[5..7]);
-        dmx_buf[7] = unipolar_to_range(0, 255, self.lamp_1_intensity);
-        dmx_buf[8] = unipolar_to_range(0, 255, self.lamp_2_intensity);
+        self.strobe_1.render(master, &mut dmx_buf[3..5]);
+        self.strobe_2.render(master, &mut dmx_buf[5..7]);
+        dmx_buf[7] = unipolar_to_range(0, 255, scale(self.lamp_1_intensity, master));
+        dmx_buf[8] = unipolar_to_range(0, 255, scale(self.lamp_2_intensity, master));
     }
 }
 
@@ -125,7 +142,7 @@ impl crate::fixture::EmitState for Lumasphere {
         use StateChange::*;
         Self::emit(Lamp1Intensity(self.lamp_1_intensity), emitter);
         Self::emit(Lamp2Intensity(self.lamp_2_intensity), emitter);
-        Self::emit(BallRotation(self.ball_rotation.current()), emitter);
+        Self::emit(BallRotation(self.ball_rotation.target), emitter);
         Self::emit(BallStart(self.ball_start), emitter);
         Self::emit(ColorRotation(self.color_rotation), emitter);
         Self::emit(ColorStart(self.color_start), emitter);
@@ -140,9 +157,10 @@ impl crate::fixture::Control for Lumasphere {
         msg: &OscControlMessage,
         emitter: &FixtureStateEmitter,
     ) -> anyhow::Result<bool> {
-        let Some((ctl, _)) = self.controls.handle(msg)? else {
+        let Some((ctl, talkback)) = self.controls.handle(msg)? else {
             return Ok(true);
         };
+        emitter.set_talkback(talkback);
         self.handle_state_change(ctl, emitter);
         Ok(true)
     }
@@ -161,10 +179,10 @@ pub struct Strobe {
 }
 
 impl Strobe {
-    fn render(&self, dmx_slice: &mut [u8]) {
+    fn render(&self, master: UnipolarFloat, dmx_slice: &mut [u8]) {
         let (intensity, rate) = if self.state.on() {
             (
-                unipolar_to_range(0, 255, self.intensity),
+                unipolar_to_range(0, 255, scale(self.intensity, master)),
                 unipolar_to_range(0, 255, self.state.rate()),
             )
         } else {
@@ -186,6 +204,25 @@ impl Strobe {
         self.state.emit_state(&mut emit);
     }
 
+    fn emit(index: u8, sc: StrobeStateChange, emitter: &FixtureStateEmitter) {
+        use ControlKind::*;
+        use GenericStrobeStateChange::*;
+        use StrobeStateChange::*;
+        match sc {
+            State(On(v)) => {
+                emitter.emit_float_with_kind(&format!("strobe_{index}_state"), v.into(), Bool)
+            }
+            State(Rate(v)) => {
+                emitter.emit_float_with_kind(&format!("strobe_{index}_rate"), v.val(), Unipolar)
+            }
+            Intensity(v) => emitter.emit_float_with_kind(
+                &format!("strobe_{index}_intensity"),
+                v.val(),
+                Unipolar,
+            ),
+        }
+    }
+
     fn handle_state_change(&mut self, sc: &StrobeStateChange) {
         use StrobeStateChange::*;
         match sc {
@@ -216,6 +253,11 @@ pub enum StateChange {
 // Lumasphere has no controls that are not represented as state changes.
 pub type ControlMessage = StateChange;
 
+/// Scale an intensity by the master level.
+fn scale(intensity: UnipolarFloat, master: UnipolarFloat) -> UnipolarFloat {
+    UnipolarFloat::new(intensity.val() * master.val())
+}
+
 const BALL_START: Button = button("ball_start");
 const COLOR_START: Button = button("color_start");
 
@@ -59,9 +59,13 @@ impl AnimatedFixture for RotosphereQ3 {
             self.sat
                 .control
                 .val_with_anim(animation_vals.filter(&AnimationTarget::Sat)),
-            self.val
-                .control
-                .val_with_anim(animation_vals.filter(&AnimationTarget::Val)),
+            UnipolarFloat::new(
+                self.val
+                    .control
+                    .val_with_anim(animation_vals.filter(&AnimationTarget::Val))
+                    .val()
+                    * group_controls.master_level().val(),
+            ),
         );
         self.strobe
             .render_with_group(group_controls, std::iter::empty(), dmx_buf);
@@ -19,6 +19,11 @@ pub struct RugDoctor {
     size: ChannelKnobUnipolar<Unipolar<()>>,
     #[on_change = "update_preset"]
     preset: IndexedSelect<()>,
+    /// The master level, as of the last change to the master controls.
+    /// WLED isn't rendered to DMX, so it is applied when sending the level.
+    #[skip_emit]
+    #[skip_control]
+    master_level: UnipolarFloat,
 }
 
 impl Default for RugDoctor {
@@ -28,6 +33,7 @@ impl Default for RugDoctor {
             speed: Unipolar::new("Speed", ()).with_channel_knob(0),
             size: Unipolar::new("Size", ()).with_channel_knob(1),
             preset: IndexedSelect::new("Preset", 6, false, ()),
+            master_level: UnipolarFloat::ONE,
         }
     }
 }
@@ -51,11 +57,28 @@ impl AnimatedFixture for RugDoctor {
     }
 }
 
-impl ControllableFixture for RugDoctor {}
+impl ControllableFixture for RugDoctor {
+    fn master_controls_changed(
+        &mut self,
+        master_controls: &MasterControls,
+        emitter: &FixtureStateEmitter,
+    ) {
+        let master_level = master_controls.master_level();
+        if master_level.val() == self.master_level.val() {
+            return;
+        }
+        self.master_level = master_level;
+        self.update_level(emitter);
+    }
+}
 
 impl RugDoctor {
     fn set_level(&self, state: &mut State) {
-        let level = unipolar_to_range(0, 255, self.level.control.val());
+        let level = unipolar_to_range(
+            0,
+            255,
+            UnipolarFloat::new(self.level.control.val().val() * self.master_level.val()),
+        );
         if level == 0 {
             state.on = Some(false);
         } else {
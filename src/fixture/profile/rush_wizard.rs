@@ -27,7 +27,9 @@ pub struct RushWizard {
 impl Default for RushWizard {
     fn default() -> Self {
         Self {
-            dimmer: Unipolar::full_channel("Dimmer", 1).with_channel_level(),
+            dimmer: Unipolar::full_channel("Dimmer", 1)
+                .with_master()
+                .with_channel_level(),
             strobe: Strobe::channel("Strobe", 0, 16, 131, 8),
             color: LabeledSelect::new(
                 "Color",
@@ -66,7 +66,7 @@ impl AnimatedFixture for SolarSystem {
             animation_vals.filter(&AnimationTarget::RearRotation),
             dmx_buf,
         );
-        dmx_buf[6] = if !self.shutter_open.control.val() {
+        dmx_buf[6] = if !self.shutter_open.control.val() || group_controls.blacked_out() {
             0
         } else if self.auto_shutter.val() {
             38
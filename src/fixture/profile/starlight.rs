@@ -14,7 +14,9 @@ pub struct Starlight {
 impl Default for Starlight {
     fn default() -> Self {
         Self {
-            dimmer: Unipolar::full_channel("Dimmer", 1).with_channel_level(),
+            dimmer: Unipolar::full_channel("Dimmer", 1)
+                .with_master()
+                .with_channel_level(),
             strobe: Strobe::channel("Strobe", 2, 10, 255, 0),
             rotation: Bipolar::split_channel("Rotation", 3, 127, 1, 128, 255, 0)
                 .with_detent()
@@ -40,8 +42,11 @@ impl AnimatedFixture for Starlight {
         dmx_buf: &mut [u8],
     ) {
         dmx_buf[0] = 255; // DMX mode
-        self.dimmer
-            .render(animation_vals.filter(&AnimationTarget::Dimmer), dmx_buf);
+        self.dimmer.render_with_group(
+            group_controls,
+            animation_vals.filter(&AnimationTarget::Dimmer),
+            dmx_buf,
+        );
         self.strobe
             .render_with_group(group_controls, std::iter::empty(), dmx_buf);
         self.rotation.render_with_group(
@@ -3,6 +3,7 @@
 use std::time::Duration;
 
 use crate::fixture::prelude::*;
+use crate::osc::{ControlKind, EmitScopedOscMessage};
 
 /// Control abstraction for the RA venus.
 /// DMX profile Venus
@@ -66,13 +67,21 @@ impl Venus {
         Self::emit(sc, emitter);
     }
 
-    fn emit(_sc: StateChange, _emitter: &FixtureStateEmitter) {
-        // FIXME: no talkback
+    fn emit(sc: StateChange, emitter: &FixtureStateEmitter) {
+        use ControlKind::*;
+        use StateChange::*;
+        match sc {
+            BaseRotation(v) => emitter.emit_float_with_kind("BaseRotation", v.val(), Bipolar),
+            CradleMotion(v) => emitter.emit_float_with_kind("CradleMotion", v.val(), Unipolar),
+            HeadRotation(v) => emitter.emit_float_with_kind("HeadRotation", v.val(), Bipolar),
+            ColorRotation(v) => emitter.emit_float_with_kind("ColorRotation", v.val(), Bipolar),
+            LampOn(v) => LAMP_ON.send(v, emitter),
+        }
     }
 }
 
 impl NonAnimatedFixture for Venus {
-    fn render(&self, _group_controls: &FixtureGroupControls, dmx_buf: &mut [u8]) {
+    fn render(&self, group_controls: &FixtureGroupControls, dmx_buf: &mut [u8]) {
         render_bipolar_to_dir_and_val(self.base_rotation.current(), &mut dmx_buf[0..2]);
         dmx_buf[2] = unipolar_to_range(0, 255, self.cradle_motion.current());
         render_bipolar_to_dir_and_val(self.head_rotation.current(), &mut dmx_buf[3..5]);
@@ -82,7 +91,11 @@ impl NonAnimatedFixture for Venus {
             self.color_rotation.current() * color_wheel_scale,
             &mut dmx_buf[5..7],
         );
-        dmx_buf[7] = if self.lamp_on { 255 } else { 0 };
+        dmx_buf[7] = if self.lamp_on && !group_controls.blacked_out() {
+            255
+        } else {
+            0
+        };
     }
 }
 
@@ -103,9 +116,10 @@ impl crate::fixture::Control for Venus {
         msg: &OscControlMessage,
         emitter: &FixtureStateEmitter,
     ) -> anyhow::Result<bool> {
-        let Some((ctl, _)) = self.controls.handle(msg)? else {
+        let Some((ctl, talkback)) = self.controls.handle(msg)? else {
             return Ok(true);
         };
+        emitter.set_talkback(talkback);
         self.handle_state_change(ctl, emitter);
         Ok(true)
     }
@@ -26,7 +26,7 @@ impl Default for WizardExtreme {
     fn default() -> Self {
         Self {
             shutter: ShutterStrobe::new(
-                Unipolar::channel("Dimmer", 0, 0, 129),
+                Unipolar::channel("Dimmer", 0, 0, 129).with_master(),
                 Strobe::channel("Strobe", 0, 189, 130, 0),
             )
             .with_channel_level(),
@@ -60,7 +60,9 @@ impl Default for Wizlet {
             .with_mirroring(true)
             .with_channel_knob(2),
             strobe: Strobe::channel("Strobe", 4, 64, 95, 32),
-            dimmer: Unipolar::full_channel("Dimmer", 5).with_channel_level(),
+            dimmer: Unipolar::full_channel("Dimmer", 5)
+                .with_master()
+                .with_channel_level(),
         }
     }
 }
@@ -0,0 +1,300 @@
+//! Serve show state and accept control changes over a local HTTP/JSON API.
+//!
+//! Endpoints:
+//! - GET /patch: every fixture group, with the address and universe of each fixture
+//! - GET /groups: the current control and animation state of every fixture group
+//! - GET /channels: the fixture group assigned to each channel, the current channel,
+//!   and the visible bank of channels
+//! - GET /master: the current master control values
+//! - POST /control: apply a control change, given as {"addr": ..., "value": ...}
+//!
+//! Control changes are handled exactly like OSC control messages, using the
+//! same address. The response lists the talkback the change produced.
+//!
+//! The server runs in its own thread, and passes each request to the show as
+//! a control message along with a channel for the response.
+use std::{
+    io::Read,
+    net::SocketAddr,
+    sync::mpsc::{channel, Sender},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use log::{error, info};
+use rosc::{OscMessage, OscType};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{
+    channel::Channels,
+    control::ControlMessage,
+    fixture::Patch,
+    osc::{OscClientId, OscControlMessage},
+};
+
+/// How long to wait for the show to handle a request.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A request from the HTTP API, passed to the show for handling.
+pub struct HttpRequest {
+    pub query: Query,
+    reply: Sender<Result<Value, String>>,
+}
+
+pub enum Query {
+    Patch,
+    Groups,
+    Channels,
+    Master,
+    Control(OscControlMessage),
+    /// The OSCQuery namespace.
+    Namespace,
+}
+
+impl HttpRequest {
+    /// Send the result of handling this request back to the HTTP client.
+    pub fn reply(&self, result: &Result<Value>) {
+        let result = match result {
+            Ok(value) => Ok(value.clone()),
+            Err(err) => Err(format!("{err:#}")),
+        };
+        // The client may have given up waiting; nothing to do about it.
+        let _ = self.reply.send(result);
+    }
+}
+
+/// Start serving the HTTP API on the provided address.
+pub fn start_server(addr: SocketAddr, send: Sender<ControlMessage>) -> Result<()> {
+    let server =
+        Server::http(addr).map_err(|err| anyhow!("starting HTTP server on {addr}: {err}"))?;
+    info!("Serving HTTP API on {addr}.");
+    thread::spawn(move || {
+        for mut request in server.incoming_requests() {
+            let result = handle(&mut request, &send);
+            respond(request, result);
+        }
+        info!("HTTP server shutting down.");
+    });
+    Ok(())
+}
+
+/// Respond to a request with a JSON body, or a JSON error.
+pub fn respond(request: Request, result: Result<Value, (u16, String)>) {
+    let (status, body) = match result {
+        Ok(value) => (200, value),
+        Err((status, err)) => (status, json!({ "error": err })),
+    };
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(
+            Header::from_bytes("Content-Type", "application/json").expect("static header is valid"),
+        );
+    if let Err(err) = request.respond(response) {
+        error!("HTTP response error: {err}.");
+    }
+}
+
+/// Handle a single request, returning the response body or an error status.
+fn handle(request: &mut Request, send: &Sender<ControlMessage>) -> Result<Value, (u16, String)> {
+    let query = match (request.method(), request.url()) {
+        (Method::Get, "/patch") => Query::Patch,
+        (Method::Get, "/groups") => Query::Groups,
+        (Method::Get, "/channels") => Query::Channels,
+        (Method::Get, "/master") => Query::Master,
+        (Method::Post, "/control") => {
+            // Every request connects from a new port, so HTTP clients share
+            // a single surface rather than each getting their own selection.
+            let client_id = OscClientId::internal();
+            let mut body = String::new();
+            request
+                .as_reader()
+                .read_to_string(&mut body)
+                .map_err(|err| (400, err.to_string()))?;
+            Query::Control(
+                parse_control(&body, client_id).map_err(|err| (400, format!("{err:#}")))?,
+            )
+        }
+        (method, url) => return Err((404, format!("no endpoint for {method} {url}"))),
+    };
+    query_show(send, query)
+}
+
+/// Pass a query to the show and wait for the result.
+pub fn query_show(send: &Sender<ControlMessage>, query: Query) -> Result<Value, (u16, String)> {
+    let (reply, recv) = channel();
+    send.send(ControlMessage::Http(HttpRequest { query, reply }))
+        .map_err(|_| (503, "show is not running".to_string()))?;
+    match recv.recv_timeout(REPLY_TIMEOUT) {
+        Ok(result) => result.map_err(|err| (400, err)),
+        Err(_) => Err((503, "timed out waiting for the show".to_string())),
+    }
+}
+
+/// A control change posted to the API.
+#[derive(Deserialize)]
+struct ControlChange {
+    addr: String,
+    value: Value,
+}
+
+/// Parse a posted control change into an OSC control message.
+pub fn parse_control(body: &str, client_id: OscClientId) -> Result<OscControlMessage> {
+    let change: ControlChange = serde_json::from_str(body).context("parsing control change")?;
+    let arg = match change.value {
+        Value::Number(n) => OscType::Float(n.as_f64().context("invalid number")? as f32),
+        Value::Bool(b) => OscType::Bool(b),
+        Value::String(s) => OscType::String(s),
+        other => bail!("unsupported control value {other}"),
+    };
+    Ok(OscControlMessage::new(
+        OscMessage {
+            addr: change.addr,
+            args: vec![arg],
+        },
+        client_id,
+    )?)
+}
+
+/// Convert OSC talkback into JSON.
+pub fn talkback_json(msgs: Vec<OscMessage>) -> Value {
+    let talkback: Vec<_> = msgs.iter().map(osc_json).collect();
+    json!({ "talkback": talkback })
+}
+
+/// Convert an OSC message into JSON.
+pub fn osc_json(msg: &OscMessage) -> Value {
+    json!({
+        "addr": msg.addr,
+        "args": msg.args.iter().map(osc_arg_json).collect::<Vec<_>>(),
+    })
+}
+
+pub fn osc_arg_json(arg: &OscType) -> Value {
+    match arg {
+        OscType::Float(v) => json!(v),
+        OscType::Double(v) => json!(v),
+        OscType::Int(v) => json!(v),
+        OscType::Long(v) => json!(v),
+        OscType::Bool(v) => json!(v),
+        OscType::String(v) => json!(v),
+        OscType::Nil => Value::Null,
+        other => json!(format!("{other:?}")),
+    }
+}
+
+/// A patched fixture group.
+#[derive(Serialize)]
+struct PatchedGroup<'a> {
+    fixture: &'a str,
+    group: Option<&'a str>,
+    channel: Option<usize>,
+    fixtures: Vec<PatchedFixture>,
+}
+
+/// A single patched fixture.
+#[derive(Serialize)]
+struct PatchedFixture {
+    universe: usize,
+    /// The DMX address of the fixture, if it renders to DMX.
+    addr: Option<usize>,
+    mirror: bool,
+}
+
+/// Describe every patched fixture group.
+pub fn patch_json(patch: &Patch, channels: &Channels) -> Result<Value> {
+    let groups: Vec<_> = patch
+        .iter()
+        .map(|group| PatchedGroup {
+            fixture: &group.key().fixture,
+            group: group.key().group.as_deref(),
+            channel: channels
+                .channel_for_fixture(group.key())
+                .map(|channel| channel.inner()),
+            fixtures: group
+                .fixture_configs()
+                .iter()
+                .map(|cfg| PatchedFixture {
+                    universe: cfg.universe,
+                    addr: cfg.dmx_addr.map(|addr| addr + 1),
+                    mirror: cfg.mirror,
+                })
+                .collect(),
+        })
+        .collect();
+    Ok(serde_json::to_value(groups)?)
+}
+
+/// A fixture group assigned to a channel.
+#[derive(Serialize)]
+struct AssignedChannel<'a> {
+    channel: usize,
+    fixture: &'a str,
+    group: Option<&'a str>,
+}
+
+/// Describe the fixture group assigned to each channel.
+pub fn channels_json(channels: &Channels, patch: &Patch) -> Result<Value> {
+    let assigned = channels
+        .channel_ids()
+        .map(|channel| {
+            let group = channels.group_by_channel(patch, channel)?;
+            Ok(AssignedChannel {
+                channel: channel.inner(),
+                fixture: &group.key().fixture,
+                group: group.key().group.as_deref(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(json!({
+        "current": channels.current_channel().map(|channel| channel.inner()),
+        "bank": channels.bank(),
+        "channels": assigned,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_control() {
+        let msg = parse_control(
+            r#"{"addr": "/:left/Comet/Shutter", "value": 0.5}"#,
+            OscClientId::internal(),
+        )
+        .unwrap();
+        assert_eq!("Comet", msg.entity_type());
+        assert_eq!("Shutter", msg.control());
+        assert_eq!(Some("left"), msg.group());
+        assert_eq!(OscType::Float(0.5), msg.arg);
+
+        let msg = parse_control(
+            r#"{"addr": "/Master/Strobe", "value": true}"#,
+            OscClientId::internal(),
+        )
+        .unwrap();
+        assert_eq!(OscType::Bool(true), msg.arg);
+
+        assert!(parse_control(
+            r#"{"addr": "/Master/Strobe", "value": [1]}"#,
+            OscClientId::internal()
+        )
+        .is_err());
+        assert!(parse_control(r#"{"addr": "nope", "value": 1}"#, OscClientId::internal()).is_err());
+    }
+
+    #[test]
+    fn test_talkback_json() {
+        let talkback = talkback_json(vec![OscMessage {
+            addr: "/Master/Strobe".to_string(),
+            args: vec![OscType::Float(1.0), OscType::String("on".to_string())],
+        }]);
+        assert_eq!(
+            json!({"talkback": [{"addr": "/Master/Strobe", "args": [1.0, "on"]}]}),
+            talkback
+        );
+    }
+}
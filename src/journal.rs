@@ -0,0 +1,441 @@
+//! Journal every incoming control message, so a session can be replayed.
+//!
+//! A journal starts with a header describing the show at the moment the
+//! journal was started, serialized as YAML: where its clocks came from, its
+//! patch, and its state. It is followed by one entry for each control message
+//! and each patch reload: the number of show updates that had been computed
+//! when the message was handled, the time in microseconds since the start of
+//! the journal, the kind of message, and the message itself. Replaying uses
+//! the update count rather than the timestamp, so a replay handles every
+//! message between exactly the same updates as the original session.
+//! Reloaded patches are journaled in full, so a replay doesn't depend on the
+//! config file as it is now.
+//!
+//! Clock state isn't journaled, so only a show running internal clocks with
+//! no audio input or MIDI clock can be replayed exactly.
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    net::SocketAddr,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use log::info;
+use rosc::{OscMessage, OscPacket};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tunnels::midi::{Event, EventType, Mapping};
+
+use crate::{
+    config::FixtureGroupConfig,
+    control::ControlMessage,
+    http::Query,
+    midi::{Device, MidiControlMessage},
+    osc::{OscClientId, OscControlMessage},
+    persist::ShowState,
+};
+
+const MAGIC: &[u8; 8] = b"COMETJNL";
+const VERSION: u8 = 2;
+
+const OSC: u8 = 0;
+const MIDI: u8 = 1;
+const CLIENT_EXPIRED: u8 = 2;
+const PATCH: u8 = 3;
+
+/// Where the clocks of a journaled show came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClockSource {
+    /// Internal clocks driven only by control messages.
+    Internal,
+    /// Internal clocks driven by an audio input.
+    Audio,
+    /// Internal clocks slaved to MIDI clock.
+    MidiClock,
+    /// An external clock service.
+    Service,
+}
+
+impl Display for ClockSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Internal => "internal clocks",
+            Self::Audio => "audio input",
+            Self::MidiClock => "MIDI clock",
+            Self::Service => "a clock service",
+        })
+    }
+}
+
+/// The show as it was when a journal was started.
+#[derive(Serialize, Deserialize)]
+pub struct JournalHeader {
+    pub clock_source: ClockSource,
+    /// The fixture groups the show was patched with.
+    pub fixtures: Vec<FixtureGroupConfig>,
+    /// The MIDI devices the show was sending to, whose pickup state follows
+    /// the show.
+    #[serde(with = "midi_devices")]
+    pub midi_devices: Vec<Device>,
+    pub state: ShowState,
+}
+
+/// Serialize MIDI devices as their name and channel offset.
+mod midi_devices {
+    use super::{Deserialize, Deserializer, Device, Serializer};
+
+    pub fn serialize<S: Serializer>(devices: &[Device], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(
+            devices
+                .iter()
+                .map(|device| (device.to_string(), device.channel_offset())),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<Device>, D::Error> {
+        let devices: Vec<(String, usize)> = Deserialize::deserialize(d)?;
+        Ok(devices
+            .iter()
+            .map(|(name, channel_offset)| Device::from_name(name, *channel_offset))
+            .collect())
+    }
+}
+
+/// Write control messages to a journal.
+pub struct JournalWriter {
+    file: BufWriter<File>,
+    start: Instant,
+}
+
+impl JournalWriter {
+    /// Create a journal starting from the provided show.
+    pub fn create(path: &Path, header: &JournalHeader) -> Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("creating journal {}", path.display()))?;
+        let mut file = BufWriter::new(file);
+        file.write_all(MAGIC)?;
+        file.write_all(&[VERSION])?;
+        write_bytes(&mut file, serde_yaml::to_string(header)?.as_bytes())?;
+        // Make sure the header is on disk even if the show never handles a
+        // control message.
+        file.flush()?;
+        info!("Journaling control messages to {}.", path.display());
+        Ok(Self {
+            file,
+            start: Instant::now(),
+        })
+    }
+
+    /// Write a control message to the journal, handled after the provided
+    /// number of show updates.
+    pub fn write(&mut self, update_count: u64, msg: &ControlMessage) -> Result<()> {
+        let (kind, payload) = match msg {
+            ControlMessage::Osc(msg) => (OSC, encode_osc(msg)?),
+            ControlMessage::Midi(msg) => (MIDI, encode_midi(msg)),
+            ControlMessage::Wled(msg) => match *msg {},
+            // Registering a client only refreshes its UI.
+            ControlMessage::OscClientRegistered(_) => return Ok(()),
+            // Expiring a client drops its selections, which later messages
+            // depend on.
+            ControlMessage::OscClientExpired(client_id) => {
+                (CLIENT_EXPIRED, client_id.addr().to_string().into_bytes())
+            }
+            // HTTP control changes are handled as OSC, so journal them as OSC.
+            // Other HTTP requests don't change the show.
+            ControlMessage::Http(req) => match &req.query {
+                Query::Control(msg) => (OSC, encode_osc(msg)?),
+                _ => return Ok(()),
+            },
+        };
+        self.write_entry(update_count, kind, &payload)
+    }
+
+    /// Write a reloaded patch to the journal, applied after the provided
+    /// number of show updates.
+    pub fn write_patch(
+        &mut self,
+        update_count: u64,
+        fixtures: &[FixtureGroupConfig],
+    ) -> Result<()> {
+        self.write_entry(
+            update_count,
+            PATCH,
+            serde_yaml::to_string(fixtures)?.as_bytes(),
+        )
+    }
+
+    fn write_entry(&mut self, update_count: u64, kind: u8, payload: &[u8]) -> Result<()> {
+        let timestamp = self.start.elapsed().as_micros() as u64;
+        self.file.write_all(&update_count.to_le_bytes())?;
+        self.file.write_all(&timestamp.to_le_bytes())?;
+        self.file.write_all(&[kind])?;
+        write_bytes(&mut self.file, payload)?;
+        // Flush every message, so the journal leading up to a crash survives.
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+/// A single entry read from a journal.
+pub struct JournalEntry {
+    /// The number of show updates computed before this message was handled.
+    pub update_count: u64,
+    /// The time since the start of the journal.
+    pub elapsed: Duration,
+    pub msg: JournalMessage,
+}
+
+pub enum JournalMessage {
+    Control(ControlMessage),
+    /// The show was repatched with these fixture groups.
+    Patch(Vec<FixtureGroupConfig>),
+}
+
+/// Read control messages back from a journal.
+pub struct JournalReader {
+    file: BufReader<File>,
+    header: JournalHeader,
+}
+
+impl JournalReader {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("opening journal {}", path.display()))?;
+        let mut file = BufReader::new(file);
+        let mut header = [0; MAGIC.len() + 1];
+        file.read_exact(&mut header)
+            .context("reading journal header")?;
+        ensure!(
+            &header[..MAGIC.len()] == MAGIC,
+            "{} is not a control journal",
+            path.display()
+        );
+        ensure!(
+            header[MAGIC.len()] == VERSION,
+            "unsupported journal version {}",
+            header[MAGIC.len()]
+        );
+        let header =
+            serde_yaml::from_slice(&read_bytes(&mut file)?).context("parsing journal header")?;
+        Ok(Self { file, header })
+    }
+
+    /// The show at the start of the journal.
+    pub fn header(&self) -> &JournalHeader {
+        &self.header
+    }
+
+    /// Read the next entry, or None at the end of the journal.
+    pub fn next_entry(&mut self) -> Result<Option<JournalEntry>> {
+        let mut update_count = [0; 8];
+        match self.file.read_exact(&mut update_count) {
+            Ok(()) => (),
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
+        let mut timestamp = [0; 8];
+        self.file.read_exact(&mut timestamp)?;
+        let mut kind = [0];
+        self.file.read_exact(&mut kind)?;
+        let payload = read_bytes(&mut self.file)?;
+        let msg = match kind[0] {
+            OSC => JournalMessage::Control(ControlMessage::Osc(decode_osc(&payload)?)),
+            MIDI => JournalMessage::Control(ControlMessage::Midi(decode_midi(&payload)?)),
+            CLIENT_EXPIRED => JournalMessage::Control(ControlMessage::OscClientExpired(
+                OscClientId::new(String::from_utf8(payload)?.parse()?),
+            )),
+            PATCH => JournalMessage::Patch(
+                serde_yaml::from_slice(&payload).context("parsing journaled patch")?,
+            ),
+            other => bail!("unknown journal entry kind {other}"),
+        };
+        Ok(Some(JournalEntry {
+            update_count: u64::from_le_bytes(update_count),
+            elapsed: Duration::from_micros(u64::from_le_bytes(timestamp)),
+            msg,
+        }))
+    }
+}
+
+/// Encode an OSC message as its client address followed by the OSC packet.
+fn encode_osc(msg: &OscControlMessage) -> Result<Vec<u8>> {
+    let mut payload = Vec::new();
+    write_bytes(&mut payload, msg.client_id.addr().to_string().as_bytes())?;
+    payload.extend(rosc::encoder::encode(&OscPacket::Message(OscMessage {
+        addr: msg.addr().to_string(),
+        args: vec![msg.arg.clone()],
+    }))?);
+    Ok(payload)
+}
+
+fn decode_osc(mut payload: &[u8]) -> Result<OscControlMessage> {
+    let client_addr: SocketAddr = String::from_utf8(read_bytes(&mut payload)?)?.parse()?;
+    let (_, packet) = rosc::decoder::decode_udp(payload)?;
+    let OscPacket::Message(msg) = packet else {
+        bail!("journaled OSC packet is not a single message");
+    };
+    Ok(OscControlMessage::new(msg, OscClientId::new(client_addr))?)
+}
+
+/// Encode a MIDI message as the device model and channel offset, followed by
+/// the raw event.
+fn encode_midi(msg: &MidiControlMessage) -> Vec<u8> {
+    let mut payload = Vec::new();
+    // Writing to a Vec can't fail.
+    write_bytes(&mut payload, msg.device.to_string().as_bytes()).unwrap();
+    payload.extend((msg.device.channel_offset() as u16).to_le_bytes());
+    let mapping = &msg.event.mapping;
+    let event_type = match mapping.event_type {
+        EventType::NoteOn => 0,
+        EventType::NoteOff => 1,
+        EventType::ControlChange => 2,
+    };
+    payload.extend([
+        event_type,
+        mapping.channel,
+        mapping.control,
+        msg.event.value,
+    ]);
+    payload
+}
+
+fn decode_midi(mut payload: &[u8]) -> Result<MidiControlMessage> {
+    let model = String::from_utf8(read_bytes(&mut payload)?)?;
+    let mut fields = [0; 6];
+    payload.read_exact(&mut fields)?;
+    let [offset_lo, offset_hi, event_type, channel, control, value] = fields;
+    let event_type = match event_type {
+        0 => EventType::NoteOn,
+        1 => EventType::NoteOff,
+        2 => EventType::ControlChange,
+        other => bail!("unknown MIDI event type {other}"),
+    };
+    Ok(MidiControlMessage {
+        device: Device::from_name(&model, u16::from_le_bytes([offset_lo, offset_hi]) as usize),
+        event: Event {
+            mapping: Mapping {
+                event_type,
+                channel,
+                control,
+            },
+            value,
+        },
+    })
+}
+
+/// Write a length-prefixed byte string.
+fn write_bytes(w: &mut impl Write, bytes: &[u8]) -> Result<()> {
+    w.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    w.write_all(bytes)?;
+    Ok(())
+}
+
+/// Read a length-prefixed byte string.
+fn read_bytes(r: &mut impl Read) -> Result<Vec<u8>> {
+    let mut len = [0; 4];
+    r.read_exact(&mut len)?;
+    let mut bytes = vec![0; u32::from_le_bytes(len) as usize];
+    r.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rosc::OscType;
+
+    #[test]
+    fn test_journal_round_trip() {
+        let path = std::env::temp_dir().join(format!("comet-{}.journal", std::process::id()));
+        let client = OscClientId::new(SocketAddr::from(([192, 168, 1, 10], 9000)));
+        let osc = OscControlMessage::new(
+            OscMessage {
+                addr: "/:left/Comet/Shutter".to_string(),
+                args: vec![OscType::Float(0.5)],
+            },
+            client,
+        )
+        .unwrap();
+        let device = Device::from_model("Launch Control XL", 8).unwrap();
+        let event = Event {
+            mapping: Mapping {
+                event_type: EventType::ControlChange,
+                channel: 3,
+                control: 77,
+            },
+            value: 64,
+        };
+
+        let header = JournalHeader {
+            clock_source: ClockSource::Internal,
+            fixtures: Vec::new(),
+            midi_devices: vec![device],
+            state: ShowState::default(),
+        };
+        let mut journal = JournalWriter::create(&path, &header).unwrap();
+        journal.write(0, &ControlMessage::Osc(osc)).unwrap();
+        journal
+            .write(
+                12,
+                &ControlMessage::Midi(MidiControlMessage { device, event }),
+            )
+            .unwrap();
+        journal
+            .write(20, &ControlMessage::OscClientExpired(client))
+            .unwrap();
+        let fixtures: Vec<FixtureGroupConfig> =
+            serde_yaml::from_str("- name: Comet\n  addr: 1\n  channel: true\n").unwrap();
+        journal.write_patch(30, &fixtures).unwrap();
+        drop(journal);
+
+        let mut journal = JournalReader::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(ClockSource::Internal, journal.header().clock_source);
+        assert_eq!(vec![device], journal.header().midi_devices);
+        assert!(journal.header().state.groups.is_empty());
+
+        let entry = journal.next_entry().unwrap().unwrap();
+        assert_eq!(0, entry.update_count);
+        let JournalMessage::Control(ControlMessage::Osc(msg)) = entry.msg else {
+            panic!("expected an OSC message");
+        };
+        assert_eq!(client, msg.client_id);
+        assert_eq!("/:left/Comet/Shutter", msg.addr());
+        assert_eq!(Some("left"), msg.group());
+        assert_eq!(OscType::Float(0.5), msg.arg);
+
+        let entry = journal.next_entry().unwrap().unwrap();
+        assert_eq!(12, entry.update_count);
+        let JournalMessage::Control(ControlMessage::Midi(msg)) = entry.msg else {
+            panic!("expected a MIDI message");
+        };
+        assert_eq!(device, msg.device);
+        assert!(matches!(
+            msg.event.mapping.event_type,
+            EventType::ControlChange
+        ));
+        assert_eq!(3, msg.event.mapping.channel);
+        assert_eq!(77, msg.event.mapping.control);
+        assert_eq!(64, msg.event.value);
+
+        let entry = journal.next_entry().unwrap().unwrap();
+        assert_eq!(20, entry.update_count);
+        assert!(matches!(
+            entry.msg,
+            JournalMessage::Control(ControlMessage::OscClientExpired(expired)) if expired == client
+        ));
+
+        let entry = journal.next_entry().unwrap().unwrap();
+        assert_eq!(30, entry.update_count);
+        let JournalMessage::Patch(fixtures) = entry.msg else {
+            panic!("expected a patch");
+        };
+        assert_eq!(1, fixtures.len());
+        assert_eq!("Comet", fixtures[0].name);
+        assert!(fixtures[0].channel);
+
+        assert!(journal.next_entry().unwrap().is_none());
+    }
+}
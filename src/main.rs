@@ -1,45 +1,94 @@
-use anyhow::bail;
-use clock_service::prompt_start_clock_service;
+use anyhow::{anyhow, bail, ensure};
+use artnet::{ArtNetConfig, ArtNetOutput};
+use clock_service::{prompt_start_clock_service, start_clock_service};
+use config::ClockConfig;
+use dmx::{open_port, DmxOutput, UniverseIdx};
+use journal::JournalReader;
 use local_ip_address::local_ip;
 use log::info;
 use log::LevelFilter;
 use midi::Device;
+use midi_clock::{MidiClock, MidiClockConfig};
 use number::UnipolarFloat;
 use osc::prompt_osc_config;
 use osc::GroupControlMap;
+use recording::Player;
 use rust_dmx::select_port;
-use show::Clocks;
+use sacn::SacnOutput;
+use show::{build_patch, Clocks, UPDATE_INTERVAL};
 use simplelog::{Config as LogConfig, SimpleLogger};
 use std::env;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use tunnels::audio::prompt_audio;
 use tunnels::audio::AudioInput;
 use tunnels::clock_bank::ClockBank;
 use tunnels::midi::list_ports;
 use tunnels::midi::prompt_midi;
+use virtual_dmx::{VirtualDmx, VirtualDmxConfig};
 use zmq::Context;
 
 use crate::config::Config;
 use crate::show::Show;
 
 mod animation;
+mod artnet;
 mod channel;
 mod clock_service;
 mod config;
 mod control;
+mod cue;
 mod dmx;
 mod fixture;
+mod http;
+mod journal;
 mod master;
+mod merge;
 mod midi;
+mod midi_clock;
 mod osc;
+mod oscquery;
+mod persist;
+mod preset;
+mod recording;
+mod sacn;
 mod show;
+mod touchosc;
 mod util;
+mod virtual_dmx;
+mod websocket;
 mod wled;
 
 fn main() -> anyhow::Result<()> {
-    let config_path = env::args()
-        .nth(1)
-        .expect("Provide config path as first arg.");
+    let mut args = env::args().skip(1);
+    let config_path = args.next().expect("Provide config path as first arg.");
     let mut cfg = Config::load(&config_path)?;
+    let mut play = None;
+    let mut replay = None;
+    let mut touchosc = None;
+    let mut lint_touchosc = None;
+    let mut looping = false;
+    let mut speed = 1.0;
+    let mut seek = 0.0;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("{arg} requires a value"));
+        match arg.as_str() {
+            "--headless" => cfg.headless = true,
+            "--virtual-dmx" => cfg.virtual_dmx = Some(VirtualDmxConfig::parse(&value()?)),
+            "--record" => cfg.record_file = Some(PathBuf::from(value()?)),
+            "--journal" => cfg.journal_file = Some(PathBuf::from(value()?)),
+            "--replay" => replay = Some(PathBuf::from(value()?)),
+            "--touchosc" => touchosc = Some(PathBuf::from(value()?)),
+            "--lint-touchosc" => lint_touchosc = Some(PathBuf::from(value()?)),
+            "--play" => play = Some(PathBuf::from(value()?)),
+            "--loop" => looping = true,
+            "--speed" => speed = value()?.parse()?,
+            "--seek" => seek = value()?.parse()?,
+            other => bail!("unknown argument \"{other}\""),
+        }
+    }
     let log_level = if cfg.debug {
         LevelFilter::Debug
     } else {
@@ -47,18 +96,90 @@ fn main() -> anyhow::Result<()> {
     };
 
     SimpleLogger::init(log_level, LogConfig::default())?;
-    let clocks = if let Some(clock_service) = prompt_start_clock_service(Context::new())? {
+
+    // Write a TouchOSC layout for the patch, without running the show.
+    if let Some(path) = touchosc {
+        let (patch, _) = build_patch(cfg.fixtures)?;
+        touchosc::write_layout(&patch, &path)?;
+        return Ok(());
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_handle = stop.clone();
+    ctrlc::set_handler(move || stop_handle.store(true, Ordering::Relaxed))?;
+
+    // Play back a recording on the show's outputs, without running the show.
+    if let Some(path) = play {
+        let mut player = Player::open(&path)?;
+        player.set_looping(looping);
+        player.set_speed(speed)?;
+        player.seek(Duration::try_from_secs_f64(seek)?);
+        let mut dmx_ports = DmxOutputs::take(&mut cfg)?.open(player.universe_count())?;
+        player.run(&mut dmx_ports, UPDATE_INTERVAL, &stop);
+        return Ok(());
+    }
+
+    // Replay a control journal through the show, with no live control inputs.
+    // Only journals of shows running internal clocks with no audio input or
+    // MIDI clock can be replayed, so the replay runs the same. The show is
+    // patched as it was when the journal started.
+    if let Some(path) = replay {
+        let journal = JournalReader::open(&path)?;
+        disable_live_io(&mut cfg);
+        cfg.fixtures = journal.header().fixtures.clone();
+        let outputs = DmxOutputs::take(&mut cfg)?;
+        let mut show = Show::new(cfg, internal_clocks(None, None)?)?;
+        let mut dmx_ports = outputs.open(show.universe_count())?;
+        show.replay(journal, &mut dmx_ports, speed, &stop)?;
+        return Ok(());
+    }
+
+    // Check a TouchOSC layout against a show with no live inputs or outputs.
+    if let Some(path) = lint_touchosc {
+        disable_live_io(&mut cfg);
+        let show = Show::new(cfg, internal_clocks(None, None)?)?;
+        let report = touchosc::lint(&show, &path)?;
+        for (addr, err) in &report.unhandled {
+            println!("Unhandled: {addr}: {err}");
+        }
+        for addr in &report.unsent {
+            println!("Never sent: {addr}");
+        }
+        ensure!(
+            report.is_empty(),
+            "{} has {} unhandled and {} unsent addresses",
+            path.display(),
+            report.unhandled.len(),
+            report.unsent.len()
+        );
+        println!("{} matches the patch.", path.display());
+        return Ok(());
+    }
+
+    let clock_service = match &cfg.clock {
+        Some(ClockConfig::Service { provider }) => {
+            Some(start_clock_service(Context::new(), Some(provider))?)
+        }
+        Some(ClockConfig::Internal { .. }) => None,
+        None if cfg.headless => None,
+        None => prompt_start_clock_service(Context::new())?,
+    };
+    let clocks = if let Some(clock_service) = clock_service {
         Clocks::Service(clock_service)
     } else {
-        let audio_input = AudioInput::new(prompt_audio()?)?;
-        let clocks = ClockBank::default();
-        let mut audio_controls = GroupControlMap::default();
-        crate::osc::audio::map_controls(&mut audio_controls);
-        Clocks::Internal {
-            clocks,
-            audio_input,
-            audio_controls,
-        }
+        let audio_device = match &cfg.clock {
+            Some(ClockConfig::Internal {
+                audio_device: Some(device),
+                ..
+            }) => Some(device.clone()),
+            _ if cfg.headless => None,
+            _ => prompt_audio()?,
+        };
+        let midi_clock = match &cfg.clock {
+            Some(ClockConfig::Internal { midi_clock, .. }) => midi_clock.as_ref(),
+            _ => None,
+        };
+        internal_clocks(audio_device, midi_clock)?
     };
 
     match local_ip() {
@@ -66,28 +187,118 @@ fn main() -> anyhow::Result<()> {
         Err(e) => info!("Unable to fetch local IP address: {}.", e),
     }
 
-    if let Some(clients) = prompt_osc_config(cfg.receive_port)? {
-        cfg.controllers = clients;
+    if cfg.controllers.is_empty() && !cfg.headless {
+        if let Some(clients) = prompt_osc_config(cfg.receive_port)? {
+            cfg.controllers = clients;
+        }
     }
-    let (midi_inputs, midi_outputs) = list_ports()?;
-    cfg.midi_devices = prompt_midi(&midi_inputs, &midi_outputs, Device::all())?;
+    cfg.midi_devices = if let Some(midi) = &cfg.midi {
+        midi.iter()
+            .map(|d| d.device_spec())
+            .collect::<anyhow::Result<_>>()?
+    } else if cfg.headless {
+        Vec::new()
+    } else {
+        let (midi_inputs, midi_outputs) = list_ports()?;
+        prompt_midi(&midi_inputs, &midi_outputs, Device::all())?
+    };
     if cfg.controllers.is_empty() && cfg.midi_devices.is_empty() {
-        bail!("No OSC or midi clients were registered or manually configured.");
+        info!("No OSC or MIDI clients configured; OSC clients will be registered when they send a message.");
     }
 
+    let outputs = DmxOutputs::take(&mut cfg)?;
     let mut show = Show::new(cfg, clocks)?;
 
     let universe_count = show.universe_count();
     println!("This show requires {universe_count} universes.");
 
-    let mut dmx_ports = Vec::new();
+    let mut dmx_ports = outputs.open(universe_count)?;
+
+    show.run(&mut dmx_ports, &stop);
+
+    Ok(())
+}
+
+/// Configure the show to run without any live control inputs, network
+/// services, DMX input, or persisted state.
+fn disable_live_io(cfg: &mut Config) {
+    cfg.headless = true;
+    cfg.receive_port = 0;
+    cfg.controllers.clear();
+    cfg.wled_addr = None;
+    cfg.http_port = None;
+    cfg.websocket_port = None;
+    cfg.oscquery_port = None;
+    cfg.dmx_input = None;
+    cfg.state_file = None;
+    cfg.journal_file = None;
+}
+
+/// Run clocks internally, using the provided audio device if any.
+fn internal_clocks(
+    audio_device: Option<String>,
+    midi_clock: Option<&MidiClockConfig>,
+) -> anyhow::Result<Clocks> {
+    let audio_input = AudioInput::new(audio_device.clone())?;
+    let clocks = ClockBank::default();
+    let mut audio_controls = GroupControlMap::default();
+    crate::osc::audio::map_controls(&mut audio_controls);
+    Ok(Clocks::Internal {
+        clocks,
+        audio_input,
+        audio_device,
+        audio_controls,
+        midi_clock: midi_clock.map(MidiClock::start).transpose()?,
+    })
+}
+
+/// The configured DMX outputs, taken from the config before it is consumed.
+struct DmxOutputs {
+    dmx_port_names: Vec<String>,
+    artnet_configs: Vec<ArtNetConfig>,
+    virtual_dmx: Option<VirtualDmx>,
+    sacn_outputs: Vec<(UniverseIdx, SacnOutput)>,
+}
 
-    for i in 0..universe_count {
-        println!("Assign port to universe {i}:");
-        dmx_ports.push(select_port()?);
+impl DmxOutputs {
+    fn take(cfg: &mut Config) -> anyhow::Result<Self> {
+        let virtual_dmx = match cfg.virtual_dmx.take() {
+            Some(virtual_dmx) => Some(virtual_dmx.open()?),
+            None if cfg.headless => Some(VirtualDmxConfig::Memory.open()?),
+            None => None,
+        };
+        let sacn_outputs = match cfg.sacn.take() {
+            Some(sacn) => sacn.outputs()?,
+            None => Vec::new(),
+        };
+        Ok(Self {
+            dmx_port_names: std::mem::take(&mut cfg.dmx_ports),
+            artnet_configs: std::mem::take(&mut cfg.artnet),
+            virtual_dmx,
+            sacn_outputs,
+        })
     }
 
-    show.run(&mut dmx_ports);
+    /// Open an output for each universe, prompting for any that aren't configured.
+    fn open(mut self, universe_count: usize) -> anyhow::Result<Vec<Box<dyn DmxOutput>>> {
+        let mut dmx_ports: Vec<Box<dyn DmxOutput>> = Vec::new();
 
-    Ok(())
+        for i in 0..universe_count {
+            if let Some(index) = self.sacn_outputs.iter().position(|(u, _)| *u == i) {
+                dmx_ports.push(Box::new(self.sacn_outputs.swap_remove(index).1));
+            } else if let Some(artnet) = self.artnet_configs.iter().find(|a| a.universe == i) {
+                dmx_ports.push(Box::new(ArtNetOutput::new(artnet)?));
+            } else if let Some(name) = self.dmx_port_names.get(i) {
+                println!("Assigning port {name} to universe {i}.");
+                dmx_ports.push(Box::new(open_port(name)?));
+            } else if let Some(virtual_dmx) = &self.virtual_dmx {
+                println!("Assigning virtual output to universe {i}.");
+                dmx_ports.push(Box::new(virtual_dmx.output(i)));
+            } else {
+                println!("Assign port to universe {i}:");
+                dmx_ports.push(Box::new(select_port()?));
+            }
+        }
+        Ok(dmx_ports)
+    }
 }
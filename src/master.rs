@@ -3,6 +3,7 @@
 use std::time::Duration;
 
 use number::UnipolarFloat;
+use serde::Serialize;
 use tunnels::clock_server::StaticClockBank;
 
 use crate::fixture::prelude::*;
@@ -12,6 +13,8 @@ pub struct MasterControls {
     strobe_on: Bool<()>,
     strobe_rate: Unipolar<()>,
     use_master_rate: Bool<()>,
+    grand_master: Unipolar<()>,
+    blackout: Bool<()>,
     pub clock_state: StaticClockBank,
     pub audio_envelope: UnipolarFloat,
 }
@@ -22,6 +25,8 @@ impl MasterControls {
             strobe_on: Bool::new_off("StrobeOn", ()),
             strobe_rate: Unipolar::new("StrobeRate", ()),
             use_master_rate: Bool::new_off("UseMasterStrobeRate", ()),
+            grand_master: Unipolar::new("GrandMaster", ()).at_full(),
+            blackout: Bool::new_off("Blackout", ()),
             clock_state: Default::default(),
             audio_envelope: Default::default(),
         }
@@ -35,6 +40,16 @@ impl MasterControls {
         }
     }
 
+    /// Return the level that all fixture intensities should be scaled by.
+    /// This is zero while blackout is engaged, regardless of the grand master.
+    pub fn master_level(&self) -> UnipolarFloat {
+        if self.blackout.val() {
+            UnipolarFloat::ZERO
+        } else {
+            self.grand_master.val()
+        }
+    }
+
     pub fn update(&mut self, _delta_t: Duration) {}
 
     pub fn emit_state(&self, emitter: &dyn EmitControlMessage) {
@@ -50,10 +65,17 @@ impl MasterControls {
             .emit_state_with_callback(scoped_emitter, |v| {
                 emitter.emit_midi_master_message(&StateChange::StrobeRate(*v));
             });
-        self.strobe_on
+        self.use_master_rate
             .emit_state_with_callback(scoped_emitter, |v| {
                 emitter.emit_midi_master_message(&StateChange::UseMasterStrobeRate(*v));
             });
+        self.grand_master
+            .emit_state_with_callback(scoped_emitter, |v| {
+                emitter.emit_midi_master_message(&StateChange::GrandMaster(*v));
+            });
+        self.blackout.emit_state_with_callback(scoped_emitter, |v| {
+            emitter.emit_midi_master_message(&StateChange::Blackout(*v));
+        });
     }
 
     pub fn control(
@@ -66,19 +88,30 @@ impl MasterControls {
             emitter,
         };
 
-        match msg {
+        let sc = match msg {
+            ControlMessage::Set(sc) => sc.clone(),
+            ControlMessage::ToggleBlackout => StateChange::Blackout(!self.blackout.val()),
+        };
+
+        match sc {
             StateChange::StrobeOn(v) => {
-                self.strobe_on.control_direct(*v, scoped_emitter)?;
+                self.strobe_on.control_direct(v, scoped_emitter)?;
             }
             StateChange::StrobeRate(v) => {
-                self.strobe_rate.control_direct(*v, scoped_emitter)?;
+                self.strobe_rate.control_direct(v, scoped_emitter)?;
             }
             StateChange::UseMasterStrobeRate(v) => {
-                self.use_master_rate.control_direct(*v, scoped_emitter)?;
+                self.use_master_rate.control_direct(v, scoped_emitter)?;
+            }
+            StateChange::GrandMaster(v) => {
+                self.grand_master.control_direct(v, scoped_emitter)?;
+            }
+            StateChange::Blackout(v) => {
+                self.blackout.control_direct(v, scoped_emitter)?;
             }
         }
 
-        emitter.emit_midi_master_message(msg);
+        emitter.emit_midi_master_message(&sc);
         Ok(())
     }
 
@@ -105,17 +138,32 @@ impl MasterControls {
             ));
             return Ok(());
         }
+        if self.grand_master.control(msg, scoped_emitter)? {
+            emitter.emit_midi_master_message(&StateChange::GrandMaster(self.grand_master.val()));
+            return Ok(());
+        }
+        if self.blackout.control(msg, scoped_emitter)? {
+            emitter.emit_midi_master_message(&StateChange::Blackout(self.blackout.val()));
+            return Ok(());
+        }
         Ok(())
     }
 }
 
-pub type ControlMessage = StateChange;
-
 #[derive(Debug, Clone)]
+pub enum ControlMessage {
+    Set(StateChange),
+    /// Flip the blackout state; used by momentary hardware buttons.
+    ToggleBlackout,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub enum StateChange {
     StrobeOn(bool),
     StrobeRate(UnipolarFloat),
     UseMasterStrobeRate(bool),
+    GrandMaster(UnipolarFloat),
+    Blackout(bool),
 }
 
 #[derive(Debug, Default)]
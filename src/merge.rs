@@ -0,0 +1,389 @@
+//! Merge DMX received over the network into the show's rendered output.
+//!
+//! External sources such as a house console are received over Art-Net or
+//! sACN. Each configured address range is merged into a show universe using
+//! either highest-takes-precedence or latest-takes-precedence.
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, UdpSocket},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{ensure, Context, Result};
+use log::{error, info, warn};
+use serde::Deserialize;
+
+use crate::{
+    artnet::{parse_artdmx, ARTNET_PORT},
+    dmx::{DmxAddr, DmxBuffer, UniverseIdx},
+    fixture::Patch,
+    sacn::{multicast_addr, parse_data, SACN_PORT},
+};
+
+/// Configure network DMX input and how it merges with the show.
+#[derive(Clone, Debug, Deserialize)]
+pub struct DmxInputConfig {
+    /// Drop a source if nothing has been received from it for this many seconds.
+    #[serde(default = "default_timeout")]
+    pub timeout: f64,
+    pub ranges: Vec<MergeRangeConfig>,
+}
+
+/// A range of addresses in a show universe that an external source controls.
+/// Addresses in the input universe map to the same addresses in the show.
+#[derive(Clone, Debug, Deserialize)]
+pub struct MergeRangeConfig {
+    /// The show universe to merge into.
+    pub universe: UniverseIdx,
+    /// The network universe to receive.
+    pub source: InputUniverse,
+    pub start: DmxAddr,
+    pub count: usize,
+    #[serde(default)]
+    pub mode: MergeMode,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputUniverse {
+    /// An Art-Net port-address.
+    Artnet(u16),
+    /// An sACN universe number.
+    Sacn(u16),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeMode {
+    /// Highest takes precedence.
+    #[default]
+    Htp,
+    /// Latest takes precedence; whichever side changed an address most
+    /// recently controls it.
+    Ltp,
+}
+
+/// The most recent frame received from a single source.
+struct SourceFrame {
+    frame: DmxBuffer,
+    received: Instant,
+}
+
+/// Frames received from the network, by universe and then by source.
+type InputFrames = Arc<Mutex<HashMap<InputUniverse, HashMap<IpAddr, SourceFrame>>>>;
+
+pub struct DmxMerge {
+    ranges: Vec<MergeRange>,
+    inputs: InputFrames,
+    timeout: Duration,
+}
+
+impl DmxMerge {
+    /// Start listening for network DMX for all configured ranges.
+    pub fn start(cfg: &DmxInputConfig) -> Result<Self> {
+        ensure!(cfg.timeout > 0.0, "DMX input timeout must be positive");
+        let ranges = cfg
+            .ranges
+            .iter()
+            .map(MergeRange::new)
+            .collect::<Result<Vec<_>>>()?;
+        let inputs = InputFrames::default();
+
+        if ranges
+            .iter()
+            .any(|r| matches!(r.source, InputUniverse::Artnet(_)))
+        {
+            let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, ARTNET_PORT))
+                .context("binding Art-Net input socket")?;
+            info!("Listening for Art-Net on port {ARTNET_PORT}.");
+            receive(socket, inputs.clone(), |packet| {
+                let (port_address, data) = parse_artdmx(packet)?;
+                Some((InputUniverse::Artnet(port_address), false, data))
+            });
+        }
+
+        let sacn_universes: Vec<_> = ranges
+            .iter()
+            .filter_map(|r| match r.source {
+                InputUniverse::Sacn(u) => Some(u),
+                _ => None,
+            })
+            .collect();
+        if !sacn_universes.is_empty() {
+            let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, SACN_PORT))
+                .context("binding sACN input socket")?;
+            for universe in sacn_universes {
+                let group = multicast_addr(universe);
+                if let Err(err) = socket.join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED) {
+                    warn!("Unable to join sACN multicast group {group}: {err}.");
+                }
+            }
+            info!("Listening for sACN on port {SACN_PORT}.");
+            receive(socket, inputs.clone(), |packet| {
+                let packet = parse_data(packet)?;
+                Some((
+                    InputUniverse::Sacn(packet.universe),
+                    packet.terminated,
+                    packet.data,
+                ))
+            });
+        }
+
+        Ok(Self {
+            ranges,
+            inputs,
+            timeout: Duration::from_secs_f64(cfg.timeout),
+        })
+    }
+
+    /// Check the merge ranges against the patch.
+    /// Ranges must fall within the patched universes; any patched fixture that
+    /// shares an address with an external source is logged.
+    pub fn check_patch(&self, patch: &Patch) -> Result<()> {
+        for range in &self.ranges {
+            ensure!(
+                range.universe < patch.universe_count(),
+                "DMX input range targets universe {} but the patch only has {}",
+                range.universe,
+                patch.universe_count()
+            );
+            let mut overlapping = Vec::new();
+            for index in range.start..range.start + range.count {
+                if let Some(fixture) = patch.fixture_at(range.universe, index) {
+                    if !overlapping.contains(&fixture.name) {
+                        overlapping.push(fixture.name.clone());
+                    }
+                }
+            }
+            for name in overlapping {
+                warn!(
+                    "{name} in universe {} shares addresses with {:?}, merged using {:?}.",
+                    range.universe, range.source, range.mode
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Merge the rendered show universes with network input into out.
+    pub fn merge(&mut self, rendered: &[DmxBuffer], out: &mut [DmxBuffer]) {
+        out.copy_from_slice(rendered);
+        let inputs = self.live_inputs();
+        for range in &mut self.ranges {
+            let Some(show) = rendered.get(range.universe) else {
+                continue;
+            };
+            range.merge(show, inputs.get(&range.source), &mut out[range.universe]);
+        }
+    }
+
+    /// Drop stale sources, and combine the remaining sources for each
+    /// universe highest-takes-precedence.
+    fn live_inputs(&self) -> HashMap<InputUniverse, DmxBuffer> {
+        let Ok(mut inputs) = self.inputs.lock() else {
+            error!("Failed to get DMX input lock.");
+            return HashMap::new();
+        };
+        let mut live = HashMap::new();
+        for (universe, sources) in inputs.iter_mut() {
+            sources.retain(|source, frame| {
+                let stale = frame.received.elapsed() > self.timeout;
+                if stale {
+                    info!("Dropping stale DMX source {source} on {universe:?}.");
+                }
+                !stale
+            });
+            for source in sources.values() {
+                let combined = live.entry(*universe).or_insert([0; 512]);
+                for (c, v) in combined.iter_mut().zip(source.frame.iter()) {
+                    *c = (*c).max(*v);
+                }
+            }
+        }
+        live
+    }
+}
+
+/// Receive DMX packets on a socket in a new thread.
+/// The parser returns the universe, whether the source has terminated the
+/// stream, and the DMX data.
+fn receive(
+    socket: UdpSocket,
+    inputs: InputFrames,
+    parse: impl Fn(&[u8]) -> Option<(InputUniverse, bool, &[u8])> + Send + 'static,
+) {
+    std::thread::spawn(move || {
+        let mut buf = [0; 1024];
+        loop {
+            let (len, from) = match socket.recv_from(&mut buf) {
+                Ok(r) => r,
+                Err(err) => {
+                    error!("DMX input receive error: {err}.");
+                    continue;
+                }
+            };
+            let Some((universe, terminated, data)) = parse(&buf[..len]) else {
+                continue;
+            };
+            let Ok(mut inputs) = inputs.lock() else {
+                error!("Failed to get DMX input lock.");
+                continue;
+            };
+            let sources = inputs.entry(universe).or_default();
+            if terminated {
+                if sources.remove(&from.ip()).is_some() {
+                    info!("DMX source {} terminated {universe:?}.", from.ip());
+                }
+                continue;
+            }
+            let mut frame = [0; 512];
+            frame[..data.len()].copy_from_slice(data);
+            sources.insert(
+                from.ip(),
+                SourceFrame {
+                    frame,
+                    received: Instant::now(),
+                },
+            );
+        }
+    });
+}
+
+/// Which side most recently changed an address in an LTP range.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Owner {
+    Show,
+    Input,
+}
+
+struct MergeRange {
+    universe: UniverseIdx,
+    source: InputUniverse,
+    /// The DMX buffer index of the first address in the range.
+    start: usize,
+    count: usize,
+    mode: MergeMode,
+    /// LTP state; the values seen from each side last frame, and who owns
+    /// each address. The input is None while no source is live.
+    last_show: Vec<u8>,
+    last_input: Option<Vec<u8>>,
+    owner: Vec<Owner>,
+}
+
+impl MergeRange {
+    fn new(cfg: &MergeRangeConfig) -> Result<Self> {
+        let start = cfg.start.dmx_index();
+        ensure!(
+            cfg.count > 0 && start + cfg.count <= 512,
+            "DMX input range starting at {} with {} addresses doesn't fit in a universe",
+            cfg.start,
+            cfg.count
+        );
+        Ok(Self {
+            universe: cfg.universe,
+            source: cfg.source,
+            start,
+            count: cfg.count,
+            mode: cfg.mode,
+            last_show: vec![0; cfg.count],
+            last_input: None,
+            owner: vec![Owner::Show; cfg.count],
+        })
+    }
+
+    fn merge(&mut self, show: &DmxBuffer, input: Option<&DmxBuffer>, out: &mut DmxBuffer) {
+        let range = self.start..self.start + self.count;
+        let show = &show[range.clone()];
+        let out = &mut out[range.clone()];
+        let Some(input) = input.map(|input| &input[range]) else {
+            // With no live source, the show keeps control of the range.
+            self.last_input = None;
+            self.owner.fill(Owner::Show);
+            self.last_show.copy_from_slice(show);
+            return;
+        };
+        match self.mode {
+            MergeMode::Htp => {
+                for (o, i) in out.iter_mut().zip(input) {
+                    *o = (*o).max(*i);
+                }
+            }
+            MergeMode::Ltp => {
+                for (i, owner) in self.owner.iter_mut().enumerate() {
+                    if show[i] != self.last_show[i] {
+                        *owner = Owner::Show;
+                    }
+                    // A source that has just appeared takes every address.
+                    // If both sides changed, the input wins.
+                    if !matches!(&self.last_input, Some(last) if last[i] == input[i]) {
+                        *owner = Owner::Input;
+                    }
+                    if *owner == Owner::Input {
+                        out[i] = input[i];
+                    }
+                }
+                self.last_input = Some(input.to_vec());
+            }
+        }
+        self.last_show.copy_from_slice(show);
+    }
+}
+
+const fn default_timeout() -> f64 {
+    2.5
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn range(mode: MergeMode) -> MergeRange {
+        MergeRange {
+            universe: 0,
+            source: InputUniverse::Sacn(1),
+            start: 0,
+            count: 2,
+            mode,
+            last_show: vec![0; 2],
+            last_input: None,
+            owner: vec![Owner::Show; 2],
+        }
+    }
+
+    fn frame(a: u8, b: u8) -> DmxBuffer {
+        let mut frame = [0; 512];
+        frame[0] = a;
+        frame[1] = b;
+        frame
+    }
+
+    fn merge(range: &mut MergeRange, show: DmxBuffer, input: Option<DmxBuffer>) -> [u8; 2] {
+        let mut out = show;
+        range.merge(&show, input.as_ref(), &mut out);
+        [out[0], out[1]]
+    }
+
+    #[test]
+    fn test_htp() {
+        let mut r = range(MergeMode::Htp);
+        // The show is higher on the first address, the input on the second.
+        assert_eq!([10, 100], merge(&mut r, frame(10, 50), Some(frame(5, 100))));
+        // And the other way around.
+        assert_eq!([200, 50], merge(&mut r, frame(0, 50), Some(frame(200, 30))));
+        assert_eq!([10, 50], merge(&mut r, frame(10, 50), None));
+    }
+
+    #[test]
+    fn test_ltp() {
+        let mut r = range(MergeMode::Ltp);
+        // The input takes over when it appears.
+        assert_eq!([5, 100], merge(&mut r, frame(10, 50), Some(frame(5, 100))));
+        // The show takes back only the address it changed.
+        assert_eq!([20, 100], merge(&mut r, frame(20, 50), Some(frame(5, 100))));
+        // Then the input takes it back by changing it.
+        assert_eq!([6, 100], merge(&mut r, frame(20, 50), Some(frame(6, 100))));
+        // Dropping the source returns control to the show.
+        assert_eq!([20, 50], merge(&mut r, frame(20, 50), None));
+    }
+}
@@ -7,10 +7,10 @@ use tunnels::{
 use crate::midi::Device;
 
 /// Basic model for the few APC20 controls we use so far.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct AkaiApc20 {
     /// When interpreting channel control messages, offset the incoming channel
-    /// by this amount.
+    /// by this amount, relative to the start of the visible bank.
     pub channel_offset: usize,
 }
 
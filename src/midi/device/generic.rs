@@ -0,0 +1,342 @@
+//! A generic MIDI device, whose controls are mapped by a YAML file.
+//!
+//! Each entry in the file maps a note or control change to an action:
+//!
+//! ```yaml
+//! controls:
+//!   - cc: 0
+//!     action:
+//!       channel_level: 0
+//!   - note: 32
+//!     channel: 1
+//!     action: go
+//!   - cc: 16
+//!     action:
+//!       osc: /Comet/Shutter
+//!     feedback: {}
+//! ```
+//!
+//! Channel indices are relative to the device's channel offset within the
+//! visible bank. Controls with feedback are sent back their value, as note
+//! velocity or controller value, whenever it changes.
+use std::{
+    collections::HashSet,
+    fs::File,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result};
+use lazy_static::lazy_static;
+use log::error;
+use rosc::OscMessage;
+use serde::{Deserialize, Serialize};
+use tunnels::midi::{Event, EventType, Output};
+
+use crate::{
+    channel::KnobIndex,
+    midi::Device,
+    osc::{OscClientId, OscControlMessage},
+};
+
+/// A MIDI device with no built-in model, named by its config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GenericMidiDevice {
+    name: &'static str,
+    /// When interpreting channel control messages, offset the incoming channel
+    /// by this amount, relative to the start of the visible bank.
+    pub channel_offset: usize,
+}
+
+lazy_static! {
+    static ref DEVICE_NAMES: Mutex<HashSet<&'static str>> = Default::default();
+}
+
+impl GenericMidiDevice {
+    pub fn new(name: &str, channel_offset: usize) -> Self {
+        // Device names are few and live as long as the show, so keep a
+        // single copy of each to let devices stay Copy.
+        let mut names = DEVICE_NAMES.lock().unwrap();
+        let name = match names.get(name) {
+            Some(name) => *name,
+            None => {
+                let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+                names.insert(name);
+                name
+            }
+        };
+        Self {
+            name,
+            channel_offset,
+        }
+    }
+
+    pub fn device_name(&self) -> &str {
+        self.name
+    }
+}
+
+/// The MIDI message sent by a control.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MidiInput {
+    Note(u8),
+    Cc(u8),
+}
+
+impl MidiInput {
+    /// Return the input that sent an event, and the MIDI channel it was sent on.
+    pub fn from_event(event: &Event) -> (Self, u8) {
+        let control = event.mapping.control;
+        let input = match event.mapping.event_type {
+            EventType::NoteOn | EventType::NoteOff => Self::Note(control),
+            EventType::ControlChange => Self::Cc(control),
+        };
+        (input, event.mapping.channel)
+    }
+}
+
+/// What a mapped control does.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    SelectChannel(usize),
+    ChannelLevel(usize),
+    /// Set a knob of a channel.
+    /// If no channel is provided, control the selected channel.
+    ChannelKnob {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        channel: Option<usize>,
+        knob: KnobIndex,
+    },
+    BankUp,
+    BankDown,
+    Go,
+    Back,
+    Release,
+    GrandMaster,
+    StrobeRate,
+    /// Strobe while the control is held.
+    StrobeOn,
+    ToggleBlackout,
+    /// Send the control's value to an OSC control address, such as
+    /// /Comet/Shutter.
+    Osc(String),
+}
+
+/// Send a control's value back to it, scaled between off and on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Feedback {
+    #[serde(default = "default_feedback_on")]
+    pub on: u8,
+    #[serde(default)]
+    pub off: u8,
+}
+
+const fn default_feedback_on() -> u8 {
+    127
+}
+
+impl Default for Feedback {
+    fn default() -> Self {
+        Self {
+            on: default_feedback_on(),
+            off: 0,
+        }
+    }
+}
+
+/// A single control of a generic device.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ControlMapping {
+    #[serde(flatten)]
+    pub input: MidiInput,
+    /// The MIDI channel the control sends on.
+    #[serde(default)]
+    pub channel: u8,
+    pub action: Action,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub feedback: Option<Feedback>,
+}
+
+impl ControlMapping {
+    /// Return the MIDI message showing the provided value, if this control
+    /// has feedback.
+    pub fn feedback_message(&self, value: f64) -> Option<[u8; 3]> {
+        let feedback = self.feedback?;
+        let (status, number, data) = match self.input {
+            MidiInput::Note(number) => (
+                0x90,
+                number,
+                if value > 0.0 {
+                    feedback.on
+                } else {
+                    feedback.off
+                },
+            ),
+            MidiInput::Cc(number) => {
+                let (off, on) = (feedback.off as f64, feedback.on as f64);
+                (0xB0, number, (off + (on - off) * value).round() as u8)
+            }
+        };
+        Some([status | (self.channel & 0x0F), number, data & 0x7F])
+    }
+
+    /// Send feedback showing the provided value, if this control has any.
+    pub fn send_feedback(&self, value: f64, output: &mut Output<Device>) {
+        let Some(msg) = self.feedback_message(value) else {
+            return;
+        };
+        if let Err(err) = output.send_raw(&msg) {
+            error!(
+                "MIDI send error sending feedback for {:?}: {err}.",
+                self.input
+            );
+        }
+    }
+}
+
+/// The mapping of every control of a generic device, as loaded from a file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MidiMap {
+    /// The file this mapping was loaded from, and is saved to.
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(default)]
+    pub controls: Vec<ControlMapping>,
+}
+
+impl MidiMap {
+    /// Load a mapping from a YAML file.
+    /// A file that doesn't exist yet is an empty mapping, to be learned.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut map: Self = if path.exists() {
+            let file = File::open(path)?;
+            serde_yaml::from_reader(file)
+                .with_context(|| format!("loading MIDI mapping {}", path.display()))?
+        } else {
+            Self::default()
+        };
+        map.path = path.to_path_buf();
+        for control in &map.controls {
+            if let Action::Osc(addr) = &control.action {
+                osc_control(addr, 0.0).with_context(|| {
+                    format!("MIDI mapping {} for {:?}", path.display(), control.input)
+                })?;
+            }
+        }
+        Ok(map)
+    }
+
+    /// Save this mapping back to the file it was loaded from.
+    pub fn save(&self) -> Result<()> {
+        let file = File::create(&self.path)?;
+        serde_yaml::to_writer(file, self)
+            .with_context(|| format!("saving MIDI mapping {}", self.path.display()))
+    }
+
+    /// Return the control that sent an event, if it is mapped.
+    pub fn control(&self, event: &Event) -> Option<&ControlMapping> {
+        let (input, channel) = MidiInput::from_event(event);
+        self.controls
+            .iter()
+            .find(|control| control.input == input && control.channel == channel)
+    }
+
+    /// Map a control to an action, replacing any previous mapping.
+    pub fn learn(&mut self, input: MidiInput, channel: u8, action: Action) {
+        self.controls
+            .retain(|control| control.input != input || control.channel != channel);
+        self.controls.push(ControlMapping {
+            input,
+            channel,
+            action,
+            feedback: None,
+        });
+    }
+}
+
+/// A generic device together with its mapping.
+pub struct MappedDevice<'a> {
+    pub device: GenericMidiDevice,
+    pub map: &'a MidiMap,
+}
+
+/// Build a control message setting an OSC control to a value.
+pub fn osc_control(addr: &str, value: f64) -> Result<OscControlMessage> {
+    Ok(OscControlMessage::new(
+        OscMessage {
+            addr: addr.to_string(),
+            args: vec![rosc::OscType::Float(value as f32)],
+        },
+        OscClientId::internal(),
+    )?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_learn() -> Result<()> {
+        let mut map: MidiMap = serde_yaml::from_str(
+            "
+controls:
+  - cc: 0
+    action:
+      channel_level: 2
+  - note: 32
+    channel: 1
+    action: go
+    feedback:
+      on: 5
+  - cc: 16
+    action:
+      osc: /Comet/Shutter
+",
+        )?;
+        assert_eq!(
+            ControlMapping {
+                input: MidiInput::Note(32),
+                channel: 1,
+                action: Action::Go,
+                feedback: Some(Feedback { on: 5, off: 0 }),
+            },
+            map.controls[1]
+        );
+        assert_eq!(Action::ChannelLevel(2), map.controls[0].action);
+
+        map.learn(
+            MidiInput::Cc(0),
+            0,
+            Action::Osc("/Master/GrandMaster".into()),
+        );
+        assert_eq!(3, map.controls.len());
+        let reloaded: MidiMap = serde_yaml::from_str(&serde_yaml::to_string(&map)?)?;
+        assert_eq!(map.controls, reloaded.controls);
+        assert_eq!(
+            Action::Osc("/Master/GrandMaster".into()),
+            reloaded.controls[2].action
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_feedback_message() {
+        let mut control = ControlMapping {
+            input: MidiInput::Cc(16),
+            channel: 2,
+            action: Action::GrandMaster,
+            feedback: None,
+        };
+        assert_eq!(None, control.feedback_message(1.0));
+
+        control.feedback = Some(Feedback { on: 100, off: 20 });
+        assert_eq!(Some([0xB2, 16, 60]), control.feedback_message(0.5));
+        assert_eq!(Some([0xB2, 16, 100]), control.feedback_message(1.0));
+
+        control.input = MidiInput::Note(32);
+        assert_eq!(Some([0x92, 32, 100]), control.feedback_message(0.1));
+        assert_eq!(Some([0x92, 32, 20]), control.feedback_message(0.0));
+    }
+}
@@ -9,10 +9,10 @@ use tunnels::{
 use crate::{channel::KnobValue, midi::Device, show::ChannelId};
 
 /// Model of the Novation Launch Control XL.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct NovationLaunchControlXL {
     /// When interpreting channel control messages, offset the incoming channel
-    /// by this amount.
+    /// by this amount, relative to the start of the visible bank.
     pub channel_offset: usize,
 }
 
@@ -57,7 +57,7 @@ impl NovationLaunchControlXL {
         Ok(())
     }
 
-    /// Determine the midi channel for the given show control channel.
+    /// Determine the midi channel for the given channel in the visible bank.
     /// Return None if the show channel isn't mapped onto this device.
     pub fn midi_channel_for_control_channel(&self, channel: ChannelId) -> Option<u8> {
         let midi_channel = channel.inner() as isize - self.channel_offset as isize;
@@ -267,16 +267,25 @@ pub struct LedState {
 impl LedState {
     pub const OFF: Self = Self { red: 0, green: 0 };
     pub const YELLOW: Self = Self { red: 3, green: 3 };
+    pub const RED: Self = Self { red: 3, green: 0 };
 
     fn as_byte(self) -> u8 {
         0b1100 + self.red + (self.green << 4)
     }
 
     /// Set bipolar knobs to green, unipolar knobs to red.
-    pub fn from_knob_value(val: &KnobValue) -> Self {
+    /// Knobs that haven't picked up their value yet are dimmed.
+    pub fn from_knob_value(val: &KnobValue, picked_up: bool) -> Self {
+        let level = if picked_up { 3 } else { 1 };
         match val {
-            KnobValue::Bipolar(_) => Self { red: 0, green: 3 },
-            KnobValue::Unipolar(_) => Self { red: 3, green: 0 },
+            KnobValue::Bipolar(_) => Self {
+                red: 0,
+                green: level,
+            },
+            KnobValue::Unipolar(_) => Self {
+                red: level,
+                green: 0,
+            },
         }
     }
 }
@@ -1,4 +1,5 @@
 //! Device models - presenting midi actions from a control surface as typed events.
 
 pub mod apc20;
+pub mod generic;
 pub mod launch_control_xl;
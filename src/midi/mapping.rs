@@ -1,5 +1,9 @@
 //! Mappings between show control events and midi device-specific actions.
 //!
+use log::error;
+use number::UnipolarFloat;
+use rosc::{OscMessage, OscType};
+use tunnels::midi::{EventType, Output};
 use tunnels::midi_controls::unipolar_from_midi;
 
 use super::{
@@ -8,13 +12,16 @@ use super::{
             AkaiApc20, Apc20ChannelButtonType, Apc20ChannelControlEvent, Apc20ControlEvent,
             Apc20StateChange,
         },
+        generic::{osc_control, Action, ControlMapping, MappedDevice, MidiInput},
         launch_control_xl::{
             LaunchControlXLChannelButton, LaunchControlXLChannelControlEvent,
             LaunchControlXLChannelStateChange, LaunchControlXLControlEvent,
-            LaunchControlXLStateChange, LedState, NovationLaunchControlXL,
+            LaunchControlXLSideButton, LaunchControlXLStateChange, LedState,
+            NovationLaunchControlXL,
         },
     },
-    MidiHandler,
+    pickup::{Pickup, StripControl},
+    Device, MidiHandler,
 };
 use crate::{
     channel::{
@@ -22,11 +29,25 @@ use crate::{
         ChannelStateChange as SpecificChannelStateChange, ControlMessage as ChannelControlMessage,
         KnobValue, StateChange as ChannelStateChange,
     },
-    show::ShowControlMessage,
+    cue::ControlMessage as CueControlMessage,
+    master::{ControlMessage as MasterControlMessage, StateChange as MasterStateChange},
+    show::{ChannelId, ShowControlMessage},
 };
 
+/// Move a physical control to a MIDI value, subject to pickup.
+/// Return the value to send to the show, if the move should change it.
+fn take(pickup: &mut Pickup, channel: u8, control: StripControl, val: u8) -> Option<UnipolarFloat> {
+    pickup
+        .take(channel, control, unipolar_from_midi(val).val())
+        .map(UnipolarFloat::new)
+}
+
 impl MidiHandler for AkaiApc20 {
-    fn interpret(&self, event: &tunnels::midi::Event) -> Option<ShowControlMessage> {
+    fn interpret(
+        &self,
+        event: &tunnels::midi::Event,
+        pickup: &mut Pickup,
+    ) -> Option<ShowControlMessage> {
         use Apc20ChannelButtonType::*;
         use Apc20ChannelControlEvent::*;
         use Apc20ControlEvent::*;
@@ -34,7 +55,12 @@ impl MidiHandler for AkaiApc20 {
             Channel { channel, event } => match event {
                 Fader(val) => ChannelControlMessage::Control {
                     channel_id: Some(channel as usize + self.channel_offset),
-                    msg: ScopedChannelControlMessage::Level(unipolar_from_midi(val)),
+                    msg: ScopedChannelControlMessage::Level(take(
+                        pickup,
+                        channel,
+                        StripControl::Fader,
+                        val,
+                    )?),
                 },
                 Button(TrackSelect) => {
                     ChannelControlMessage::SelectChannel(channel as usize + self.channel_offset)
@@ -46,39 +72,70 @@ impl MidiHandler for AkaiApc20 {
     fn emit_channel_control(
         &self,
         msg: &ChannelStateChange,
-        output: &mut tunnels::midi::Output<super::Device>,
+        pickup: &mut Pickup,
+        output: Option<&mut tunnels::midi::Output<super::Device>>,
     ) {
-        if let ChannelStateChange::SelectChannel(channel) = msg {
+        let midi_channel = |channel: ChannelId| {
             let midi_channel = channel.inner() as isize - self.channel_offset as isize;
-            let midi_channel = (midi_channel >= 0 && midi_channel < Self::CHANNEL_COUNT as isize)
-                .then_some(midi_channel as u8);
-            self.emit(
-                Apc20StateChange::ChannelButtonRadio {
-                    channel: midi_channel,
-                    button: Apc20ChannelButtonType::TrackSelect,
-                },
-                output,
-            );
+            (midi_channel >= 0 && midi_channel < Self::CHANNEL_COUNT as isize)
+                .then_some(midi_channel as u8)
+        };
+        match msg {
+            ChannelStateChange::SelectChannel(channel) => {
+                if let Some(output) = output {
+                    self.emit(
+                        Apc20StateChange::ChannelButtonRadio {
+                            channel: channel.and_then(midi_channel),
+                            button: Apc20ChannelButtonType::TrackSelect,
+                        },
+                        output,
+                    )
+                }
+            }
+            ChannelStateChange::State {
+                channel_id,
+                msg: SpecificChannelStateChange::Level(level),
+            } => {
+                if let Some(channel) = midi_channel(*channel_id) {
+                    pickup.set_value(channel, StripControl::Fader, level.val());
+                }
+            }
+            _ => (),
         }
     }
 }
 
 impl MidiHandler for NovationLaunchControlXL {
-    fn interpret(&self, event: &tunnels::midi::Event) -> Option<ShowControlMessage> {
+    fn interpret(
+        &self,
+        event: &tunnels::midi::Event,
+        pickup: &mut Pickup,
+    ) -> Option<ShowControlMessage> {
         use LaunchControlXLChannelButton::*;
         use LaunchControlXLChannelControlEvent::*;
         use LaunchControlXLControlEvent::*;
+        use LaunchControlXLSideButton::*;
         Some(ShowControlMessage::Channel(match self.parse(event)? {
             Channel { channel, event } => match event {
                 Fader(val) => ChannelControlMessage::Control {
                     channel_id: Some(channel as usize + self.channel_offset),
-                    msg: ScopedChannelControlMessage::Level(unipolar_from_midi(val)),
+                    msg: ScopedChannelControlMessage::Level(take(
+                        pickup,
+                        channel,
+                        StripControl::Fader,
+                        val,
+                    )?),
                 },
                 Knob { row, val } => ChannelControlMessage::Control {
                     channel_id: Some(channel as usize + self.channel_offset),
                     msg: ScopedChannelControlMessage::Knob {
                         index: row, // TODO: these are numbered top to bottom, do we want bottom to top?
-                        value: KnobValue::Unipolar(unipolar_from_midi(val)),
+                        value: KnobValue::Unipolar(take(
+                            pickup,
+                            channel,
+                            StripControl::Knob(row),
+                            val,
+                        )?),
                     },
                 },
                 Button(TrackFocus) => {
@@ -88,6 +145,22 @@ impl MidiHandler for NovationLaunchControlXL {
                     return None;
                 }
             },
+            SideButton(Left) => ChannelControlMessage::BankDown,
+            SideButton(Right) => ChannelControlMessage::BankUp,
+            SideButton(Record) => {
+                return Some(ShowControlMessage::Cue(CueControlMessage::Go));
+            }
+            SideButton(Solo) => {
+                return Some(ShowControlMessage::Cue(CueControlMessage::Back));
+            }
+            SideButton(Mute) => {
+                return Some(ShowControlMessage::Cue(CueControlMessage::Release));
+            }
+            SideButton(Device) => {
+                return Some(ShowControlMessage::Master(
+                    MasterControlMessage::ToggleBlackout,
+                ));
+            }
             SideButton(_) => {
                 return None;
             }
@@ -97,11 +170,16 @@ impl MidiHandler for NovationLaunchControlXL {
     fn emit_channel_control(
         &self,
         msg: &ChannelStateChange,
-        output: &mut tunnels::midi::Output<super::Device>,
+        pickup: &mut Pickup,
+        output: Option<&mut tunnels::midi::Output<super::Device>>,
     ) {
         match msg {
             ChannelStateChange::SelectChannel(channel) => {
-                let midi_channel = self.midi_channel_for_control_channel(*channel);
+                let Some(output) = output else {
+                    return;
+                };
+                let midi_channel =
+                    channel.and_then(|channel| self.midi_channel_for_control_channel(channel));
                 self.emit(
                     LaunchControlXLStateChange::ChannelButtonRadio {
                         channel: midi_channel,
@@ -116,20 +194,377 @@ impl MidiHandler for NovationLaunchControlXL {
                     return;
                 };
                 match msg {
-                    SpecificChannelStateChange::Knob { index, value } => self.emit(
-                        LaunchControlXLStateChange::Channel {
-                            channel,
-                            state: LaunchControlXLChannelStateChange::Knob {
-                                row: *index,
-                                state: LedState::from_knob_value(value),
+                    SpecificChannelStateChange::Knob { index, value } => {
+                        let control = StripControl::Knob(*index);
+                        pickup.set_value(channel, control, value.as_unipolar().val());
+                        let Some(output) = output else {
+                            return;
+                        };
+                        self.emit(
+                            LaunchControlXLStateChange::Channel {
+                                channel,
+                                state: LaunchControlXLChannelStateChange::Knob {
+                                    row: *index,
+                                    state: LedState::from_knob_value(
+                                        value,
+                                        pickup.is_picked_up(channel, control),
+                                    ),
+                                },
                             },
+                            output,
+                        )
+                    }
+                    SpecificChannelStateChange::Level(level) => {
+                        pickup.set_value(channel, StripControl::Fader, level.val())
+                    }
+                }
+            }
+            ChannelStateChange::Bank { bank, count } => {
+                let Some(output) = output else {
+                    return;
+                };
+                // Clear the knob LEDs; the visible channels will set them again.
+                for channel in 0..Self::CHANNEL_COUNT {
+                    for row in 0..3 {
+                        self.emit(
+                            LaunchControlXLStateChange::Channel {
+                                channel,
+                                state: LaunchControlXLChannelStateChange::Knob {
+                                    row,
+                                    state: LedState::OFF,
+                                },
+                            },
+                            output,
+                        );
+                    }
+                }
+                // Light the bank buttons that lead somewhere.
+                for (button, lit) in [
+                    (LaunchControlXLSideButton::Left, *bank > 0),
+                    (LaunchControlXLSideButton::Right, bank + 1 < *count),
+                ] {
+                    self.emit(
+                        LaunchControlXLStateChange::SideButton {
+                            button,
+                            state: if lit { LedState::YELLOW } else { LedState::OFF },
                         },
                         output,
-                    ),
-                    SpecificChannelStateChange::Level(_) => (),
+                    );
                 }
             }
             ChannelStateChange::ChannelLabels(_) => (),
         }
     }
+
+    fn emit_master_control(
+        &self,
+        msg: &MasterStateChange,
+        _pickup: &mut Pickup,
+        output: Option<&mut tunnels::midi::Output<super::Device>>,
+    ) {
+        if let (MasterStateChange::Blackout(v), Some(output)) = (msg, output) {
+            self.emit(
+                LaunchControlXLStateChange::SideButton {
+                    button: LaunchControlXLSideButton::Device,
+                    state: if *v { LedState::RED } else { LedState::OFF },
+                },
+                output,
+            );
+        }
+    }
+}
+
+impl MappedDevice<'_> {
+    /// Send feedback to every control whose value is provided.
+    /// Values of mapped controllers are recorded in the pickup state.
+    fn emit_values(
+        &self,
+        value: impl Fn(&Action) -> Option<f64>,
+        pickup: &mut Pickup,
+        mut output: Option<&mut Output<Device>>,
+    ) {
+        for control in &self.map.controls {
+            let Some(value) = value(&control.action) else {
+                continue;
+            };
+            if let MidiInput::Cc(number) = control.input {
+                pickup.set_value(control.channel, StripControl::Control(number), value);
+            }
+            if let Some(output) = output.as_deref_mut() {
+                control.send_feedback(value, output);
+            }
+        }
+    }
+
+    fn channel_id(&self, channel: usize) -> usize {
+        channel + self.device.channel_offset
+    }
+}
+
+/// Return the value of a control that is on or off.
+fn on_off(on: bool) -> f64 {
+    if on {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+impl MidiHandler for MappedDevice<'_> {
+    fn interpret(
+        &self,
+        event: &tunnels::midi::Event,
+        pickup: &mut Pickup,
+    ) -> Option<ShowControlMessage> {
+        let ControlMapping {
+            input,
+            channel,
+            action,
+            ..
+        } = self.map.control(event)?;
+        let pressed = event.value > 0 && event.mapping.event_type != EventType::NoteOff;
+        // Buttons only act when pressed.
+        let trigger = |msg| pressed.then_some(msg);
+        // Notes are on or off; controllers are continuous.
+        let mut value = || match input {
+            MidiInput::Note(_) => Some(UnipolarFloat::new(on_off(pressed))),
+            MidiInput::Cc(number) => take(
+                pickup,
+                *channel,
+                StripControl::Control(*number),
+                event.value,
+            ),
+        };
+        match action {
+            Action::SelectChannel(c) => trigger(ShowControlMessage::Channel(
+                ChannelControlMessage::SelectChannel(self.channel_id(*c)),
+            )),
+            Action::ChannelLevel(c) => Some(ShowControlMessage::Channel(
+                ChannelControlMessage::Control {
+                    channel_id: Some(self.channel_id(*c)),
+                    msg: ScopedChannelControlMessage::Level(value()?),
+                },
+            )),
+            Action::ChannelKnob { channel, knob } => Some(ShowControlMessage::Channel(
+                ChannelControlMessage::Control {
+                    channel_id: channel.map(|c| self.channel_id(c)),
+                    msg: ScopedChannelControlMessage::Knob {
+                        index: *knob,
+                        value: KnobValue::Unipolar(value()?),
+                    },
+                },
+            )),
+            Action::BankUp => trigger(ShowControlMessage::Channel(ChannelControlMessage::BankUp)),
+            Action::BankDown => {
+                trigger(ShowControlMessage::Channel(ChannelControlMessage::BankDown))
+            }
+            Action::Go => trigger(ShowControlMessage::Cue(CueControlMessage::Go)),
+            Action::Back => trigger(ShowControlMessage::Cue(CueControlMessage::Back)),
+            Action::Release => trigger(ShowControlMessage::Cue(CueControlMessage::Release)),
+            Action::GrandMaster => Some(ShowControlMessage::Master(MasterControlMessage::Set(
+                MasterStateChange::GrandMaster(value()?),
+            ))),
+            Action::StrobeRate => Some(ShowControlMessage::Master(MasterControlMessage::Set(
+                MasterStateChange::StrobeRate(value()?),
+            ))),
+            Action::StrobeOn => Some(ShowControlMessage::Master(MasterControlMessage::Set(
+                MasterStateChange::StrobeOn(pressed),
+            ))),
+            Action::ToggleBlackout => trigger(ShowControlMessage::Master(
+                MasterControlMessage::ToggleBlackout,
+            )),
+            Action::Osc(addr) => match osc_control(addr, value()?.val()) {
+                Ok(msg) => Some(ShowControlMessage::Osc(msg)),
+                Err(err) => {
+                    error!("MIDI mapping for {input:?}: {err:#}.");
+                    None
+                }
+            },
+        }
+    }
+
+    fn emit_channel_control(
+        &self,
+        msg: &ChannelStateChange,
+        pickup: &mut Pickup,
+        output: Option<&mut Output<Device>>,
+    ) {
+        self.emit_values(
+            |action| match (action, msg) {
+                (Action::SelectChannel(c), ChannelStateChange::SelectChannel(selected)) => {
+                    Some(on_off(selected.is_some_and(|selected| {
+                        selected.inner() == self.channel_id(*c)
+                    })))
+                }
+                (Action::BankUp, ChannelStateChange::Bank { bank, count }) => {
+                    Some(on_off(bank + 1 < *count))
+                }
+                (Action::BankDown, ChannelStateChange::Bank { bank, .. }) => {
+                    Some(on_off(*bank > 0))
+                }
+                (
+                    Action::ChannelLevel(c),
+                    ChannelStateChange::State {
+                        channel_id,
+                        msg: SpecificChannelStateChange::Level(level),
+                    },
+                ) if channel_id.inner() == self.channel_id(*c) => Some(level.val()),
+                (
+                    Action::ChannelKnob {
+                        channel: Some(c),
+                        knob,
+                    },
+                    ChannelStateChange::State {
+                        channel_id,
+                        msg: SpecificChannelStateChange::Knob { index, value },
+                    },
+                ) if channel_id.inner() == self.channel_id(*c) && index == knob => {
+                    Some(value.as_unipolar().val())
+                }
+                _ => None,
+            },
+            pickup,
+            output,
+        );
+    }
+
+    fn emit_master_control(
+        &self,
+        msg: &MasterStateChange,
+        pickup: &mut Pickup,
+        output: Option<&mut Output<Device>>,
+    ) {
+        self.emit_values(
+            |action| match (action, msg) {
+                (Action::GrandMaster, MasterStateChange::GrandMaster(v))
+                | (Action::StrobeRate, MasterStateChange::StrobeRate(v)) => Some(v.val()),
+                (Action::StrobeOn, MasterStateChange::StrobeOn(v))
+                | (Action::ToggleBlackout, MasterStateChange::Blackout(v)) => Some(on_off(*v)),
+                _ => None,
+            },
+            pickup,
+            output,
+        );
+    }
+
+    fn emit_osc(&self, msg: &OscMessage, pickup: &mut Pickup, output: Option<&mut Output<Device>>) {
+        let value = match msg.args.first() {
+            Some(OscType::Float(v)) => *v as f64,
+            Some(OscType::Double(v)) => *v,
+            Some(OscType::Bool(v)) => on_off(*v),
+            _ => return,
+        };
+        self.emit_values(
+            |action| matches!(action, Action::Osc(addr) if *addr == msg.addr).then_some(value),
+            pickup,
+            output,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tunnels::midi::{Event, Mapping};
+
+    use super::*;
+    use crate::midi::{
+        device::generic::{GenericMidiDevice, MidiMap},
+        PickupMode,
+    };
+
+    fn event(event_type: EventType, control: u8, value: u8) -> Event {
+        Event {
+            mapping: Mapping {
+                event_type,
+                channel: 0,
+                control,
+            },
+            value,
+        }
+    }
+
+    #[test]
+    fn test_interpret_mapped_device() -> anyhow::Result<()> {
+        let map: MidiMap = serde_yaml::from_str(
+            "
+controls:
+  - cc: 0
+    action:
+      channel_level: 1
+  - note: 32
+    action: go
+  - cc: 16
+    action:
+      osc: /Comet/Shutter
+",
+        )?;
+        let device = MappedDevice {
+            device: GenericMidiDevice::new("test", 8),
+            map: &map,
+        };
+        let mut pickup = Pickup::new(PickupMode::Jump);
+
+        // Channels are offset by the device's channel offset.
+        let msg = device.interpret(&event(EventType::ControlChange, 0, 127), &mut pickup);
+        assert!(matches!(
+            msg,
+            Some(ShowControlMessage::Channel(ChannelControlMessage::Control {
+                channel_id: Some(9),
+                msg: ScopedChannelControlMessage::Level(level),
+            })) if level == UnipolarFloat::ONE
+        ));
+
+        // Buttons only act when pressed.
+        assert!(matches!(
+            device.interpret(&event(EventType::NoteOn, 32, 127), &mut pickup),
+            Some(ShowControlMessage::Cue(CueControlMessage::Go))
+        ));
+        assert!(device
+            .interpret(&event(EventType::NoteOff, 32, 0), &mut pickup)
+            .is_none());
+
+        let Some(ShowControlMessage::Osc(msg)) =
+            device.interpret(&event(EventType::ControlChange, 16, 0), &mut pickup)
+        else {
+            panic!("expected an OSC control message");
+        };
+        assert_eq!("/Comet/Shutter", msg.addr());
+
+        // Unmapped controls are ignored.
+        assert!(device
+            .interpret(&event(EventType::ControlChange, 1, 64), &mut pickup)
+            .is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pickup_without_output() -> anyhow::Result<()> {
+        let map: MidiMap = serde_yaml::from_str(
+            "
+controls:
+  - cc: 0
+    action: grand_master
+",
+        )?;
+        let device = MappedDevice {
+            device: GenericMidiDevice::new("test", 0),
+            map: &map,
+        };
+        let mut pickup = Pickup::new(PickupMode::Cross);
+
+        // The value is tracked even though there's no output to send it to,
+        // so moving the control onto it picks it up.
+        device.emit_master_control(
+            &MasterStateChange::GrandMaster(UnipolarFloat::new(0.8)),
+            &mut pickup,
+            None,
+        );
+        assert!(matches!(
+            device.interpret(&event(EventType::ControlChange, 0, 102), &mut pickup),
+            Some(ShowControlMessage::Master(MasterControlMessage::Set(
+                MasterStateChange::GrandMaster(_)
+            )))
+        ));
+        Ok(())
+    }
 }
@@ -1,8 +1,20 @@
 //! Define midi devices and handle midi controls.
 
-use anyhow::Result;
-use device::{apc20::AkaiApc20, launch_control_xl::NovationLaunchControlXL};
-use std::{cell::RefCell, fmt::Display, sync::mpsc::Sender};
+use anyhow::{bail, Result};
+use device::{
+    apc20::AkaiApc20,
+    generic::{Action, GenericMidiDevice, MappedDevice, MidiInput},
+    launch_control_xl::NovationLaunchControlXL,
+};
+use log::info;
+use pickup::Pickup;
+use rosc::OscMessage;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt::Display,
+    sync::mpsc::Sender,
+};
 
 use crate::{channel::StateChange as ChannelStateChange, show::ShowControlMessage};
 use tunnels::{
@@ -14,11 +26,16 @@ use crate::control::ControlMessage;
 
 mod device;
 mod mapping;
+mod pickup;
+
+pub use device::generic::MidiMap;
+pub use pickup::PickupMode;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Device {
     Apc20(AkaiApc20),
     LaunchControlXL(NovationLaunchControlXL),
+    Generic(GenericMidiDevice),
 }
 
 impl Display for Device {
@@ -32,6 +49,7 @@ impl MidiDevice for Device {
         match self {
             Self::Apc20(d) => d.device_name(),
             Self::LaunchControlXL(d) => d.device_name(),
+            Self::Generic(d) => d.device_name(),
         }
     }
 
@@ -39,6 +57,7 @@ impl MidiDevice for Device {
         match self {
             Self::Apc20(d) => d.init_midi(out),
             Self::LaunchControlXL(d) => d.init_midi(out),
+            Self::Generic(_) => Ok(()),
         }
     }
 }
@@ -51,27 +70,75 @@ impl Device {
             Self::LaunchControlXL(NovationLaunchControlXL { channel_offset: 0 }),
         ]
     }
+
+    /// Construct a device from its model name and channel offset.
+    pub fn from_model(model: &str, channel_offset: usize) -> Result<Self> {
+        let apc20 = AkaiApc20 { channel_offset };
+        let lcxl = NovationLaunchControlXL { channel_offset };
+        Ok(if model == apc20.device_name() {
+            Self::Apc20(apc20)
+        } else if model == lcxl.device_name() {
+            Self::LaunchControlXL(lcxl)
+        } else {
+            bail!("unknown MIDI device model \"{model}\"");
+        })
+    }
+
+    /// Construct a generic device, whose controls are mapped by a file.
+    pub fn generic(name: &str, channel_offset: usize) -> Self {
+        Self::Generic(GenericMidiDevice::new(name, channel_offset))
+    }
+
+    /// Construct a device from its name and channel offset, treating any
+    /// name that isn't a known model as a generic device.
+    pub fn from_name(name: &str, channel_offset: usize) -> Self {
+        Self::from_model(name, channel_offset)
+            .unwrap_or_else(|_| Self::generic(name, channel_offset))
+    }
+
+    /// Return the first show channel this device controls.
+    pub fn channel_offset(&self) -> usize {
+        match self {
+            Self::Apc20(d) => d.channel_offset,
+            Self::LaunchControlXL(d) => d.channel_offset,
+            Self::Generic(d) => d.channel_offset,
+        }
+    }
 }
 
 impl MidiHandler for Device {
-    fn interpret(&self, event: &Event) -> Option<ShowControlMessage> {
+    fn interpret(&self, event: &Event, pickup: &mut Pickup) -> Option<ShowControlMessage> {
         match self {
-            Self::Apc20(d) => d.interpret(event),
-            Self::LaunchControlXL(d) => d.interpret(event),
+            Self::Apc20(d) => d.interpret(event, pickup),
+            Self::LaunchControlXL(d) => d.interpret(event, pickup),
+            // Generic devices do nothing without a mapping.
+            Self::Generic(_) => None,
         }
     }
 
-    fn emit_channel_control(&self, msg: &ChannelStateChange, output: &mut Output<Device>) {
+    fn emit_channel_control(
+        &self,
+        msg: &ChannelStateChange,
+        pickup: &mut Pickup,
+        output: Option<&mut Output<Device>>,
+    ) {
         match self {
-            Self::Apc20(d) => d.emit_channel_control(msg, output),
-            Self::LaunchControlXL(d) => d.emit_channel_control(msg, output),
+            Self::Apc20(d) => d.emit_channel_control(msg, pickup, output),
+            Self::LaunchControlXL(d) => d.emit_channel_control(msg, pickup, output),
+            Self::Generic(_) => (),
         }
     }
 
-    fn emit_master_control(&self, msg: &crate::master::StateChange, output: &mut Output<Device>) {
+    fn emit_master_control(
+        &self,
+        msg: &crate::master::StateChange,
+        pickup: &mut Pickup,
+        output: Option<&mut Output<Device>>,
+    ) {
         match self {
-            Self::Apc20(d) => d.emit_master_control(msg, output),
-            Self::LaunchControlXL(d) => d.emit_master_control(msg, output),
+            Self::Apc20(d) => d.emit_master_control(msg, pickup, output),
+            Self::LaunchControlXL(d) => d.emit_master_control(msg, pickup, output),
+            Self::Generic(_) => (),
         }
     }
 }
@@ -79,15 +146,35 @@ impl MidiHandler for Device {
 /// MIDI handling, interpreting a MIDI event as a channel control message.
 pub trait MidiHandler {
     /// Interpet an incoming MIDI event as a show control message.
-    fn interpret(&self, event: &Event) -> Option<ShowControlMessage>;
+    /// Moves of faders and knobs are subject to the device's pickup state.
+    fn interpret(&self, event: &Event, pickup: &mut Pickup) -> Option<ShowControlMessage>;
 
     /// Send MIDI state to handle the provided channel state change.
+    /// Channel values are recorded in the device's pickup state, even if
+    /// the device has no output.
     #[allow(unused)]
-    fn emit_channel_control(&self, msg: &ChannelStateChange, output: &mut Output<Device>) {}
+    fn emit_channel_control(
+        &self,
+        msg: &ChannelStateChange,
+        pickup: &mut Pickup,
+        output: Option<&mut Output<Device>>,
+    ) {
+    }
 
     /// Send MIDI state to handle the provided master state change.
     #[allow(unused)]
-    fn emit_master_control(&self, msg: &crate::master::StateChange, output: &mut Output<Device>) {}
+    fn emit_master_control(
+        &self,
+        msg: &crate::master::StateChange,
+        pickup: &mut Pickup,
+        output: Option<&mut Output<Device>>,
+    ) {
+    }
+
+    /// Send MIDI state to handle the provided OSC message.
+    #[allow(unused)]
+    fn emit_osc(&self, msg: &OscMessage, pickup: &mut Pickup, output: Option<&mut Output<Device>>) {
+    }
 }
 
 pub struct MidiControlMessage {
@@ -99,45 +186,183 @@ pub struct MidiControlMessage {
 /// Writing to a midi ouput requires a unique reference; we can safely wrap
 /// this using RefCell since we only need a reference to the outputs to write,
 /// and we can only be making one write call at a time.
-pub struct MidiController(RefCell<Manager<Device>>);
+pub struct MidiController {
+    manager: RefCell<Manager<Device>>,
+    pickup_mode: PickupMode,
+    /// Pickup state for the faders and knobs of each device.
+    pickup: RefCell<HashMap<Device, Pickup>>,
+    /// Control mappings for generic devices.
+    maps: RefCell<HashMap<Device, MidiMap>>,
+    /// If true, moving a control on a generic device selects it to be mapped
+    /// rather than controlling the show.
+    learning: Cell<bool>,
+    /// The last control moved on a generic device while learning.
+    learned: Cell<Option<(Device, MidiInput, u8)>>,
+}
 
 impl MidiController {
-    pub fn new(devices: Vec<DeviceSpec<Device>>, send: Sender<ControlMessage>) -> Result<Self> {
+    pub fn new(
+        devices: Vec<DeviceSpec<Device>>,
+        maps: HashMap<Device, MidiMap>,
+        pickup_mode: PickupMode,
+        send: Sender<ControlMessage>,
+    ) -> Result<Self> {
         let mut controller = Manager::default();
         for d in devices {
             controller.add_device(d, send.clone())?;
         }
-        Ok(Self(RefCell::new(controller)))
+        Ok(Self {
+            manager: RefCell::new(controller),
+            pickup_mode,
+            pickup: Default::default(),
+            maps: RefCell::new(maps),
+            learning: Default::default(),
+            learned: Default::default(),
+        })
     }
 
-    /// Handle a channel state change message.
-    pub fn emit_channel_control(&self, msg: &ChannelStateChange) {
-        for output in self.0.borrow_mut().outputs() {
-            // FIXME: tunnels devices are inside-out/stateless
-            let device = *output.device();
-            device.emit_channel_control(msg, output);
+    /// Call f with the handler for a device and its pickup state.
+    /// Generic devices are handled by their mapping, if they have one.
+    fn handle<R>(&self, device: Device, f: impl FnOnce(&dyn MidiHandler, &mut Pickup) -> R) -> R {
+        let mut pickup = self.pickup.borrow_mut();
+        let pickup = pickup
+            .entry(device)
+            .or_insert_with(|| Pickup::new(self.pickup_mode));
+        let maps = self.maps.borrow();
+        match (device, maps.get(&device)) {
+            (Device::Generic(device), Some(map)) => f(&MappedDevice { device, map }, pickup),
+            _ => f(&device, pickup),
         }
     }
 
-    /// Handle a master state change message.
-    pub fn emit_master_control(&self, msg: &crate::master::StateChange) {
-        for output in self.0.borrow_mut().outputs() {
+    /// Call f with the handler, pickup state, and output of every device to
+    /// send to, or only the provided device.
+    /// Devices whose pickup state is tracked but have no output, as when
+    /// replaying a journal, are called without one, so their pickup state
+    /// stays current.
+    fn for_each_device(
+        &self,
+        only: Option<Device>,
+        f: impl Fn(&dyn MidiHandler, &mut Pickup, Option<&mut Output<Device>>),
+    ) {
+        let mut without_output: Vec<Device> = self.pickup.borrow().keys().copied().collect();
+        for output in self.manager.borrow_mut().outputs() {
             // FIXME: tunnels devices are inside-out/stateless
             let device = *output.device();
-            device.emit_master_control(msg, output);
+            without_output.retain(|d| *d != device);
+            if only.is_some_and(|only| only != device) {
+                continue;
+            }
+            self.handle(device, |handler, pickup| f(handler, pickup, Some(output)));
+        }
+        for device in without_output {
+            if only.is_some_and(|only| only != device) {
+                continue;
+            }
+            self.handle(device, |handler, pickup| f(handler, pickup, None));
         }
     }
+
+    /// Return every device with an output.
+    pub fn devices(&self) -> Vec<Device> {
+        let mut devices = Vec::new();
+        for output in self.manager.borrow_mut().outputs() {
+            devices.push(*output.device());
+        }
+        devices
+    }
+
+    /// Track pickup state for the provided devices, whether or not they have
+    /// an output.
+    pub fn track_pickup(&self, devices: &[Device]) {
+        let mut pickup = self.pickup.borrow_mut();
+        for device in devices {
+            pickup
+                .entry(*device)
+                .or_insert_with(|| Pickup::new(self.pickup_mode));
+        }
+    }
+
+    /// Interpret an incoming MIDI message as a show control message.
+    pub fn interpret(&self, msg: &MidiControlMessage) -> Option<ShowControlMessage> {
+        self.handle(msg.device, |handler, pickup| {
+            handler.interpret(&msg.event, pickup)
+        })
+    }
+
+    /// Start or stop learning mappings for generic devices.
+    pub fn set_learning(&self, learning: bool) {
+        info!(
+            "MIDI learn {}.",
+            if learning { "started" } else { "stopped" }
+        );
+        self.learning.set(learning);
+        self.learned.set(None);
+    }
+
+    /// While learning, select the control that sent a message to be mapped.
+    /// Return true if the message was consumed.
+    pub fn learn_control(&self, msg: &MidiControlMessage) -> bool {
+        if !self.learning.get() || !self.maps.borrow().contains_key(&msg.device) {
+            return false;
+        }
+        let (input, channel) = MidiInput::from_event(&msg.event);
+        self.learned.set(Some((msg.device, input, channel)));
+        true
+    }
+
+    /// While learning, map the last control moved to an OSC address, and
+    /// save the mapping.
+    pub fn learn_target(&self, addr: &str) -> Result<()> {
+        if !self.learning.get() {
+            return Ok(());
+        }
+        let Some((device, input, channel)) = self.learned.take() else {
+            return Ok(());
+        };
+        let mut maps = self.maps.borrow_mut();
+        let Some(map) = maps.get_mut(&device) else {
+            return Ok(());
+        };
+        map.learn(input, channel, Action::Osc(addr.to_string()));
+        info!("Mapped {input:?} on MIDI channel {channel} of {device} to {addr}.");
+        map.save()
+    }
+
+    /// Handle a channel state change message.
+    /// If a device is provided, only send to that device.
+    pub fn emit_channel_control(&self, msg: &ChannelStateChange, only: Option<Device>) {
+        self.for_each_device(only, |handler, pickup, output| {
+            handler.emit_channel_control(msg, pickup, output)
+        });
+    }
+
+    /// Handle a master state change message.
+    /// If a device is provided, only send to that device.
+    pub fn emit_master_control(&self, msg: &crate::master::StateChange, only: Option<Device>) {
+        self.for_each_device(only, |handler, pickup, output| {
+            handler.emit_master_control(msg, pickup, output)
+        });
+    }
+
+    /// Handle an OSC state change message.
+    /// If a device is provided, only send to that device.
+    pub fn emit_osc(&self, msg: &OscMessage, only: Option<Device>) {
+        self.for_each_device(only, |handler, pickup, output| {
+            handler.emit_osc(msg, pickup, output)
+        });
+    }
 }
 
 impl EmitMidiChannelMessage for MidiController {
     fn emit_midi_channel_message(&self, msg: &ChannelStateChange) {
-        self.emit_channel_control(msg);
+        self.emit_channel_control(msg, None);
     }
 }
 
 impl EmitMidiMasterMessage for MidiController {
     fn emit_midi_master_message(&self, msg: &crate::master::StateChange) {
-        self.emit_master_control(msg);
+        self.emit_master_control(msg, None);
     }
 }
 
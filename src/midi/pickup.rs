@@ -0,0 +1,183 @@
+//! Soft takeover for MIDI faders and knobs.
+//!
+//! The position of a physical control can disagree with the value it controls,
+//! for example after a preset recall, a bank change or an edit over OSC. Track
+//! both, and decide how each move of the control is applied to the value.
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::channel::KnobIndex;
+
+/// How a physical control takes over a value it disagrees with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PickupMode {
+    /// Apply every move immediately, so the value jumps to the control.
+    #[default]
+    Jump,
+    /// Ignore the control until it crosses the current value.
+    Cross,
+    /// Move the value along with the control, scaled so that the two meet at
+    /// the end of the control's travel.
+    Scale,
+}
+
+/// A control and value closer than this are considered to match.
+const TOLERANCE: f64 = 1.5 / 127.0;
+
+/// A physical control on one channel strip of a device.
+/// Generic devices have no strips; their controls are keyed by MIDI channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StripControl {
+    Fader,
+    Knob(KnobIndex),
+    /// A control of a generic device, by its MIDI controller number.
+    Control(u8),
+}
+
+#[derive(Default)]
+struct ControlState {
+    /// The current value of the control, as last sent by the show.
+    value: Option<f64>,
+    /// The last position received from the physical control.
+    position: Option<f64>,
+}
+
+/// The pickup state of every control on a device.
+pub struct Pickup {
+    mode: PickupMode,
+    controls: HashMap<(u8, StripControl), ControlState>,
+}
+
+impl Pickup {
+    pub fn new(mode: PickupMode) -> Self {
+        Self {
+            mode,
+            controls: Default::default(),
+        }
+    }
+
+    /// Note the current value of a control, as sent by the show.
+    pub fn set_value(&mut self, channel: u8, control: StripControl, value: f64) {
+        self.controls.entry((channel, control)).or_default().value = Some(value);
+    }
+
+    /// Return true if the position of a control matches its value.
+    /// A control that hasn't been moved yet has not been picked up.
+    /// In jump mode every move is applied, so every control is picked up.
+    pub fn is_picked_up(&self, channel: u8, control: StripControl) -> bool {
+        if self.mode == PickupMode::Jump {
+            return true;
+        }
+        self.controls.get(&(channel, control)).is_some_and(|state| {
+            match (state.value, state.position) {
+                (Some(value), Some(position)) => (value - position).abs() <= TOLERANCE,
+                _ => false,
+            }
+        })
+    }
+
+    /// Handle a control moving to a new position.
+    /// Return the value to apply, if the move should change it.
+    pub fn take(&mut self, channel: u8, control: StripControl, position: f64) -> Option<f64> {
+        let state = self.controls.entry((channel, control)).or_default();
+        let previous = state.position.replace(position);
+        let value = match state.value {
+            // With no value to disagree with, take the control as it is.
+            None => Some(position),
+            Some(value) => {
+                let matches = |p: f64| (value - p).abs() <= TOLERANCE;
+                if matches(position) || previous.is_some_and(matches) {
+                    // Already picked up, or just landed on the value.
+                    Some(position)
+                } else {
+                    match self.mode {
+                        PickupMode::Jump => Some(position),
+                        PickupMode::Cross => previous
+                            .filter(|previous| (*previous < value) != (position < value))
+                            .map(|_| position),
+                        PickupMode::Scale => {
+                            previous.and_then(|previous| scale(value, previous, position))
+                        }
+                    }
+                }
+            }
+        };
+        if value.is_some() {
+            state.value = value;
+        }
+        value
+    }
+}
+
+/// Move a value in the same direction as a control moving from previous to
+/// position, scaled so that both reach the end of the control's travel
+/// together.
+fn scale(value: f64, previous: f64, position: f64) -> Option<f64> {
+    let scaled = if position > previous {
+        1.0 - (1.0 - value) * (1.0 - position) / (1.0 - previous)
+    } else if position < previous {
+        value * position / previous
+    } else {
+        return None;
+    };
+    Some(scaled.clamp(0.0, 1.0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FADER: (u8, StripControl) = (0, StripControl::Fader);
+
+    fn pickup(mode: PickupMode) -> Pickup {
+        let mut pickup = Pickup::new(mode);
+        pickup.set_value(FADER.0, FADER.1, 0.5);
+        pickup
+    }
+
+    #[test]
+    fn test_jump() {
+        let mut p = pickup(PickupMode::Jump);
+        assert!(p.is_picked_up(FADER.0, FADER.1));
+        assert!(p.is_picked_up(1, StripControl::Knob(0)));
+        assert_eq!(Some(0.1), p.take(FADER.0, FADER.1, 0.1));
+        assert!(p.is_picked_up(FADER.0, FADER.1));
+    }
+
+    #[test]
+    fn test_cross() {
+        let mut p = pickup(PickupMode::Cross);
+        // The first move only tells us where the control is.
+        assert_eq!(None, p.take(FADER.0, FADER.1, 0.1));
+        assert_eq!(None, p.take(FADER.0, FADER.1, 0.3));
+        assert!(!p.is_picked_up(FADER.0, FADER.1));
+        assert_eq!(Some(0.6), p.take(FADER.0, FADER.1, 0.6));
+        assert!(p.is_picked_up(FADER.0, FADER.1));
+        assert_eq!(Some(0.2), p.take(FADER.0, FADER.1, 0.2));
+        assert_eq!(Some(0.9), p.take(FADER.0, FADER.1, 0.9));
+
+        // Landing on the value picks up without crossing it.
+        p.set_value(FADER.0, FADER.1, 0.8);
+        assert_eq!(None, p.take(FADER.0, FADER.1, 0.95));
+        assert_eq!(Some(0.8), p.take(FADER.0, FADER.1, 0.8));
+    }
+
+    #[test]
+    fn test_scale() {
+        let mut p = pickup(PickupMode::Scale);
+        assert_eq!(None, p.take(FADER.0, FADER.1, 0.0));
+        // Halfway up the control's travel moves halfway up the value's.
+        let value = p.take(FADER.0, FADER.1, 0.5).unwrap();
+        assert!((value - 0.75).abs() < 1e-9);
+        assert!(!p.is_picked_up(FADER.0, FADER.1));
+        assert_eq!(Some(1.0), p.take(FADER.0, FADER.1, 1.0));
+        assert!(p.is_picked_up(FADER.0, FADER.1));
+
+        // Moving down scales towards zero.
+        p.set_value(FADER.0, FADER.1, 0.4);
+        let value = p.take(FADER.0, FADER.1, 0.5).unwrap();
+        assert!((value - 0.2).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,341 @@
+//! Slave internal clocks to MIDI beat clock and MIDI timecode.
+//!
+//! Beat clock (24 pulses per quarter note) sets the tempo, and start, stop,
+//! continue and song position set the position. MIDI timecode also sets the
+//! position, converted to beats at the current tempo.
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use anyhow::{anyhow, bail, ensure, Result};
+use log::info;
+use midir::{Ignore, MidiInput, MidiInputConnection};
+use number::Phase;
+use serde::Deserialize;
+use tunnels::{clock_bank::N_CLOCKS, clock_server::StaticClockBank};
+
+/// Slave clocks to a MIDI input.
+#[derive(Debug, Deserialize)]
+pub struct MidiClockConfig {
+    /// The name of the MIDI input port to receive clock and timecode from.
+    pub input_port: String,
+    /// The indices of the clocks to slave, counting from 0.
+    pub clocks: Vec<usize>,
+    /// The number of clock cycles per beat; 0.25 cycles once per 4/4 bar.
+    #[serde(default = "default_subdivision")]
+    pub subdivision: f64,
+}
+
+const fn default_subdivision() -> f64 {
+    1.0
+}
+
+/// Beat clock pulses per quarter note.
+const PPQN: usize = 24;
+
+/// Clocks slaved to a MIDI input.
+pub struct MidiClock {
+    transport: Arc<Mutex<Transport>>,
+    clocks: Vec<usize>,
+    subdivision: f64,
+    /// The position of the slaved clocks, in cycles, as of the last update.
+    cycles: f64,
+    /// True if the slaved clocks completed a cycle during the last update.
+    ticked: bool,
+    _connection: MidiInputConnection<()>,
+}
+
+impl MidiClock {
+    /// Start receiving clock from the configured MIDI input.
+    pub fn start(cfg: &MidiClockConfig) -> Result<Self> {
+        for &index in &cfg.clocks {
+            ensure!(
+                index < N_CLOCKS,
+                "MIDI clock can't slave clock {index}, only {N_CLOCKS} clocks exist"
+            );
+        }
+        let mut input = MidiInput::new("comet clock")?;
+        // Beat clock and timecode are timing and sysex messages, which are
+        // ignored by default.
+        input.ignore(Ignore::ActiveSense);
+        let Some(port) = input.ports().into_iter().find(|port| {
+            input
+                .port_name(port)
+                .is_ok_and(|name| name == cfg.input_port)
+        }) else {
+            bail!("MIDI clock input port \"{}\" not found", cfg.input_port);
+        };
+        let transport = Arc::new(Mutex::new(Transport::default()));
+        let receiver = transport.clone();
+        let connection = input
+            .connect(
+                &port,
+                "comet clock input",
+                move |_, msg, _| receiver.lock().unwrap().handle(msg, Instant::now()),
+                (),
+            )
+            .map_err(|err| anyhow!("connecting to MIDI clock input: {err}"))?;
+        info!(
+            "Slaving clocks {:?} to MIDI clock from {}.",
+            cfg.clocks, cfg.input_port
+        );
+        Ok(Self {
+            transport,
+            clocks: cfg.clocks.clone(),
+            subdivision: cfg.subdivision,
+            cycles: 0.0,
+            ticked: false,
+            _connection: connection,
+        })
+    }
+
+    /// Advance the slaved clocks to the current MIDI position.
+    pub fn update(&mut self) {
+        let beats = self.transport.lock().unwrap().beats_at(Instant::now());
+        let cycles = beats * self.subdivision;
+        self.ticked = cycles.floor() != self.cycles.floor();
+        self.cycles = cycles;
+    }
+
+    /// Overwrite the state of the slaved clocks in a clock bank.
+    pub fn slave(&self, clock_bank: &mut StaticClockBank) {
+        for &index in &self.clocks {
+            let clock = &mut clock_bank.0[index];
+            clock.phase = Phase::new(self.cycles.rem_euclid(1.0));
+            clock.ticks = self.cycles.floor() as i64;
+            clock.ticked = self.ticked;
+        }
+    }
+}
+
+/// The transport state of a MIDI clock source.
+#[derive(Default)]
+struct Transport {
+    running: bool,
+    /// If true, the next pulse marks the current position rather than
+    /// advancing it, as after start or continue.
+    pending: bool,
+    /// The position in beats, as of the last message.
+    beats: f64,
+    /// When the position was last set.
+    updated: Option<Instant>,
+    /// When the last beat clock pulse arrived.
+    last_pulse: Option<Instant>,
+    /// The intervals between recent beat clock pulses, in seconds.
+    intervals: VecDeque<f64>,
+    timecode: TimecodeDecoder,
+}
+
+impl Transport {
+    /// Handle a single MIDI message, received at the provided time.
+    fn handle(&mut self, msg: &[u8], now: Instant) {
+        match msg {
+            [0xF8] => {
+                if let Some(last) = self.last_pulse.replace(now) {
+                    if self.intervals.len() == PPQN {
+                        self.intervals.pop_front();
+                    }
+                    self.intervals.push_back((now - last).as_secs_f64());
+                }
+                if std::mem::take(&mut self.pending) {
+                    self.locate(self.beats, now);
+                } else if self.running {
+                    self.locate(self.beats + 1.0 / PPQN as f64, now);
+                }
+            }
+            [0xFA] => {
+                self.running = true;
+                self.pending = true;
+                self.beats = 0.0;
+            }
+            [0xFB] => {
+                self.running = true;
+                self.pending = true;
+            }
+            [0xFC] => {
+                self.running = false;
+                self.locate(self.beats, now);
+            }
+            // Song position, in sixteenth notes.
+            [0xF2, lsb, msb] => {
+                let sixteenths = (*lsb as u16 | ((*msb as u16) << 7)) as f64;
+                self.locate(sixteenths / 4.0, now);
+            }
+            // While beat clock is running it sets the position, and locating
+            // by quarter frames would fight it; only a full frame locates.
+            [0xF1, data] => {
+                if let Some(seconds) = self.timecode.quarter_frame(*data) {
+                    if !self.running {
+                        self.locate_time(seconds, now);
+                    }
+                }
+            }
+            // Full timecode frame, sent when the source locates.
+            [0xF0, 0x7F, _, 0x01, 0x01, hours, minutes, seconds, frames, 0xF7] => {
+                self.locate_time(timecode_seconds(*hours, *minutes, *seconds, *frames), now);
+            }
+            _ => (),
+        }
+    }
+
+    fn locate(&mut self, beats: f64, now: Instant) {
+        self.beats = beats;
+        self.updated = Some(now);
+    }
+
+    /// Locate to a time, at the current tempo.
+    /// Without beat clock, there's no tempo to convert the time to beats.
+    fn locate_time(&mut self, seconds: f64, now: Instant) {
+        if let Some(bpm) = self.bpm() {
+            self.locate(seconds * bpm / 60.0, now);
+        }
+    }
+
+    /// Return the tempo in beats per minute, if beat clock is being received.
+    fn bpm(&self) -> Option<f64> {
+        if self.intervals.is_empty() {
+            return None;
+        }
+        let interval = self.intervals.iter().sum::<f64>() / self.intervals.len() as f64;
+        (interval > 0.0).then(|| 60.0 / (interval * PPQN as f64))
+    }
+
+    /// Return the position in beats at the provided time, extrapolating from
+    /// the last message at the current tempo.
+    fn beats_at(&self, now: Instant) -> f64 {
+        let moving = self.running && !self.pending;
+        let (true, Some(updated), Some(bpm)) = (moving, self.updated, self.bpm()) else {
+            return self.beats;
+        };
+        // Don't run ahead of the next pulse, which may be late.
+        let elapsed = now.saturating_duration_since(updated).as_secs_f64() * bpm / 60.0;
+        self.beats + elapsed.min(1.0 / PPQN as f64)
+    }
+}
+
+/// Assemble MIDI timecode quarter frames into times.
+#[derive(Default)]
+struct TimecodeDecoder {
+    pieces: [u8; 8],
+    /// Bit n is set if piece n has been received since piece 0.
+    received: u8,
+}
+
+impl TimecodeDecoder {
+    /// Handle a quarter frame, returning the time in seconds once a full
+    /// frame has been received.
+    fn quarter_frame(&mut self, data: u8) -> Option<f64> {
+        let piece = (data >> 4) as usize & 0x07;
+        if piece == 0 {
+            self.received = 0;
+        }
+        self.pieces[piece] = data & 0x0F;
+        self.received |= 1 << piece;
+        if piece != 7 || self.received != 0xFF {
+            return None;
+        }
+        let p = &self.pieces;
+        let hours = p[6] | (p[7] << 4);
+        let seconds = timecode_seconds(
+            hours,
+            p[4] | (p[5] << 4),
+            p[2] | (p[3] << 4),
+            p[0] | (p[1] << 4),
+        );
+        // The last piece arrives two frames after the frame it describes.
+        Some(seconds + 2.0 / frame_rate((hours >> 5) & 0x03))
+    }
+}
+
+/// Return the frames per second for a timecode rate code.
+fn frame_rate(rate: u8) -> f64 {
+    match rate {
+        0 => 24.0,
+        1 => 25.0,
+        2 => 29.97,
+        _ => 30.0,
+    }
+}
+
+/// Return the time in seconds of a timecode, whose hours byte also carries
+/// the frame rate.
+fn timecode_seconds(hours: u8, minutes: u8, seconds: u8, frames: u8) -> f64 {
+    let rate = frame_rate((hours >> 5) & 0x03);
+    (hours & 0x1F) as f64 * 3600.0
+        + (minutes & 0x3F) as f64 * 60.0
+        + (seconds & 0x3F) as f64
+        + (frames & 0x1F) as f64 / rate
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_beat_clock() {
+        let mut transport = Transport::default();
+        let start = Instant::now();
+        // 120 bpm is a pulse every 1/48 of a second.
+        let pulse = Duration::from_secs_f64(1.0 / 48.0);
+        for i in 0..PPQN as u32 {
+            transport.handle(&[0xF8], start + pulse * i);
+        }
+        assert!((transport.bpm().unwrap() - 120.0).abs() < 1e-6);
+        // Stopped, the position doesn't move.
+        assert_eq!(0.0, transport.beats_at(start + pulse * 30));
+
+        // The first pulse after start is the first beat.
+        transport.handle(&[0xFA], start + pulse * 23 + pulse / 2);
+        let start = start + pulse * PPQN as u32;
+        for i in 0..=12 {
+            transport.handle(&[0xF8], start + pulse * i);
+        }
+        assert!((transport.beats_at(start + pulse * 12) - 0.5).abs() < 1e-6);
+        // Between pulses, the position moves at the tempo.
+        let between = start + pulse * 12 + pulse / 2;
+        assert!((transport.beats_at(between) - (0.5 + 1.0 / 48.0)).abs() < 1e-6);
+
+        transport.handle(&[0xFC], start + pulse * 13);
+        transport.handle(&[0xF2, 8, 0], start + pulse * 14);
+        assert_eq!(2.0, transport.beats_at(start + pulse * 20));
+    }
+
+    #[test]
+    fn test_timecode() {
+        let mut transport = Transport::default();
+        let now = Instant::now();
+        // Locating by timecode needs a tempo; 60 bpm is a beat per second.
+        for i in 0..2 {
+            transport.handle(&[0xF8], now + Duration::from_secs_f64(i as f64 / 24.0));
+        }
+        // 00:01:02:12 at 25 fps.
+        transport.handle(&[0xF0, 0x7F, 0x7F, 0x01, 0x01, 0x20, 1, 2, 12, 0xF7], now);
+        assert!((transport.beats - 62.48).abs() < 1e-6);
+
+        // The same time, less two frames, as quarter frames.
+        let mut decoder = TimecodeDecoder::default();
+        let pieces = [10, 0, 2, 0, 1, 0, 0, 0x2];
+        for (piece, value) in pieces.iter().enumerate().take(7) {
+            assert_eq!(None, decoder.quarter_frame((piece as u8) << 4 | value));
+        }
+        let seconds = decoder.quarter_frame(7 << 4 | pieces[7]).unwrap();
+        assert!((seconds - 62.48).abs() < 1e-6);
+
+        // While beat clock is running, quarter frames don't locate.
+        transport.handle(&[0xF2, 0, 0], now);
+        transport.handle(&[0xFB], now);
+        for (piece, value) in pieces.iter().enumerate() {
+            transport.handle(&[0xF1, (piece as u8) << 4 | value], now);
+        }
+        assert_eq!(0.0, transport.beats);
+        transport.handle(&[0xFC], now);
+        for (piece, value) in pieces.iter().enumerate() {
+            transport.handle(&[0xF1, (piece as u8) << 4 | value], now);
+        }
+        assert!((transport.beats - 62.48).abs() < 1e-6);
+    }
+}
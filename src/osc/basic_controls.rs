@@ -37,6 +37,10 @@ impl Button {
     where
         E: crate::osc::EmitScopedOscMessage + ?Sized,
     {
-        emitter.emit_float(self.control, if val { 1.0 } else { 0.0 });
+        emitter.emit_float_with_kind(
+            self.control,
+            if val { 1.0 } else { 0.0 },
+            super::ControlKind::Bool,
+        );
     }
 }
@@ -1,19 +1,25 @@
-use crate::channel::{ChannelControlMessage, ChannelStateChange, Channels, KnobIndex};
+use rosc::OscType;
+
+use crate::channel::{ChannelControlMessage, ChannelStateChange, Channels, KnobIndex, BANK_SIZE};
 use crate::channel::{ControlMessage, StateChange};
 
 use crate::osc::{GroupControlMap, RadioButton};
 
+use super::basic_controls::{button, Button};
 use super::fader_array::FaderArray;
 use super::label_array::LabelArray;
+use super::ScopedOscMessage;
 use anyhow::{anyhow, Context};
 
-const N_CHANNELS: usize = 8;
+const N_CHANNELS: usize = BANK_SIZE;
 
 pub(crate) const GROUP: &str = "Show";
 
 impl Channels {
     pub fn map_controls(map: &mut GroupControlMap<ControlMessage>) {
         CHANNEL_SELECT.map(map, ControlMessage::SelectChannel);
+        BANK_UP.map_trigger(map, || ControlMessage::BankUp);
+        BANK_DOWN.map_trigger(map, || ControlMessage::BankDown);
         CHANNEL_FADERS.map(map, |channel_id, level| {
             Ok(ControlMessage::Control {
                 channel_id: Some(channel_id),
@@ -47,7 +53,14 @@ impl Channels {
         S: crate::osc::EmitScopedOscMessage + ?Sized,
     {
         match sc {
-            StateChange::SelectChannel(channel_id) => CHANNEL_SELECT.set(channel_id.into(), send),
+            StateChange::SelectChannel(Some(channel_id)) if channel_id.inner() < N_CHANNELS => {
+                CHANNEL_SELECT.set(channel_id.into(), send)
+            }
+            StateChange::SelectChannel(_) => CHANNEL_SELECT.clear(send),
+            StateChange::Bank { bank, count } => send.emit_osc(ScopedOscMessage {
+                control: BANK_LABEL,
+                arg: OscType::String(format!("{}/{count}", bank + 1)),
+            }),
             StateChange::ChannelLabels(labels) => CHANNEL_LABELS.set(labels.into_iter(), send),
             StateChange::State { channel_id, msg } => match msg {
                 ChannelStateChange::Level(l) => CHANNEL_FADERS.set(channel_id.into(), l, send),
@@ -71,6 +84,10 @@ const CHANNEL_LABELS: LabelArray = LabelArray {
     empty_label: "",
 };
 
+const BANK_UP: Button = button("ChannelBankUp");
+const BANK_DOWN: Button = button("ChannelBankDown");
+const BANK_LABEL: &str = "ChannelBank";
+
 const CHANNEL_FADERS: FaderArray = FaderArray {
     control: "ChannelLevel",
 };
@@ -0,0 +1,106 @@
+//! Track the OSC clients that responses are sent to.
+//!
+//! Clients provided at startup are always sent to. Any other client is
+//! registered the first time it sends us a message, and is dropped if it goes
+//! silent for longer than the configured timeout. The show is told about
+//! dropped clients so it can forget their channel and animator selections.
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use log::info;
+
+use super::OscClientId;
+
+pub struct OscClients {
+    /// Clients provided at startup, which never expire.
+    fixed: Vec<OscClientId>,
+    /// Clients that registered by sending a message, and when we last heard
+    /// from each of them.
+    registered: HashMap<OscClientId, Instant>,
+    /// Drop registered clients we haven't heard from in this long.
+    timeout: Option<Duration>,
+}
+
+impl OscClients {
+    pub fn new(fixed: Vec<OscClientId>, timeout: Option<Duration>) -> Self {
+        Self {
+            fixed,
+            registered: Default::default(),
+            timeout,
+        }
+    }
+
+    /// Note that we heard from a client at the provided time.
+    /// Return true if the client was not previously known.
+    pub fn seen(&mut self, client: OscClientId, now: Instant) -> bool {
+        if self.fixed.contains(&client) {
+            return false;
+        }
+        let is_new = self.registered.insert(client, now).is_none();
+        if is_new {
+            info!("Registered {client}.");
+        }
+        is_new
+    }
+
+    /// Drop any registered client that has expired by the provided time.
+    /// Return the clients that were dropped.
+    pub fn expire(&mut self, now: Instant) -> Vec<OscClientId> {
+        let Some(timeout) = self.timeout else {
+            return Vec::new();
+        };
+        let mut expired = Vec::new();
+        self.registered.retain(|client, last_seen| {
+            if now.saturating_duration_since(*last_seen) <= timeout {
+                return true;
+            }
+            info!("Dropped {client} after {timeout:?} without a message.");
+            expired.push(*client);
+            false
+        });
+        expired
+    }
+
+    /// Return all current clients.
+    pub fn current(&self) -> impl Iterator<Item = &OscClientId> {
+        self.fixed.iter().chain(self.registered.keys())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::SocketAddr;
+
+    use super::*;
+
+    fn client(port: u16) -> OscClientId {
+        OscClientId::new(SocketAddr::from(([10, 0, 0, 1], port)))
+    }
+
+    #[test]
+    fn test_registration_and_expiry() {
+        let start = Instant::now();
+        let mut clients = OscClients::new(vec![client(1)], Some(Duration::from_secs(10)));
+
+        assert!(!clients.seen(client(1), start));
+        assert!(clients.seen(client(2), start));
+        assert!(!clients.seen(client(2), start + Duration::from_secs(5)));
+        assert!(clients.seen(client(3), start + Duration::from_secs(5)));
+
+        assert!(clients.expire(start + Duration::from_secs(12)).is_empty());
+        let mut current: Vec<_> = clients.current().copied().collect();
+        current.sort_by_key(|c| c.addr().port());
+        assert_eq!(vec![client(1), client(2), client(3)], current);
+
+        let mut expired = clients.expire(start + Duration::from_secs(20));
+        expired.sort_by_key(|c| c.addr().port());
+        assert_eq!(vec![client(2), client(3)], expired);
+        let current: Vec<_> = clients.current().copied().collect();
+        assert_eq!(vec![client(1)], current);
+
+        // An expired client registers again when it next sends a message.
+        assert!(clients.seen(client(2), start + Duration::from_secs(21)));
+    }
+}
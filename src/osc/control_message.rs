@@ -9,7 +9,7 @@ use super::{OscClientId, OscError};
 /// Wrapper type for OSC messages that provides a simplification for our domain.
 /// This includes pre-processing of the address to identify the breaks, as well
 /// as parsing of the group ID.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct OscControlMessage {
     /// The ID of the client that originated this message.
     pub client_id: OscClientId,
@@ -20,7 +20,7 @@ pub struct OscControlMessage {
     addr_index: AddressIndex,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct AddressIndex {
     /// The byte index in the addr string where the control key starts,
     /// including the leading slash.
@@ -55,6 +55,11 @@ impl OscControlMessage {
         })
     }
 
+    /// Return the raw/full OSC address.
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
     /// Return the first half of the control key, excluding the leading slash.
     pub fn entity_type(&self) -> &str {
         &self.addr[self.addr_index.key_start + 1..self.addr_index.control_start]
@@ -0,0 +1,35 @@
+use rosc::OscType;
+
+use crate::cue::{ControlMessage, CueList, StateChange};
+use crate::osc::GroupControlMap;
+
+use super::basic_controls::{button, Button};
+use super::ScopedOscMessage;
+
+pub(crate) const GROUP: &str = "Cues";
+
+const GO: Button = button("Go");
+const BACK: Button = button("Back");
+const RELEASE: Button = button("Release");
+
+impl CueList {
+    pub fn map_controls(map: &mut GroupControlMap<ControlMessage>) {
+        GO.map_trigger(map, || ControlMessage::Go);
+        BACK.map_trigger(map, || ControlMessage::Back);
+        RELEASE.map_trigger(map, || ControlMessage::Release);
+    }
+
+    pub fn emit_osc_state_change<S>(sc: StateChange, send: &S)
+    where
+        S: crate::osc::EmitScopedOscMessage + ?Sized,
+    {
+        let (control, label) = match sc {
+            StateChange::Current(label) => ("CurrentCue", label),
+            StateChange::Next(label) => ("NextCue", label),
+        };
+        send.emit_osc(ScopedOscMessage {
+            control,
+            arg: OscType::String(label),
+        });
+    }
+}
@@ -2,7 +2,7 @@
 use number::UnipolarFloat;
 use rosc::OscType;
 
-use super::{GroupControlMap, ScopedOscMessage};
+use super::{ControlKind, GroupControlMap, ScopedOscMessage, TalkbackMode};
 use anyhow::{bail, Result};
 
 use anyhow::{anyhow, Context};
@@ -15,11 +15,12 @@ pub struct FaderArray {
 
 impl FaderArray {
     /// Wire up this fader array to a control map.
+    /// Like single faders, the sender isn't sent its own changes.
     pub fn map<F, T>(self, map: &mut GroupControlMap<T>, process: F)
     where
         F: Fn(usize, UnipolarFloat) -> Result<T> + 'static + Copy,
     {
-        map.add(self.control, move |msg| {
+        map.add_with_talkback(self.control, TalkbackMode::Off, move |msg| {
             let index = msg
                 .addr_payload()
                 .split('/')
@@ -42,9 +43,12 @@ impl FaderArray {
     where
         S: crate::osc::EmitScopedOscMessage + ?Sized,
     {
-        emitter.emit_osc(ScopedOscMessage {
-            control: &format!("{}/{}", self.control, n + 1),
-            arg: OscType::Float(val.val() as f32),
-        });
+        emitter.emit_osc_with_kind(
+            ScopedOscMessage {
+                control: &format!("{}/{}", self.control, n + 1),
+                arg: OscType::Float(val.val() as f32),
+            },
+            ControlKind::Unipolar,
+        );
     }
 }
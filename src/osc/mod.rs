@@ -8,26 +8,32 @@ use anyhow::Result;
 use log::{error, info};
 use number::{BipolarFloat, Phase, UnipolarFloat};
 use rosc::{encoder, OscMessage, OscPacket, OscType};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::fmt::Display;
 use std::net::{SocketAddr, UdpSocket};
 use std::str::FromStr;
 use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
+use self::clients::OscClients;
 use self::radio_button::RadioButton;
 
 pub mod animation;
 pub mod audio;
 mod basic_controls;
 pub mod channels;
+mod clients;
 pub mod clock;
 mod control_message;
+pub mod cues;
 mod fader_array;
 mod label_array;
+pub mod presets;
 mod radio_button;
 mod register;
 
@@ -38,6 +44,17 @@ pub use register::prompt_osc_config;
 pub trait EmitScopedOscMessage {
     fn emit_osc(&self, msg: ScopedOscMessage);
 
+    /// Send an OSC message for a control that carries a known kind of value.
+    /// The kind is ignored by default; decorators should pass it along.
+    fn emit_osc_with_kind(&self, msg: ScopedOscMessage, _kind: ControlKind) {
+        self.emit_osc(msg);
+    }
+
+    /// Set how responses to the control message being handled are sent back
+    /// to the client that sent it.
+    /// Ignored by default; decorators should pass it along.
+    fn set_talkback(&self, _talkback: TalkbackMode) {}
+
     /// Send an OSC message setting the state of a float control.
     fn emit_float(&self, control: &str, val: f64) {
         self.emit_osc(ScopedOscMessage {
@@ -45,10 +62,54 @@ pub trait EmitScopedOscMessage {
             arg: OscType::Float(val as f32),
         });
     }
+
+    /// Send an OSC message setting the state of a float control of a known kind.
+    fn emit_float_with_kind(&self, control: &str, val: f64, kind: ControlKind) {
+        self.emit_osc_with_kind(
+            ScopedOscMessage {
+                control,
+                arg: OscType::Float(val as f32),
+            },
+            kind,
+        );
+    }
 }
 
 pub trait EmitOscMessage {
     fn emit_osc(&self, msg: OscMessage);
+
+    /// Send an OSC message for a control that carries a known kind of value.
+    /// The kind is ignored by default; decorators should pass it along.
+    fn emit_osc_with_kind(&self, msg: OscMessage, _kind: ControlKind) {
+        self.emit_osc(msg);
+    }
+
+    /// Set how responses to the control message being handled are sent back
+    /// to the client that sent it.
+    /// Ignored by default; decorators should pass it along.
+    fn set_talkback(&self, _talkback: TalkbackMode) {}
+}
+
+/// The kind of value carried by a control.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ControlKind {
+    /// A float in the range [0, 1].
+    Unipolar,
+    /// A float in the range [-1, 1].
+    Bipolar,
+    /// A float in the range [0, 1) that wraps around.
+    Phase,
+    /// A toggle or button, sent as 0 or 1.
+    Bool,
+    /// One option of a radio-select menu, sent as 0 or 1.
+    Select,
+}
+
+impl ControlKind {
+    /// Return true if values of this kind vary continuously.
+    pub fn is_continuous(&self) -> bool {
+        matches!(self, Self::Unipolar | Self::Bipolar | Self::Phase)
+    }
 }
 
 pub struct OscController {
@@ -56,14 +117,23 @@ pub struct OscController {
 }
 
 impl OscController {
+    /// Listen for OSC on the provided port, and send responses to the
+    /// provided clients as well as any client that sends us a message.
+    /// Clients that haven't sent a message within the timeout are dropped,
+    /// unless they were provided here.
     pub fn new(
         receive_port: u16,
         send_addrs: Vec<OscClientId>,
+        client_timeout: Option<Duration>,
         send: Sender<ControlMessage>,
     ) -> Result<Self> {
         let recv_addr = SocketAddr::from_str(&format!("0.0.0.0:{}", receive_port))?;
-        start_listener(recv_addr, send)?;
-        let response_send = start_sender(send_addrs)?;
+        let clients = Arc::new(Mutex::new(OscClients::new(send_addrs, client_timeout)));
+        start_listener(recv_addr, clients.clone(), send.clone())?;
+        if let Some(timeout) = client_timeout {
+            start_expiry(timeout, clients.clone(), send);
+        }
+        let response_send = start_sender(clients)?;
         Ok(Self {
             send: response_send,
         })
@@ -97,16 +167,34 @@ impl<'a> FixtureStateEmitter<'a> {
 
 impl<'a> EmitScopedOscMessage for FixtureStateEmitter<'a> {
     fn emit_osc(&self, msg: ScopedOscMessage) {
-        let addr = if let Some(g) = &self.key.group {
-            format!("/:{}/{}/{}", g, self.key.fixture, msg.control)
-        } else {
-            format!("/{}/{}", self.key.fixture, msg.control)
-        };
         self.channel_emitter.emit_osc(OscMessage {
-            addr,
+            addr: fixture_control_addr(self.key, msg.control),
             args: vec![msg.arg],
         });
     }
+
+    fn emit_osc_with_kind(&self, msg: ScopedOscMessage, kind: ControlKind) {
+        self.channel_emitter.emit_osc_with_kind(
+            OscMessage {
+                addr: fixture_control_addr(self.key, msg.control),
+                args: vec![msg.arg],
+            },
+            kind,
+        );
+    }
+
+    fn set_talkback(&self, talkback: TalkbackMode) {
+        self.channel_emitter.set_talkback(talkback);
+    }
+}
+
+/// Return the full OSC address of a control for the provided fixture group.
+pub fn fixture_control_addr(key: &FixtureGroupKey, control: &str) -> String {
+    if let Some(g) = &key.group {
+        format!("/:{}/{}/{}", g, key.fixture, control)
+    } else {
+        format!("/{}/{}", key.fixture, control)
+    }
 }
 
 impl<'a> EmitWledControlMessage for FixtureStateEmitter<'a> {
@@ -127,6 +215,20 @@ impl<'a> EmitScopedOscMessage for ScopedControlEmitter<'a> {
             args: vec![msg.arg],
         });
     }
+
+    fn emit_osc_with_kind(&self, msg: ScopedOscMessage, kind: ControlKind) {
+        self.emitter.emit_osc_with_kind(
+            OscMessage {
+                addr: format!("/{}/{}", self.entity, msg.control),
+                args: vec![msg.arg],
+            },
+            kind,
+        );
+    }
+
+    fn set_talkback(&self, talkback: TalkbackMode) {
+        self.emitter.set_talkback(talkback);
+    }
 }
 
 /// An OSC message that is implicitly scoped to a particular entity.
@@ -141,6 +243,16 @@ pub struct ScopedOscMessage<'a> {
 pub struct OscClientId(SocketAddr);
 
 impl OscClientId {
+    pub fn new(addr: SocketAddr) -> Self {
+        Self(addr)
+    }
+
+    /// A placeholder client ID for control messages that originate inside the
+    /// show rather than from a remote client.
+    pub fn internal() -> Self {
+        Self(SocketAddr::from(([0, 0, 0, 0], 0)))
+    }
+
     pub fn addr(&self) -> &SocketAddr {
         &self.0
     }
@@ -180,7 +292,21 @@ impl<C> GroupControlMap<C> {
         handler(msg)
     }
 
+    /// Return the names of all registered controls.
+    pub fn control_names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+
     pub fn add<F>(&mut self, control: &str, handler: F)
+    where
+        F: Fn(&OscControlMessage) -> Result<Option<C>> + 'static,
+    {
+        self.add_with_talkback(control, TalkbackMode::All, handler);
+    }
+
+    /// Add a control whose responses are sent back to the sender according
+    /// to the provided talkback mode.
+    pub fn add_with_talkback<F>(&mut self, control: &str, talkback: TalkbackMode, handler: F)
     where
         F: Fn(&OscControlMessage) -> Result<Option<C>> + 'static,
     {
@@ -188,9 +314,11 @@ impl<C> GroupControlMap<C> {
             Entry::Occupied(_) => {
                 panic!("duplicate control definition \"{control}\"");
             }
-            Entry::Vacant(v) => v.insert(Box::new(move |m| {
-                Ok(handler(m)?.map(|msg| (msg, TalkbackMode::All)))
-            })),
+            Entry::Vacant(v) => {
+                v.insert(Box::new(
+                    move |m| Ok(handler(m)?.map(|msg| (msg, talkback))),
+                ))
+            }
         };
     }
 
@@ -202,21 +330,26 @@ impl<C> GroupControlMap<C> {
         self.add(control, move |v| Ok(process(fetch(v)?)))
     }
 
+    /// Add a fader control.
+    /// The sender isn't sent its own changes, to avoid fighting it while the
+    /// fader is being dragged.
     pub fn add_unipolar<F>(&mut self, control: &str, process: F)
     where
         F: Fn(UnipolarFloat) -> C + 'static,
     {
-        self.add_fetch_process(control, OscControlMessage::get_unipolar, move |v| {
-            Some(process(v))
+        self.add_with_talkback(control, TalkbackMode::Off, move |m| {
+            Ok(Some(process(m.get_unipolar()?)))
         })
     }
 
+    /// Add a bipolar fader control.
+    /// The sender isn't sent its own changes, as for unipolar faders.
     pub fn add_bipolar<F>(&mut self, control: &str, process: F)
     where
         F: Fn(BipolarFloat) -> C + 'static,
     {
-        self.add_fetch_process(control, OscControlMessage::get_bipolar, move |v| {
-            Some(process(v))
+        self.add_with_talkback(control, TalkbackMode::Off, move |m| {
+            Ok(Some(process(m.get_bipolar()?)))
         })
     }
 
@@ -232,7 +365,13 @@ impl<C> GroupControlMap<C> {
 
 /// Forward OSC messages to the provided sender.
 /// Spawns a new thread to handle listening for messages.
-fn start_listener(addr: SocketAddr, send: Sender<ControlMessage>) -> Result<()> {
+/// Every sender is registered as a client; new clients are announced to the
+/// show before their first message is forwarded.
+fn start_listener(
+    addr: SocketAddr,
+    clients: Arc<Mutex<OscClients>>,
+    send: Sender<ControlMessage>,
+) -> Result<()> {
     let socket = UdpSocket::bind(addr)?;
 
     let mut buf = [0u8; rosc::decoder::MTU];
@@ -251,6 +390,15 @@ fn start_listener(addr: SocketAddr, send: Sender<ControlMessage>) -> Result<()>
                 continue;
             }
         };
+        let is_new = clients.lock().unwrap().seen(client_id, Instant::now());
+        if is_new
+            && send
+                .send(ControlMessage::OscClientRegistered(client_id))
+                .is_err()
+        {
+            info!("Control channel hung up, terminating OSC listener thread.");
+            return;
+        }
         if let Err(e) = forward_packet(packet, client_id, &send) {
             error!("Error unpacking/forwarding OSC packet: {}", e);
         }
@@ -258,12 +406,44 @@ fn start_listener(addr: SocketAddr, send: Sender<ControlMessage>) -> Result<()>
     Ok(())
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Periodically drop clients that have gone silent, and tell the show about
+/// them. Spawns a new thread to check for expired clients.
+fn start_expiry(timeout: Duration, clients: Arc<Mutex<OscClients>>, send: Sender<ControlMessage>) {
+    // Check often enough that a client is dropped soon after it expires.
+    let interval = (timeout / 4).clamp(Duration::from_millis(100), Duration::from_secs(1));
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        let expired = clients.lock().unwrap().expire(Instant::now());
+        for client in expired {
+            if send.send(ControlMessage::OscClientExpired(client)).is_err() {
+                info!("Control channel hung up, terminating OSC client expiry thread.");
+                return;
+            }
+        }
+    });
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum TalkbackMode {
     /// Control responses should be sent to all clients.
+    #[default]
     All,
     /// Control responses should be sent to all clients except the sender.
     Off,
+    /// Control responses should be sent only to the sender.
+    SenderOnly,
+}
+
+impl TalkbackMode {
+    /// Return true if a response should be sent to the provided client.
+    pub fn sends_to(&self, sender_id: Option<&OscClientId>, client: &OscClientId) -> bool {
+        let is_sender = sender_id == Some(client);
+        match self {
+            Self::All => true,
+            Self::Off => !is_sender,
+            Self::SenderOnly => is_sender,
+        }
+    }
 }
 
 pub struct OscControlResponse {
@@ -273,9 +453,9 @@ pub struct OscControlResponse {
 }
 
 /// Drain a control channel of OSC messages and send them.
-/// Sends each message to every provided address, unless the talkback mode
+/// Sends each message to every current client, unless the talkback mode
 /// says otherwise.
-fn start_sender(clients: Vec<OscClientId>) -> Result<Sender<OscControlResponse>> {
+fn start_sender(clients: Arc<Mutex<OscClients>>) -> Result<Sender<OscControlResponse>> {
     let (send, recv) = channel::<OscControlResponse>();
     let socket = UdpSocket::bind("0.0.0.0:0")?;
 
@@ -294,8 +474,8 @@ fn start_sender(clients: Vec<OscClientId>) -> Result<Sender<OscControlResponse>>
                 continue;
             };
             //log::debug!("Sending OSC message: {packet:?}");
-            for client in &clients {
-                if resp.talkback == TalkbackMode::Off && resp.sender_id == Some(*client) {
+            for client in clients.lock().unwrap().current() {
+                if !resp.talkback.sends_to(resp.sender_id.as_ref(), client) {
                     continue;
                 }
                 if let Err(err) = socket.send_to(&msg_buf, client.addr()) {
@@ -367,6 +547,17 @@ impl OscControlMessage {
         Ok(Phase::new(self.get_float()?))
     }
 
+    /// Get a single string argument from the provided OSC message.
+    pub fn get_string(&self) -> Result<String, OscError> {
+        match &self.arg {
+            OscType::String(v) => Ok(v.clone()),
+            other => Err(self.err(format!(
+                "expected a single string argument but found {:?}",
+                other
+            ))),
+        }
+    }
+
     /// Get a single boolean argument from the provided OSC message.
     /// Coerce ints and floats to boolean values.
     pub fn get_bool(&self) -> Result<bool, OscError> {
@@ -394,3 +585,48 @@ pub mod prelude {
     pub use super::{GroupControlMap, OscControlMessage};
     pub use crate::util::*;
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn control_msg(addr: &str, val: f32) -> OscControlMessage {
+        OscControlMessage::new(
+            OscMessage {
+                addr: addr.to_string(),
+                args: vec![OscType::Float(val)],
+            },
+            OscClientId::internal(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_control_map_talkback() {
+        let mut map = GroupControlMap::default();
+        map.add_unipolar("Level", |v| v.val());
+        map.add_bool("Active", |v| if v { 1.0 } else { 0.0 });
+        map.add_with_talkback("Quiet", TalkbackMode::Off, |_| Ok(Some(0.0)));
+
+        let (val, talkback) = map
+            .handle(&control_msg("/Test/Level", 0.5))
+            .unwrap()
+            .unwrap();
+        assert_eq!(0.5, val);
+        assert_eq!(TalkbackMode::Off, talkback);
+
+        let (_, talkback) = map
+            .handle(&control_msg("/Test/Active", 1.0))
+            .unwrap()
+            .unwrap();
+        assert_eq!(TalkbackMode::All, talkback);
+
+        let (_, talkback) = map
+            .handle(&control_msg("/Test/Quiet", 1.0))
+            .unwrap()
+            .unwrap();
+        assert_eq!(TalkbackMode::Off, talkback);
+
+        assert!(map.handle(&control_msg("/Test/Nope", 1.0)).is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+use number::UnipolarFloat;
+use rosc::OscType;
+
+use crate::osc::{GroupControlMap, RadioButton};
+use crate::preset::{ControlMessage, Presets, StateChange, N_PRESETS};
+
+use super::basic_controls::{button, Button};
+use super::label_array::LabelArray;
+use super::ScopedOscMessage;
+
+pub(crate) const GROUP: &str = "Presets";
+
+/// The fade time fader spans this range.
+const MAX_FADE_TIME: Duration = Duration::from_secs(20);
+
+const FADE_TIME: &str = "FadeTime";
+const ALL_GROUPS: Button = button("AllGroups");
+/// Text input naming the next preset to be stored.
+const NAME: &str = "Name";
+
+const STORE: RadioButton = RadioButton {
+    control: "Store",
+    n: N_PRESETS,
+    x_primary_coordinate: false,
+};
+
+const RECALL: RadioButton = RadioButton {
+    control: "Recall",
+    n: N_PRESETS,
+    x_primary_coordinate: false,
+};
+
+const LABELS: LabelArray = LabelArray {
+    control: "Label",
+    n: N_PRESETS,
+    empty_label: "",
+};
+
+impl Presets {
+    pub fn map_controls(map: &mut GroupControlMap<ControlMessage>) {
+        STORE.map(map, ControlMessage::Store);
+        RECALL.map(map, ControlMessage::Recall);
+        map.add_unipolar(FADE_TIME, |v| {
+            ControlMessage::FadeTime(MAX_FADE_TIME.mul_f64(v.val()))
+        });
+        ALL_GROUPS.map_state(map, ControlMessage::AllGroups);
+        map.add(NAME, |m| Ok(Some(ControlMessage::Name(m.get_string()?))));
+    }
+
+    pub fn emit_osc_state_change<S>(sc: StateChange, send: &S)
+    where
+        S: crate::osc::EmitScopedOscMessage + ?Sized,
+    {
+        match sc {
+            StateChange::FadeTime(t) => send.emit_float(
+                FADE_TIME,
+                UnipolarFloat::new(t.as_secs_f64() / MAX_FADE_TIME.as_secs_f64()).val(),
+            ),
+            StateChange::AllGroups(v) => ALL_GROUPS.send(v, send),
+            StateChange::Labels(labels) => LABELS.set(labels.into_iter(), send),
+            StateChange::Name(name) => send.emit_osc(ScopedOscMessage {
+                control: NAME,
+                arg: OscType::String(name),
+            }),
+        }
+    }
+}
@@ -1,7 +1,9 @@
 use log::error;
 use rosc::OscType;
 
-use super::{control_message::OscControlMessage, GroupControlMap, OscError, ScopedOscMessage};
+use super::{
+    control_message::OscControlMessage, ControlKind, GroupControlMap, OscError, ScopedOscMessage,
+};
 use anyhow::Result;
 
 /// Model a 1D button grid with radio-select behavior.
@@ -79,17 +81,35 @@ impl RadioButton {
             error!("radio button index {} out of range for {}", n, self.control);
             return;
         }
+        self.emit(Some(n), emitter);
+    }
+
+    /// Send OSC messages to turn off every button.
+    pub fn clear<S>(&self, emitter: &S)
+    where
+        S: crate::osc::EmitScopedOscMessage + ?Sized,
+    {
+        self.emit(None, emitter);
+    }
+
+    fn emit<S>(&self, selected: Option<usize>, emitter: &S)
+    where
+        S: crate::osc::EmitScopedOscMessage + ?Sized,
+    {
         for i in 0..self.n {
-            let val = if i == n { 1.0 } else { 0.0 };
+            let val = if Some(i) == selected { 1.0 } else { 0.0 };
             let (x, y) = if self.x_primary_coordinate {
                 (i + 1, 1)
             } else {
                 (1, i + 1)
             };
-            emitter.emit_osc(ScopedOscMessage {
-                control: &format!("{}/{}/{}", self.control, x, y),
-                arg: OscType::Float(val),
-            })
+            emitter.emit_osc_with_kind(
+                ScopedOscMessage {
+                    control: &format!("{}/{}/{}", self.control, x, y),
+                    arg: OscType::Float(val),
+                },
+                ControlKind::Select,
+            )
         }
     }
 }
@@ -0,0 +1,229 @@
+//! Advertise the show's OSC namespace using OSCQuery, so OSC clients can build
+//! UIs automatically.
+//!
+//! The namespace is built from the state the show sends to refresh a UI, so it
+//! covers every control that has state, along with its current value. Controls
+//! that declare their kind of value are advertised with a range. Text labels
+//! are advertised as read-only strings. Controls that have no state, such as
+//! buttons that trigger an action, are added from the show's control maps and
+//! advertised as write-only.
+//!
+//! The server runs in its own thread, and asks the show for the namespace the
+//! same way as the HTTP API.
+use std::sync::mpsc::Sender;
+
+use anyhow::{anyhow, Result};
+use log::info;
+use rosc::OscType;
+use serde_json::{json, Map, Value};
+use tiny_http::Server;
+
+use crate::{
+    control::{CapturedMessage, ControlMessage},
+    http::{osc_arg_json, query_show, respond, Query},
+    osc::ControlKind,
+};
+
+/// OSCQuery access flags.
+const READ: u8 = 1;
+const WRITE: u8 = 2;
+const READ_WRITE: u8 = 3;
+
+/// Start serving OSCQuery on the provided port, advertising the port the show
+/// listens for OSC on.
+pub fn start_server(port: u16, osc_port: u16, send: Sender<ControlMessage>) -> Result<()> {
+    let server = Server::http(("0.0.0.0", port))
+        .map_err(|err| anyhow!("starting OSCQuery server on port {port}: {err}"))?;
+    info!("Serving OSCQuery on port {port}.");
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let url = request.url().to_string();
+            let (path, attribute) = url.split_once('?').unwrap_or((&url, ""));
+            let result = if attribute == "HOST_INFO" {
+                Ok(host_info(osc_port))
+            } else {
+                query_show(&send, Query::Namespace).and_then(|namespace| {
+                    lookup(&namespace, path, attribute)
+                        .ok_or_else(|| (404, format!("no OSC node at {url}")))
+                })
+            };
+            respond(request, result);
+        }
+        info!("OSCQuery server shutting down.");
+    });
+    Ok(())
+}
+
+fn host_info(osc_port: u16) -> Value {
+    json!({
+        "NAME": "comet",
+        "OSC_PORT": osc_port,
+        "OSC_TRANSPORT": "UDP",
+        "EXTENSIONS": {
+            "ACCESS": true,
+            "VALUE": true,
+            "RANGE": true,
+            "TYPE": true,
+        },
+    })
+}
+
+/// Build the OSCQuery namespace from the OSC messages a UI refresh sends, and
+/// the addresses of every registered control.
+pub fn namespace(msgs: &[CapturedMessage], controls: &[String]) -> Value {
+    let mut root = Map::new();
+    root.insert("FULL_PATH".to_string(), json!("/"));
+    for CapturedMessage { msg, kind } in msgs {
+        let node = node_at(&mut root, &msg.addr);
+        let Some(value) = msg.args.first() else {
+            continue;
+        };
+        let (type_tag, access) = match value {
+            OscType::String(_) => ("s", READ),
+            _ => ("f", READ_WRITE),
+        };
+        node.insert("TYPE".to_string(), json!(type_tag));
+        node.insert("ACCESS".to_string(), json!(access));
+        node.insert("VALUE".to_string(), json!([osc_arg_json(value)]));
+        if let Some(kind) = kind {
+            let (min, max) = match kind {
+                ControlKind::Bipolar => (-1.0, 1.0),
+                ControlKind::Unipolar
+                | ControlKind::Phase
+                | ControlKind::Bool
+                | ControlKind::Select => (0.0, 1.0),
+            };
+            node.insert("RANGE".to_string(), json!([{ "MIN": min, "MAX": max }]));
+        }
+    }
+    for addr in controls {
+        let node = node_at(&mut root, addr);
+        // Controls with state were advertised above, either directly or as
+        // the parent of indexed controls such as radio buttons.
+        if node.contains_key("TYPE") || node.contains_key("CONTENTS") {
+            continue;
+        }
+        node.insert("TYPE".to_string(), json!("f"));
+        node.insert("ACCESS".to_string(), json!(WRITE));
+    }
+    Value::Object(root)
+}
+
+/// Return the node at the provided address, creating it and its parents if
+/// they don't exist yet.
+fn node_at<'a>(root: &'a mut Map<String, Value>, addr: &str) -> &'a mut Map<String, Value> {
+    let mut node = root;
+    let mut path = String::new();
+    for part in addr.split('/').filter(|part| !part.is_empty()) {
+        path.push('/');
+        path.push_str(part);
+        let contents = node
+            .entry("CONTENTS")
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+            .expect("CONTENTS is always an object");
+        node = contents
+            .entry(part)
+            .or_insert_with(|| json!({ "FULL_PATH": path }))
+            .as_object_mut()
+            .expect("nodes are always objects");
+    }
+    node
+}
+
+/// Find the node at the provided path, optionally returning only one of its
+/// attributes.
+fn lookup(namespace: &Value, path: &str, attribute: &str) -> Option<Value> {
+    let mut node = namespace;
+    for part in path.split('/').filter(|part| !part.is_empty()) {
+        node = node.get("CONTENTS")?.get(part)?;
+    }
+    if attribute.is_empty() {
+        return Some(node.clone());
+    }
+    let mut attributes = Map::new();
+    attributes.insert(attribute.to_string(), node.get(attribute)?.clone());
+    Some(Value::Object(attributes))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rosc::OscMessage;
+
+    fn captured(addr: &str, arg: OscType, kind: Option<ControlKind>) -> CapturedMessage {
+        CapturedMessage {
+            msg: OscMessage {
+                addr: addr.to_string(),
+                args: vec![arg],
+            },
+            kind,
+        }
+    }
+
+    #[test]
+    fn test_namespace() {
+        let namespace = namespace(
+            &[
+                captured(
+                    "/Master/GrandMaster",
+                    OscType::Float(0.5),
+                    Some(ControlKind::Unipolar),
+                ),
+                captured(
+                    "/:left/Comet/Speed",
+                    OscType::Float(-0.25),
+                    Some(ControlKind::Bipolar),
+                ),
+                captured("/Show/Label/1", OscType::String("Comet".to_string()), None),
+            ],
+            &[
+                "/Master/GrandMaster".to_string(),
+                "/Cues/Go".to_string(),
+                "/Show/Label".to_string(),
+            ],
+        );
+
+        assert_eq!(
+            Some(json!({
+                "FULL_PATH": "/Master/GrandMaster",
+                "TYPE": "f",
+                "ACCESS": 3,
+                "VALUE": [0.5],
+                "RANGE": [{ "MIN": 0.0, "MAX": 1.0 }],
+            })),
+            lookup(&namespace, "/Master/GrandMaster", "")
+        );
+        assert_eq!(
+            Some(json!({ "RANGE": [{ "MIN": -1.0, "MAX": 1.0 }] })),
+            lookup(&namespace, "/:left/Comet/Speed", "RANGE")
+        );
+        assert_eq!(
+            Some(json!({
+                "FULL_PATH": "/Show/Label/1",
+                "TYPE": "s",
+                "ACCESS": 1,
+                "VALUE": ["Comet"],
+            })),
+            lookup(&namespace, "/Show/Label/1", "")
+        );
+        assert_eq!(
+            Some(json!("/:left")),
+            lookup(&namespace, "/:left", "").and_then(|node| node.get("FULL_PATH").cloned())
+        );
+        // Controls without state are write-only.
+        assert_eq!(
+            Some(json!({
+                "FULL_PATH": "/Cues/Go",
+                "TYPE": "f",
+                "ACCESS": 2,
+            })),
+            lookup(&namespace, "/Cues/Go", "")
+        );
+        assert_eq!(
+            None,
+            lookup(&namespace, "/Show/Label", "").and_then(|node| node.get("TYPE").cloned())
+        );
+        assert!(lookup(&namespace, "/Nope", "").is_none());
+    }
+}
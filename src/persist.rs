@@ -0,0 +1,358 @@
+//! Save and restore show state, so a show can pick up where it left off.
+//!
+//! Control values are captured by recording the OSC state each fixture group
+//! emits, and restored by feeding those same messages back in as controls.
+//! Everything is keyed by fixture type, group name, and control name, so saved
+//! state remains valid if the patch order changes.
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+use rosc::{OscMessage, OscType};
+use serde::{Deserialize, Serialize};
+use tunnels::animation::Animation;
+
+use crate::{
+    animation::AnimationUIState,
+    channel::ChannelId,
+    channel::{ChannelStateEmitter, Channels},
+    control::{CapturedMessage, EmitControlMessage, StateCapture},
+    fixture::{animation_target::AnimationTargetIndex, FixtureGroup, Patch},
+    master::MasterControls,
+    osc::{fixture_control_addr, ControlKind, OscClientId, OscControlMessage},
+    preset::{PresetState, Presets},
+};
+
+/// The persisted state of the entire show.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct ShowState {
+    #[serde(default)]
+    pub groups: Vec<GroupState>,
+    #[serde(default)]
+    pub master: Vec<ControlValue>,
+    #[serde(default)]
+    pub presets: Vec<PresetState>,
+}
+
+/// The persisted state of a single fixture group.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GroupState {
+    pub fixture: String,
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub controls: Vec<ControlValue>,
+    #[serde(default)]
+    pub animations: Vec<AnimationState>,
+    /// The animator selected in the UI, if this group is assigned to a channel.
+    #[serde(default)]
+    pub selected_animation: Option<usize>,
+}
+
+/// The value of a single control, identified by its OSC control path.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ControlValue {
+    pub control: String,
+    pub value: f32,
+    /// The kind of value, if the control declared one.
+    #[serde(default)]
+    pub kind: Option<ControlKind>,
+}
+
+/// The persisted state of a single targeted animation.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AnimationState {
+    pub animation: Animation,
+    pub target: AnimationTargetIndex,
+}
+
+impl ShowState {
+    /// Capture the current state of the show.
+    pub fn capture(
+        patch: &Patch,
+        channels: &Channels,
+        animation_ui_state: &AnimationUIState,
+        master_controls: &MasterControls,
+        presets: &Presets,
+    ) -> Self {
+        let groups = patch
+            .iter()
+            .map(|group| {
+                let mut state = GroupState::capture(group);
+                state.selected_animation = channels
+                    .channel_for_fixture(group.key())
+                    .map(|channel| animation_ui_state.animation_index_for_channel(channel));
+                state
+            })
+            .collect();
+
+        let capture = StateCapture::default();
+        master_controls.emit_state(&capture);
+        let master = control_values(
+            &format!("/{}/", crate::master::GROUP),
+            capture.into_messages(),
+        );
+
+        Self {
+            groups,
+            master,
+            presets: presets.saved_state(),
+        }
+    }
+
+    /// Restore saved state into the show.
+    ///
+    /// Saved state for groups that are no longer patched is ignored.
+    /// Individual controls that fail to restore are logged and skipped.
+    pub fn restore(
+        &self,
+        patch: &mut Patch,
+        channels: &Channels,
+        animation_ui_state: &mut AnimationUIState,
+        master_controls: &mut MasterControls,
+        presets: &mut Presets,
+    ) {
+        let saved: HashMap<_, _> = self
+            .groups
+            .iter()
+            .map(|g| ((g.fixture.as_str(), g.group.as_deref()), g))
+            .collect();
+        for group in patch.iter_mut() {
+            let key = group.key();
+            let Some(state) = saved.get(&(&*key.fixture, key.group.as_deref())) else {
+                debug!("No saved state for {key}.");
+                continue;
+            };
+            state.restore(group);
+            if let (Some(channel), Some(n)) = (
+                channels.channel_for_fixture(group.key()),
+                state.selected_animation,
+            ) {
+                if let Err(err) = animation_ui_state.set_current_animation(channel, n) {
+                    warn!(
+                        "Unable to restore selected animation for {}: {err:#}.",
+                        group.key()
+                    );
+                }
+            }
+        }
+
+        let capture = StateCapture::default();
+        for cv in &self.master {
+            let addr = format!("/{}/{}", crate::master::GROUP, cv.control);
+            if let Err(err) = cv
+                .to_control_message(addr)
+                .and_then(|msg| master_controls.control_osc(&msg, &capture))
+            {
+                warn!("Unable to restore master control {}: {err:#}.", cv.control);
+            }
+        }
+
+        presets.restore_saved_state(&self.presets, patch);
+    }
+
+    /// Load show state from the provided path.
+    /// Return None if no state has been saved there yet.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+        let state = serde_yaml::from_reader(file)
+            .with_context(|| format!("parsing show state from {}", path.display()))?;
+        Ok(Some(state))
+    }
+
+    /// Save show state to the provided path.
+    ///
+    /// Write to a temporary file first, so a crash mid-save can't clobber the
+    /// previously-saved state.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        let file =
+            File::create(&tmp_path).with_context(|| format!("creating {}", tmp_path.display()))?;
+        serde_yaml::to_writer(file, self)?;
+        fs::rename(&tmp_path, path).with_context(|| format!("writing {}", path.display()))?;
+        Ok(())
+    }
+}
+
+impl GroupState {
+    /// Capture the current control and animation state of a fixture group.
+    pub fn capture(group: &FixtureGroup) -> Self {
+        let key = group.key();
+        let capture = StateCapture::default();
+        group.emit_state(ChannelStateEmitter::new(None, &capture));
+        let controls = control_values(&fixture_control_addr(key, ""), capture.into_messages());
+        let animations = (0..)
+            .map_while(|i| group.get_animation(i))
+            .map(|ta| AnimationState {
+                animation: ta.anim().clone(),
+                target: ta.target(),
+            })
+            .collect();
+        Self {
+            fixture: key.fixture.to_string(),
+            group: key.group.as_ref().map(|g| g.to_string()),
+            controls,
+            animations,
+            selected_animation: None,
+        }
+    }
+
+    /// Restore this state into the provided fixture group.
+    pub fn restore(&self, group: &mut FixtureGroup) {
+        let capture = StateCapture::default();
+        restore_controls(group, &self.controls, None, &capture);
+        self.restore_animations(group);
+    }
+
+    /// Restore only the animation state into the provided fixture group.
+    pub fn restore_animations(&self, group: &mut FixtureGroup) {
+        for (i, saved) in self.animations.iter().enumerate() {
+            let key = group.key().clone();
+            let Some(ta) = group.get_animation_mut(i) else {
+                warn!("Saved animation {i} for {key} no longer exists.");
+                break;
+            };
+            *ta.anim_mut() = saved.animation.clone();
+            if let Err(err) = ta.set_target(saved.target) {
+                warn!("Unable to restore animation {i} target for {key}: {err:#}.");
+            }
+        }
+    }
+}
+
+/// Apply control values to a fixture group, emitting any resulting talkback.
+/// Controls that fail to apply are logged and skipped.
+pub fn restore_controls<'a>(
+    group: &mut FixtureGroup,
+    controls: impl IntoIterator<Item = &'a ControlValue>,
+    channel: Option<ChannelId>,
+    emitter: &dyn EmitControlMessage,
+) {
+    for cv in controls {
+        let addr = fixture_control_addr(group.key(), &cv.control);
+        if let Err(err) = cv
+            .to_control_message(addr)
+            .and_then(|msg| group.control(&msg, ChannelStateEmitter::new(channel, emitter)))
+        {
+            warn!("Unable to restore control {}: {err:#}.", cv.control);
+        }
+    }
+}
+
+impl ControlValue {
+    /// Create a control message that will set this value at the provided address.
+    pub fn to_control_message(&self, addr: String) -> Result<OscControlMessage> {
+        Ok(OscControlMessage::new(
+            OscMessage {
+                addr,
+                args: vec![OscType::Float(self.value)],
+            },
+            OscClientId::internal(),
+        )?)
+    }
+}
+
+/// Extract float control values from captured OSC messages.
+/// Strip the provided address prefix to get the control path.
+/// Non-float messages, such as text labels, are not state and are ignored.
+pub fn control_values(prefix: &str, msgs: Vec<CapturedMessage>) -> Vec<ControlValue> {
+    msgs.into_iter()
+        .filter_map(|CapturedMessage { msg, kind }| {
+            let control = msg.addr.strip_prefix(prefix)?.to_string();
+            match msg.args.as_slice() {
+                [OscType::Float(value)] => Some(ControlValue {
+                    control,
+                    value: *value,
+                    kind,
+                }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        config::FixtureGroupConfig,
+        fixture::{prelude::FixtureType, FixtureGroupKey, GroupName},
+        show::build_patch,
+    };
+
+    fn dimmer(group: &str, addr: &str) -> FixtureGroupConfig {
+        FixtureGroupConfig {
+            name: "Dimmer".to_string(),
+            addr: Some(serde_yaml::from_str(addr).unwrap()),
+            universe: 0,
+            mirror: false,
+            group: Some(GroupName::new(group.to_string())),
+            options: Default::default(),
+            channel: true,
+        }
+    }
+
+    fn level(patch: &Patch, group: &str) -> Option<f32> {
+        let group = patch
+            .iter()
+            .find(|g| g.key().group.as_deref() == Some(group))
+            .unwrap();
+        GroupState::capture(group)
+            .controls
+            .into_iter()
+            .find(|cv| cv.control == "Level")
+            .map(|cv| cv.value)
+    }
+
+    #[test]
+    fn test_restore_after_patch_reorder() -> Result<()> {
+        let (mut patch, channels) = build_patch(vec![dimmer("a", "1"), dimmer("b", "2")])?;
+        for (group, value) in [("a", 0.25), ("b", 0.75)] {
+            let group = patch.get_mut(&FixtureGroupKey {
+                fixture: FixtureType("Dimmer"),
+                group: Some(GroupName::new(group.to_string())),
+            })?;
+            let msg = ControlValue {
+                control: "Level".to_string(),
+                value,
+                kind: None,
+            }
+            .to_control_message(fixture_control_addr(group.key(), "Level"))?;
+            group.control(
+                &msg,
+                ChannelStateEmitter::new(None, &StateCapture::default()),
+            )?;
+        }
+        let animation_ui_state = AnimationUIState::new(channels.current_channel());
+        let state = ShowState::capture(
+            &patch,
+            &channels,
+            &animation_ui_state,
+            &MasterControls::new(),
+            &Presets::new(),
+        );
+        // Round trip through the saved format.
+        let state: ShowState = serde_yaml::from_str(&serde_yaml::to_string(&state)?)?;
+
+        // Patch the same groups in the opposite order, at other addresses.
+        let (mut patch, channels) = build_patch(vec![dimmer("b", "10"), dimmer("a", "20")])?;
+        let mut animation_ui_state = AnimationUIState::new(channels.current_channel());
+        state.restore(
+            &mut patch,
+            &channels,
+            &mut animation_ui_state,
+            &mut MasterControls::new(),
+            &mut Presets::new(),
+        );
+        assert_eq!(Some(0.25), level(&patch, "a"));
+        assert_eq!(Some(0.75), level(&patch, "b"));
+        Ok(())
+    }
+}
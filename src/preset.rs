@@ -0,0 +1,487 @@
+//! Store and recall looks for fixture groups.
+//!
+//! A preset slot holds captured control and animation state for any number of
+//! fixture groups, using the same capture mechanism as show state persistence.
+//! Storing into a slot from a single channel only overwrites that channel's
+//! entry, so a slot can hold a look for one group or for the whole rig.
+//!
+//! Recall can crossfade: continuous controls are interpolated from their
+//! current values, while discrete controls and animations snap immediately.
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    channel::Channels,
+    control::{EmitControlMessage, SurfaceId},
+    fixture::{FixtureGroup, FixtureGroupKey, Patch},
+    osc::{ControlKind, GroupControlMap, OscControlMessage, ScopedControlEmitter},
+    persist::{restore_controls, ControlValue, GroupState},
+};
+
+/// The number of preset slots.
+pub const N_PRESETS: usize = 16;
+
+pub struct Presets {
+    /// Stored group states, by fixture group and then by preset slot.
+    store: HashMap<FixtureGroupKey, HashMap<usize, GroupState>>,
+    /// Names of presets that have been stored, by slot.
+    names: HashMap<usize, String>,
+    /// Crossfades currently in progress.
+    fades: Vec<Fade>,
+    /// Duration of the crossfade when recalling a preset.
+    fade_time: Duration,
+    /// The name to give the next preset stored, if one has been entered.
+    pending_name: Option<String>,
+    /// If true, store and recall act on every fixture group.
+    /// Otherwise they only act on the group in the selected channel.
+    all_groups: bool,
+    controls: GroupControlMap<ControlMessage>,
+}
+
+impl Presets {
+    pub fn new() -> Self {
+        let mut controls = GroupControlMap::default();
+        Self::map_controls(&mut controls);
+        Self {
+            store: Default::default(),
+            names: Default::default(),
+            fades: Default::default(),
+            fade_time: Duration::ZERO,
+            pending_name: None,
+            all_groups: true,
+            controls,
+        }
+    }
+
+    /// Store the current state of the provided fixture groups into a slot.
+    /// If no name is provided, the slot keeps its existing name.
+    pub fn store<'a>(
+        &mut self,
+        slot: usize,
+        name: Option<String>,
+        groups: impl IntoIterator<Item = &'a FixtureGroup>,
+    ) -> Result<()> {
+        validate_slot(slot)?;
+        for group in groups {
+            self.store
+                .entry(group.key().clone())
+                .or_default()
+                .insert(slot, GroupState::capture(group));
+        }
+        match name {
+            Some(name) => {
+                self.names.insert(slot, name);
+            }
+            None => {
+                self.names
+                    .entry(slot)
+                    .or_insert_with(|| format!("Preset {}", slot + 1));
+            }
+        }
+        Ok(())
+    }
+
+    /// Recall a preset slot into a single fixture group, fading over the
+    /// provided duration.
+    /// Set from_cue if the cue list is recalling the preset, so releasing the
+    /// cue list stops the fade.
+    /// Return false if nothing is stored in that slot for the group.
+    #[allow(clippy::too_many_arguments)]
+    pub fn recall(
+        &mut self,
+        slot: usize,
+        key: &FixtureGroupKey,
+        fade_time: Duration,
+        from_cue: bool,
+        patch: &mut Patch,
+        channels: &Channels,
+        emitter: &dyn EmitControlMessage,
+    ) -> Result<bool> {
+        validate_slot(slot)?;
+        let Some(state) = self.store.get(key).and_then(|slots| slots.get(&slot)) else {
+            return Ok(false);
+        };
+        let group = patch.get_mut(key)?;
+        let channel = channels.visible_channel_for_fixture(key);
+
+        // A new recall replaces any fade already running on this group.
+        self.fades.retain(|fade| &fade.key != key);
+
+        if fade_time.is_zero() {
+            restore_controls(group, &state.controls, channel, emitter);
+            state.restore_animations(group);
+            return Ok(true);
+        }
+
+        let current: HashMap<String, f32> = GroupState::capture(group)
+            .controls
+            .into_iter()
+            .map(|cv| (cv.control, cv.value))
+            .collect();
+        let (continuous, discrete): (Vec<_>, Vec<_>) = state
+            .controls
+            .iter()
+            .partition(|cv| cv.kind.is_some_and(|kind| kind.is_continuous()));
+
+        restore_controls(group, discrete, channel, emitter);
+        state.restore_animations(group);
+
+        let controls = continuous
+            .into_iter()
+            .filter_map(|cv| {
+                let start = current.get(&cv.control).copied().unwrap_or(cv.value);
+                Some(FadingControl {
+                    control: cv.control.clone(),
+                    kind: cv.kind?,
+                    start,
+                    end: cv.value,
+                    last: start,
+                })
+            })
+            .collect();
+        self.fades.push(Fade {
+            key: key.clone(),
+            from_cue,
+            controls,
+            elapsed: Duration::ZERO,
+            duration: fade_time,
+        });
+        Ok(true)
+    }
+
+    /// Recall a preset slot into every fixture group it holds state for.
+    pub fn recall_all(
+        &mut self,
+        slot: usize,
+        fade_time: Duration,
+        from_cue: bool,
+        patch: &mut Patch,
+        channels: &Channels,
+        emitter: &dyn EmitControlMessage,
+    ) -> Result<()> {
+        validate_slot(slot)?;
+        let keys: Vec<_> = self
+            .store
+            .iter()
+            .filter(|(_, slots)| slots.contains_key(&slot))
+            .map(|(key, _)| key.clone())
+            .collect();
+        if keys.is_empty() {
+            bail!("preset {} is empty", slot + 1);
+        }
+        for key in keys {
+            if let Err(err) = self.recall(slot, &key, fade_time, from_cue, patch, channels, emitter)
+            {
+                error!("Failed to recall preset {} for {key}: {err:#}.", slot + 1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop stored state for any fixture group that is no longer patched.
+    pub fn retain_patched(&mut self, patch: &Patch) {
+        self.store.retain(|key, _| patch.get(key).is_ok());
+        self.fades.retain(|fade| patch.get(&fade.key).is_ok());
+    }
+
+    /// Stop every crossfade started by the cue list, leaving controls where
+    /// they are.
+    pub fn cancel_cue_fades(&mut self) {
+        self.fades.retain(|fade| !fade.from_cue);
+    }
+
+    /// Look up a preset slot by name.
+    pub fn slot_by_name(&self, name: &str) -> Option<usize> {
+        self.names
+            .iter()
+            .find_map(|(slot, n)| (n == name).then_some(*slot))
+    }
+
+    /// Advance any crossfades in progress.
+    pub fn update(
+        &mut self,
+        delta_t: Duration,
+        patch: &mut Patch,
+        channels: &Channels,
+        emitter: &dyn EmitControlMessage,
+    ) {
+        self.fades.retain_mut(|fade| {
+            fade.elapsed += delta_t;
+            let alpha = fade.alpha();
+            let group = match patch.get_mut(&fade.key) {
+                Ok(group) => group,
+                Err(err) => {
+                    error!("Cancelling preset fade: {err:#}.");
+                    return false;
+                }
+            };
+            // Only apply the controls that moved in this step, so that
+            // talkback isn't sent for every fading control on every update.
+            let values: Vec<_> = fade
+                .controls
+                .iter_mut()
+                .filter_map(|c| {
+                    let value = c.value(alpha);
+                    if value == c.last {
+                        return None;
+                    }
+                    c.last = value;
+                    Some(ControlValue {
+                        control: c.control.clone(),
+                        value,
+                        kind: Some(c.kind),
+                    })
+                })
+                .collect();
+            restore_controls(
+                group,
+                &values,
+                channels.visible_channel_for_fixture(&fade.key),
+                emitter,
+            );
+            alpha < 1.0
+        });
+    }
+
+    /// Return the names of all OSC controls.
+    pub fn control_names(&self) -> impl Iterator<Item = &str> {
+        self.controls.control_names()
+    }
+
+    /// Emit all current preset state.
+    pub fn emit_state(&self, emitter: &dyn EmitControlMessage) {
+        let emitter = &ScopedControlEmitter {
+            entity: crate::osc::presets::GROUP,
+            emitter,
+        };
+        Self::emit_osc_state_change(StateChange::FadeTime(self.fade_time), emitter);
+        Self::emit_osc_state_change(StateChange::AllGroups(self.all_groups), emitter);
+        Self::emit_osc_state_change(StateChange::Labels(self.labels()), emitter);
+        Self::emit_osc_state_change(
+            StateChange::Name(self.pending_name.clone().unwrap_or_default()),
+            emitter,
+        );
+    }
+
+    /// Handle a OSC control message.
+    pub fn control_osc(
+        &mut self,
+        msg: &OscControlMessage,
+        surface: Option<&SurfaceId>,
+        patch: &mut Patch,
+        channels: &Channels,
+        emitter: &dyn EmitControlMessage,
+    ) -> Result<()> {
+        let Some((ctl, talkback)) = self.controls.handle(msg)? else {
+            return Ok(());
+        };
+        emitter.set_talkback(talkback);
+        self.control(&ctl, surface, patch, channels, emitter)
+    }
+
+    /// Handle a typed control message.
+    /// Presets for the selected group use the channel selected by the
+    /// provided control surface.
+    pub fn control(
+        &mut self,
+        msg: &ControlMessage,
+        surface: Option<&SurfaceId>,
+        patch: &mut Patch,
+        channels: &Channels,
+        emitter: &dyn EmitControlMessage,
+    ) -> Result<()> {
+        let scoped_emitter = &ScopedControlEmitter {
+            entity: crate::osc::presets::GROUP,
+            emitter,
+        };
+        match msg {
+            ControlMessage::Store(slot) => {
+                let name = self.pending_name.clone();
+                if self.all_groups {
+                    self.store(*slot, name, patch.iter())?;
+                } else {
+                    let group = selected_group(patch, channels, surface)?;
+                    self.store(*slot, name, [group])?;
+                }
+                // The entered name is used up once stored.
+                self.pending_name = None;
+                Self::emit_osc_state_change(StateChange::Labels(self.labels()), scoped_emitter);
+                Self::emit_osc_state_change(StateChange::Name(String::new()), scoped_emitter);
+            }
+            ControlMessage::Recall(slot) => {
+                if self.all_groups {
+                    self.recall_all(*slot, self.fade_time, false, patch, channels, emitter)?;
+                } else {
+                    let key = selected_group(patch, channels, surface)?.key().clone();
+                    if !self.recall(*slot, &key, self.fade_time, false, patch, channels, emitter)? {
+                        bail!("preset {} has nothing stored for {key}", slot + 1);
+                    }
+                }
+            }
+            ControlMessage::FadeTime(t) => {
+                self.fade_time = *t;
+                Self::emit_osc_state_change(StateChange::FadeTime(*t), scoped_emitter);
+            }
+            ControlMessage::AllGroups(v) => {
+                self.all_groups = *v;
+                Self::emit_osc_state_change(StateChange::AllGroups(*v), scoped_emitter);
+            }
+            ControlMessage::Name(name) => {
+                let name = name.trim();
+                self.pending_name = (!name.is_empty()).then(|| name.to_string());
+                Self::emit_osc_state_change(StateChange::Name(name.to_string()), scoped_emitter);
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the label for every preset slot; empty slots are blank.
+    fn labels(&self) -> Vec<String> {
+        (0..N_PRESETS)
+            .map(|slot| self.names.get(&slot).cloned().unwrap_or_default())
+            .collect()
+    }
+
+    /// Return all stored presets in a form suitable for persistence.
+    pub fn saved_state(&self) -> Vec<PresetState> {
+        let mut presets: Vec<_> = self
+            .names
+            .iter()
+            .map(|(slot, name)| PresetState {
+                slot: *slot,
+                name: name.clone(),
+                groups: self
+                    .store
+                    .values()
+                    .filter_map(|slots| slots.get(slot).cloned())
+                    .collect(),
+            })
+            .collect();
+        presets.sort_by_key(|p| p.slot);
+        presets
+    }
+
+    /// Load persisted presets.
+    /// State for groups that are no longer patched is dropped.
+    pub fn restore_saved_state(&mut self, presets: &[PresetState], patch: &Patch) {
+        for preset in presets {
+            if validate_slot(preset.slot).is_err() {
+                error!(
+                    "Ignoring saved preset in out-of-range slot {}.",
+                    preset.slot
+                );
+                continue;
+            }
+            self.names.insert(preset.slot, preset.name.clone());
+            for state in &preset.groups {
+                let Some(fixture) = patch.lookup_fixture_type(&state.fixture) else {
+                    continue;
+                };
+                let key = FixtureGroupKey {
+                    fixture,
+                    group: state.group.as_deref().map(crate::fixture::GroupName::new),
+                };
+                if patch.get(&key).is_err() {
+                    continue;
+                }
+                self.store
+                    .entry(key)
+                    .or_default()
+                    .insert(preset.slot, state.clone());
+            }
+        }
+    }
+}
+
+/// Get the fixture group in the channel selected by the provided control surface.
+fn selected_group<'a>(
+    patch: &'a Patch,
+    channels: &Channels,
+    surface: Option<&SurfaceId>,
+) -> Result<&'a FixtureGroup> {
+    let channel = channels
+        .selected_channel(surface)
+        .ok_or_else(|| anyhow!("no channel is selected"))?;
+    channels.group_by_channel(patch, channel)
+}
+
+fn validate_slot(slot: usize) -> Result<()> {
+    if slot >= N_PRESETS {
+        bail!("preset slot {slot} out of range (max {})", N_PRESETS - 1);
+    }
+    Ok(())
+}
+
+/// A crossfade in progress for a single fixture group.
+struct Fade {
+    key: FixtureGroupKey,
+    /// True if the cue list started this fade.
+    from_cue: bool,
+    controls: Vec<FadingControl>,
+    elapsed: Duration,
+    duration: Duration,
+}
+
+impl Fade {
+    /// Return the fraction of the fade that has completed, in [0, 1].
+    fn alpha(&self) -> f32 {
+        (self.elapsed.as_secs_f32() / self.duration.as_secs_f32()).min(1.0)
+    }
+}
+
+/// A single continuous control being crossfaded.
+struct FadingControl {
+    control: String,
+    kind: ControlKind,
+    start: f32,
+    end: f32,
+    /// The value most recently applied.
+    last: f32,
+}
+
+impl FadingControl {
+    /// Interpolate this control's value at the provided fade fraction.
+    fn value(&self, alpha: f32) -> f32 {
+        if self.kind == ControlKind::Phase {
+            // Take the short way around the circle.
+            let mut delta = self.end - self.start;
+            if delta > 0.5 {
+                delta -= 1.0;
+            } else if delta < -0.5 {
+                delta += 1.0;
+            }
+            return (self.start + delta * alpha).rem_euclid(1.0);
+        }
+        self.start + (self.end - self.start) * alpha
+    }
+}
+
+/// The persisted state of a single preset slot.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PresetState {
+    pub slot: usize,
+    pub name: String,
+    #[serde(default)]
+    pub groups: Vec<GroupState>,
+}
+
+#[derive(Debug, Clone)]
+pub enum ControlMessage {
+    Store(usize),
+    Recall(usize),
+    FadeTime(Duration),
+    AllGroups(bool),
+    /// Name the next preset to be stored.
+    Name(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum StateChange {
+    FadeTime(Duration),
+    AllGroups(bool),
+    Labels(Vec<String>),
+    Name(String),
+}
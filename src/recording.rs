@@ -0,0 +1,335 @@
+//! Record the show's DMX output to a file, and play recordings back.
+//!
+//! A recording starts with a header giving the number of universes, followed
+//! by fixed-size frames: a little-endian u64 timestamp in microseconds since
+//! the start of the recording, then 512 bytes for each universe. A frame is
+//! only written when some universe has changed, and holds until the next one.
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver},
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use log::{error, info};
+
+use crate::dmx::{DmxBuffer, DmxOutput};
+
+const MAGIC: &[u8; 8] = b"COMETREC";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 2;
+
+fn frame_len(universe_count: usize) -> usize {
+    8 + universe_count * 512
+}
+
+/// Write DMX frames to a recording.
+pub struct Recorder {
+    file: BufWriter<File>,
+    start: Instant,
+    last_frame: Vec<DmxBuffer>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, universe_count: usize) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("creating DMX recording {}", path.display()))?;
+        let mut file = BufWriter::new(file);
+        file.write_all(MAGIC)?;
+        file.write_all(&[VERSION])?;
+        file.write_all(&(universe_count as u16).to_le_bytes())?;
+        info!("Recording DMX to {}.", path.display());
+        Ok(Self {
+            file,
+            start: Instant::now(),
+            last_frame: Vec::new(),
+        })
+    }
+
+    /// Record the current state of every universe, if anything has changed.
+    pub fn record(&mut self, buffers: &[DmxBuffer]) -> Result<()> {
+        if self.last_frame == buffers {
+            return Ok(());
+        }
+        self.write(buffers)?;
+        self.last_frame = buffers.to_vec();
+        Ok(())
+    }
+
+    /// Write the final frame again to mark the end of the recording, and flush.
+    pub fn finish(mut self) -> Result<()> {
+        let last_frame = std::mem::take(&mut self.last_frame);
+        if !last_frame.is_empty() {
+            self.write(&last_frame)?;
+        }
+        self.file.flush()?;
+        Ok(())
+    }
+
+    fn write(&mut self, buffers: &[DmxBuffer]) -> Result<()> {
+        let timestamp = self.start.elapsed().as_micros() as u64;
+        self.file.write_all(&timestamp.to_le_bytes())?;
+        for buffer in buffers {
+            self.file.write_all(buffer)?;
+        }
+        Ok(())
+    }
+}
+
+/// Play back a recording.
+pub struct Player {
+    file: BufReader<File>,
+    universe_count: usize,
+    /// The timestamp of every frame in the recording.
+    timestamps: Vec<Duration>,
+    position: Duration,
+    speed: f64,
+    looping: bool,
+}
+
+impl Player {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("opening DMX recording {}", path.display()))?;
+        let mut file = BufReader::new(file);
+        let mut header = [0; HEADER_LEN];
+        file.read_exact(&mut header)
+            .context("reading DMX recording header")?;
+        ensure!(
+            &header[..MAGIC.len()] == MAGIC,
+            "{} is not a DMX recording",
+            path.display()
+        );
+        ensure!(
+            header[MAGIC.len()] == VERSION,
+            "unsupported DMX recording version {}",
+            header[MAGIC.len()]
+        );
+        let universe_count =
+            u16::from_le_bytes([header[MAGIC.len() + 1], header[MAGIC.len() + 2]]) as usize;
+
+        // Index the frame timestamps so we can seek without reading frames.
+        let len = file.get_ref().metadata()?.len() as usize - HEADER_LEN;
+        let frame_count = len / frame_len(universe_count);
+        let mut timestamps = Vec::with_capacity(frame_count);
+        for i in 0..frame_count {
+            let mut timestamp = [0; 8];
+            file.seek(SeekFrom::Start(frame_offset(universe_count, i)))?;
+            file.read_exact(&mut timestamp)?;
+            timestamps.push(Duration::from_micros(u64::from_le_bytes(timestamp)));
+        }
+        ensure!(!timestamps.is_empty(), "{} is empty", path.display());
+        info!(
+            "Loaded {} universes, {} frames, {:.1} seconds from {}.",
+            universe_count,
+            frame_count,
+            timestamps[frame_count - 1].as_secs_f64(),
+            path.display()
+        );
+        Ok(Self {
+            file,
+            universe_count,
+            timestamps,
+            position: Duration::ZERO,
+            speed: 1.0,
+            looping: false,
+        })
+    }
+
+    pub fn universe_count(&self) -> usize {
+        self.universe_count
+    }
+
+    /// The length of the recording.
+    pub fn duration(&self) -> Duration {
+        *self.timestamps.last().unwrap()
+    }
+
+    pub fn seek(&mut self, position: Duration) {
+        self.position = position.min(self.duration());
+    }
+
+    pub fn set_speed(&mut self, speed: f64) -> Result<()> {
+        ensure!(
+            speed.is_finite() && speed >= 0.0,
+            "invalid playback speed {speed}"
+        );
+        self.speed = speed;
+        Ok(())
+    }
+
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    /// Return true if playback has reached the end and isn't looping.
+    pub fn finished(&self) -> bool {
+        !self.looping && self.position >= self.duration()
+    }
+
+    /// Advance the playback position.
+    pub fn update(&mut self, delta_t: Duration) {
+        self.position += delta_t.mul_f64(self.speed);
+        let duration = self.duration();
+        if self.position < duration {
+            return;
+        }
+        if self.looping && !duration.is_zero() {
+            self.position =
+                Duration::from_nanos((self.position.as_nanos() % duration.as_nanos()) as u64);
+        } else {
+            self.position = duration;
+        }
+    }
+
+    /// Read the frame at the current position into buffers.
+    pub fn render(&mut self, buffers: &mut [DmxBuffer]) -> Result<()> {
+        let index = self
+            .timestamps
+            .partition_point(|t| *t <= self.position)
+            .saturating_sub(1);
+        self.file.seek(SeekFrom::Start(
+            frame_offset(self.universe_count, index) + 8,
+        ))?;
+        for buffer in buffers.iter_mut().take(self.universe_count) {
+            self.file.read_exact(buffer)?;
+        }
+        Ok(())
+    }
+
+    /// Play back in the current thread until the recording ends or the stop
+    /// flag is set.
+    ///
+    /// Playback can be controlled by typing commands on stdin:
+    /// "seek <seconds>", "speed <multiplier>", "loop on" and "loop off".
+    pub fn run(
+        &mut self,
+        dmx_ports: &mut [Box<dyn DmxOutput>],
+        update_interval: Duration,
+        stop: &AtomicBool,
+    ) {
+        let commands = read_commands();
+        let mut buffers = vec![[0u8; 512]; dmx_ports.len()];
+        let mut last_update = Instant::now();
+        while !stop.load(Ordering::Relaxed) && !self.finished() {
+            for cmd in commands.try_iter() {
+                if let Err(err) = self.command(&cmd) {
+                    error!("Playback command \"{cmd}\": {err:#}.");
+                }
+            }
+            let now = Instant::now();
+            self.update(now - last_update);
+            last_update = now;
+
+            if let Err(err) = self.render(&mut buffers) {
+                error!("Failed to read DMX recording: {err:#}.");
+                break;
+            }
+            for (port, buffer) in dmx_ports.iter_mut().zip(&buffers) {
+                if let Err(e) = port.write(buffer) {
+                    error!("DMX write error: {e:#}.");
+                }
+            }
+            std::thread::sleep(update_interval);
+        }
+        info!("Playback stopped.");
+        for port in dmx_ports.iter_mut() {
+            port.close();
+        }
+    }
+
+    fn command(&mut self, cmd: &str) -> Result<()> {
+        let mut words = cmd.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("seek"), Some(secs)) => {
+                self.seek(Duration::try_from_secs_f64(secs.parse()?)?);
+            }
+            (Some("speed"), Some(speed)) => self.set_speed(speed.parse()?)?,
+            (Some("loop"), Some("on")) => self.set_looping(true),
+            (Some("loop"), Some("off")) => self.set_looping(false),
+            _ => bail!("unknown command"),
+        }
+        info!(
+            "Playback at {:.1}s, speed {}, looping {}.",
+            self.position.as_secs_f64(),
+            self.speed,
+            self.looping
+        );
+        Ok(())
+    }
+}
+
+fn frame_offset(universe_count: usize, index: usize) -> u64 {
+    (HEADER_LEN + index * frame_len(universe_count)) as u64
+}
+
+/// Forward lines from stdin in a new thread.
+fn read_commands() -> Receiver<String> {
+    let (send, recv) = channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                return;
+            };
+            if send.send(line).is_err() {
+                return;
+            }
+        }
+    });
+    recv
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(v: u8) -> Vec<DmxBuffer> {
+        vec![[v; 512], [v + 1; 512]]
+    }
+
+    fn position(player: &mut Player) -> u8 {
+        let mut buffers = frame(0);
+        player.render(&mut buffers).unwrap();
+        assert_eq!(buffers[0][0] + 1, buffers[1][0]);
+        buffers[0][0]
+    }
+
+    #[test]
+    fn test_record_and_play() {
+        let path = std::env::temp_dir().join(format!("comet-{}.rec", std::process::id()));
+        let mut recorder = Recorder::create(&path, 2).unwrap();
+        recorder.record(&frame(1)).unwrap();
+        recorder.record(&frame(1)).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        recorder.record(&frame(2)).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        recorder.finish().unwrap();
+
+        let mut player = Player::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // Unchanged frames are not recorded, plus the end marker.
+        assert_eq!(3, player.timestamps.len());
+        assert_eq!(2, player.universe_count());
+        assert_eq!(1, position(&mut player));
+
+        let second = player.timestamps[1];
+        player.seek(second);
+        assert_eq!(2, position(&mut player));
+
+        player.set_speed(2.0).unwrap();
+        player.update(player.duration());
+        assert!(player.finished());
+        assert_eq!(2, position(&mut player));
+
+        player.set_looping(true);
+        player.seek(Duration::ZERO);
+        player.update(player.duration() + Duration::from_micros(1));
+        assert!(!player.finished());
+        assert_eq!(1, position(&mut player));
+    }
+}
@@ -0,0 +1,345 @@
+//! Send DMX universes over the network as streaming ACN (E1.31).
+use std::{
+    fmt::Write,
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use log::{error, info};
+use serde::Deserialize;
+
+use crate::dmx::{DmxBuffer, DmxOutput, UniverseIdx};
+
+/// The standard sACN UDP port.
+pub const SACN_PORT: u16 = 5568;
+
+/// Resend unchanged frames this often so receivers don't time out.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of stream-terminated packets to send when closing a universe.
+const TERMINATE_COUNT: usize = 3;
+
+const PACKET_LEN: usize = 638;
+const ACN_PACKET_ID: &[u8; 12] = b"ASC-E1.17\0\0\0";
+const VECTOR_ROOT_E131_DATA: u32 = 0x0000_0004;
+const VECTOR_E131_DATA_PACKET: u32 = 0x0000_0002;
+const VECTOR_DMP_SET_PROPERTY: u8 = 0x02;
+const OPTION_STREAM_TERMINATED: u8 = 0x40;
+const SOURCE_NAME_LEN: usize = 64;
+const MAX_PRIORITY: u8 = 200;
+const MAX_UNIVERSE: u16 = 63999;
+
+/// Configure sACN output.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SacnConfig {
+    /// The name of this source, as shown by receivers.
+    #[serde(default = "default_source_name")]
+    pub source_name: String,
+    /// The component identifier of this source, as a UUID string.
+    /// If not provided, a random CID is generated at startup; provide one to
+    /// keep the same identity across restarts.
+    #[serde(default)]
+    pub cid: Option<String>,
+    /// Priority of this source, 0-200.
+    #[serde(default = "default_priority")]
+    pub priority: u8,
+    pub universes: Vec<SacnUniverseConfig>,
+}
+
+/// Send a show universe to an sACN universe.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SacnUniverseConfig {
+    /// The show universe to send.
+    pub universe: UniverseIdx,
+    /// The sACN universe number, 1-63999.
+    pub sacn_universe: u16,
+    /// Send to this address rather than the universe's multicast group.
+    #[serde(default)]
+    pub destination: Option<IpAddr>,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Override the source priority for this universe.
+    #[serde(default)]
+    pub priority: Option<u8>,
+}
+
+impl SacnConfig {
+    /// Parse the configured CID, or generate a random one.
+    pub fn cid(&self) -> Result<[u8; 16]> {
+        let Some(cid) = &self.cid else {
+            let cid = rand::random();
+            info!("Using generated sACN CID {}.", format_cid(&cid));
+            return Ok(cid);
+        };
+        parse_cid(cid).with_context(|| format!("parsing sACN CID \"{cid}\""))
+    }
+
+    /// Open an output for every configured universe.
+    pub fn outputs(&self) -> Result<Vec<(UniverseIdx, SacnOutput)>> {
+        let cid = self.cid()?;
+        self.universes
+            .iter()
+            .map(|u| Ok((u.universe, SacnOutput::new(self, u, cid)?)))
+            .collect()
+    }
+}
+
+/// Send a single universe as E1.31 data packets.
+pub struct SacnOutput {
+    socket: UdpSocket,
+    destination: SocketAddr,
+    /// The packet template; everything except sequence, options and data is
+    /// fixed for the life of the output.
+    packet: [u8; PACKET_LEN],
+    sequence: u8,
+    last_frame: Option<DmxBuffer>,
+    last_send: Instant,
+}
+
+impl SacnOutput {
+    pub fn new(cfg: &SacnConfig, universe: &SacnUniverseConfig, cid: [u8; 16]) -> Result<Self> {
+        let priority = universe.priority.unwrap_or(cfg.priority);
+        ensure!(
+            priority <= MAX_PRIORITY,
+            "sACN priority {priority} is out of range"
+        );
+        ensure!(
+            (1..=MAX_UNIVERSE).contains(&universe.sacn_universe),
+            "sACN universe {} is out of range",
+            universe.sacn_universe
+        );
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).context("binding sACN socket")?;
+        let destination = SocketAddr::new(
+            universe
+                .destination
+                .unwrap_or_else(|| IpAddr::V4(multicast_addr(universe.sacn_universe))),
+            universe.port,
+        );
+        info!(
+            "Sending universe {} to sACN universe {} at {destination}.",
+            universe.universe, universe.sacn_universe
+        );
+        Ok(Self {
+            socket,
+            destination,
+            packet: packet_template(&cid, &cfg.source_name, priority, universe.sacn_universe),
+            sequence: 0,
+            last_frame: None,
+            last_send: Instant::now(),
+        })
+    }
+
+    fn send(&mut self, frame: &DmxBuffer, options: u8) -> Result<()> {
+        self.packet[111] = self.sequence;
+        self.sequence = self.sequence.wrapping_add(1);
+        self.packet[112] = options;
+        self.packet[126..].copy_from_slice(frame);
+        self.socket
+            .send_to(&self.packet, self.destination)
+            .with_context(|| format!("sending sACN to {}", self.destination))?;
+        self.last_send = Instant::now();
+        Ok(())
+    }
+}
+
+impl DmxOutput for SacnOutput {
+    /// Send the frame if it has changed, or if the keepalive interval has
+    /// elapsed since the last send.
+    fn write(&mut self, frame: &DmxBuffer) -> Result<()> {
+        let changed = self.last_frame.as_ref() != Some(frame);
+        if !changed && self.last_send.elapsed() < KEEPALIVE_INTERVAL {
+            return Ok(());
+        }
+        self.send(frame, 0)?;
+        self.last_frame = Some(*frame);
+        Ok(())
+    }
+
+    /// Tell receivers this stream has ended so they can release the universe
+    /// immediately rather than waiting for it to time out.
+    fn close(&mut self) {
+        let frame = self.last_frame.unwrap_or([0; 512]);
+        for _ in 0..TERMINATE_COUNT {
+            if let Err(err) = self.send(&frame, OPTION_STREAM_TERMINATED) {
+                error!("{err:#}.");
+                return;
+            }
+        }
+    }
+}
+
+/// The contents of an E1.31 data packet received from the network.
+pub struct SacnData<'a> {
+    pub universe: u16,
+    /// True if the source has announced that it is no longer sending.
+    pub terminated: bool,
+    pub data: &'a [u8],
+}
+
+/// Parse an E1.31 data packet.
+/// Return None if the packet is not a data packet with a null start code.
+pub fn parse_data(packet: &[u8]) -> Option<SacnData<'_>> {
+    if packet.len() < 126 || &packet[4..16] != ACN_PACKET_ID {
+        return None;
+    }
+    let vector = |start: usize| u32::from_be_bytes(packet[start..start + 4].try_into().unwrap());
+    if vector(18) != VECTOR_ROOT_E131_DATA || vector(40) != VECTOR_E131_DATA_PACKET {
+        return None;
+    }
+    if packet[125] != 0 {
+        return None;
+    }
+    // The property count includes the start code.
+    let count = u16::from_be_bytes([packet[123], packet[124]]) as usize;
+    let data = packet.get(126..125 + count.clamp(1, 513))?;
+    Some(SacnData {
+        universe: u16::from_be_bytes([packet[113], packet[114]]),
+        terminated: packet[112] & OPTION_STREAM_TERMINATED != 0,
+        data,
+    })
+}
+
+/// Return the multicast group for an sACN universe.
+pub fn multicast_addr(universe: u16) -> Ipv4Addr {
+    let [hi, lo] = universe.to_be_bytes();
+    Ipv4Addr::new(239, 255, hi, lo)
+}
+
+/// Build a full-universe data packet with empty sequence, options and data.
+fn packet_template(
+    cid: &[u8; 16],
+    source_name: &str,
+    priority: u8,
+    universe: u16,
+) -> [u8; PACKET_LEN] {
+    let flags_and_length = |start: usize| (0x7000 | (PACKET_LEN - start) as u16).to_be_bytes();
+    let mut p = [0; PACKET_LEN];
+    // Root layer
+    p[0..2].copy_from_slice(&0x0010u16.to_be_bytes()); // preamble size
+    p[4..16].copy_from_slice(ACN_PACKET_ID);
+    p[16..18].copy_from_slice(&flags_and_length(16));
+    p[18..22].copy_from_slice(&VECTOR_ROOT_E131_DATA.to_be_bytes());
+    p[22..38].copy_from_slice(cid);
+    // Framing layer
+    p[38..40].copy_from_slice(&flags_and_length(38));
+    p[40..44].copy_from_slice(&VECTOR_E131_DATA_PACKET.to_be_bytes());
+    // The source name is null-terminated, so truncate to leave room.
+    let name = truncate_utf8(source_name, SOURCE_NAME_LEN - 1);
+    p[44..44 + name.len()].copy_from_slice(name.as_bytes());
+    p[108] = priority;
+    p[113..115].copy_from_slice(&universe.to_be_bytes());
+    // DMP layer
+    p[115..117].copy_from_slice(&flags_and_length(115));
+    p[117] = VECTOR_DMP_SET_PROPERTY;
+    p[118] = 0xa1; // address and data type
+    p[121..123].copy_from_slice(&1u16.to_be_bytes()); // address increment
+    p[123..125].copy_from_slice(&513u16.to_be_bytes()); // start code plus slots
+    p
+}
+
+/// Truncate a string to at most len bytes, on a character boundary.
+fn truncate_utf8(s: &str, len: usize) -> &str {
+    let mut end = s.len().min(len);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+fn parse_cid(s: &str) -> Result<[u8; 16]> {
+    let hex: String = s.chars().filter(|c| *c != '-').collect();
+    if hex.len() != 32 || !hex.is_ascii() {
+        bail!("expected 32 hex digits");
+    }
+    let mut cid = [0; 16];
+    for (i, byte) in cid.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)?;
+    }
+    Ok(cid)
+}
+
+fn format_cid(cid: &[u8; 16]) -> String {
+    let mut s = String::new();
+    for (i, byte) in cid.iter().enumerate() {
+        if [4, 6, 8, 10].contains(&i) {
+            s.push('-');
+        }
+        write!(s, "{byte:02x}").unwrap();
+    }
+    s
+}
+
+fn default_source_name() -> String {
+    "comet".to_string()
+}
+
+const fn default_priority() -> u8 {
+    100
+}
+
+const fn default_port() -> u16 {
+    SACN_PORT
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cid_round_trip() {
+        let cid = "6ba7b810-9dad-11d1-80b4-00c04fd430c8";
+        assert_eq!(cid, format_cid(&parse_cid(cid).unwrap()));
+        assert!(parse_cid("6ba7b810").is_err());
+    }
+
+    #[test]
+    fn test_send_and_terminate() {
+        let listener = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let cfg = SacnConfig {
+            source_name: "test".to_string(),
+            cid: None,
+            priority: 150,
+            universes: vec![SacnUniverseConfig {
+                universe: 0,
+                sacn_universe: 258,
+                destination: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+                port: listener.local_addr().unwrap().port(),
+                priority: None,
+            }],
+        };
+        let (_, mut output) = cfg.outputs().unwrap().pop().unwrap();
+        let mut frame = [0; 512];
+        frame[0] = 10;
+        frame[511] = 20;
+        output.write(&frame).unwrap();
+
+        let mut buf = [0; 1024];
+        let (len, _) = listener.recv_from(&mut buf).unwrap();
+        assert_eq!(PACKET_LEN, len);
+        assert_eq!(ACN_PACKET_ID, &buf[4..16]);
+        assert_eq!(b"test\0", &buf[44..49]);
+        assert_eq!(150, buf[108]);
+        assert_eq!(0, buf[111]);
+        assert_eq!(0, buf[112]);
+        assert_eq!([1, 2], buf[113..115]);
+        assert_eq!(10, buf[126]);
+        assert_eq!(20, buf[637]);
+
+        let parsed = parse_data(&buf[..len]).unwrap();
+        assert_eq!(258, parsed.universe);
+        assert!(!parsed.terminated);
+        assert_eq!(&frame[..], parsed.data);
+
+        output.close();
+        for seq in 1..=TERMINATE_COUNT as u8 {
+            let (len, _) = listener.recv_from(&mut buf).unwrap();
+            assert_eq!(seq, buf[111]);
+            assert!(parse_data(&buf[..len]).unwrap().terminated);
+            assert_eq!(10, buf[126]);
+        }
+    }
+}
@@ -1,24 +1,37 @@
-use std::time::{Duration, Instant};
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
 
 use crate::{
     animation::AnimationUIState,
     channel::{ChannelStateEmitter, Channels},
     clock_service::ClockService,
-    config::Config,
-    control::{ControlMessage, Controller},
-    dmx::DmxBuffer,
+    config::{Config, FixtureGroupConfig},
+    control::{
+        CapturedMessage, ControlMessage, Controller, EmitControlMessage, StateCapture, SurfaceId,
+    },
+    cue::CueList,
+    dmx::{DmxBuffer, DmxOutput},
     fixture::{FixtureGroupKey, GroupName, Patch},
+    http::{HttpRequest, Query},
+    journal::{ClockSource, JournalHeader, JournalMessage, JournalReader, JournalWriter},
     master::MasterControls,
-    midi::{MidiControlMessage, MidiHandler},
-    osc::{GroupControlMap, OscControlMessage, ScopedControlEmitter},
+    merge::DmxMerge,
+    midi::MidiControlMessage,
+    midi_clock::MidiClock,
+    osc::{EmitOscMessage, GroupControlMap, OscControlMessage, ScopedControlEmitter, TalkbackMode},
+    persist::{GroupState, ShowState},
+    preset::Presets,
+    recording::Recorder,
     wled::WledResponse,
 };
 
 pub use crate::channel::ChannelId;
-use anyhow::{bail, Result};
-use log::error;
+use anyhow::{bail, ensure, Context, Result};
+use log::{error, info};
 use number::UnipolarFloat;
-use rust_dmx::DmxPort;
 use tunnels::{
     audio::AudioInput,
     clock_bank::ClockBank,
@@ -28,10 +41,24 @@ use tunnels::{
 pub struct Show {
     controller: Controller,
     patch: Patch,
+    /// The fixture groups the patch was built from.
+    fixtures: Vec<FixtureGroupConfig>,
     channels: Channels,
     master_controls: MasterControls,
     animation_ui_state: AnimationUIState,
+    presets: Presets,
+    cues: CueList,
     clocks: Clocks,
+    dmx_merge: Option<DmxMerge>,
+    state_file: Option<PathBuf>,
+    record_file: Option<PathBuf>,
+    journal_file: Option<PathBuf>,
+    journal: Option<JournalWriter>,
+    /// The number of updates computed since the show started running.
+    update_count: u64,
+    /// True while replaying a journal, which provides any patch reloads.
+    replaying: bool,
+    config_path: PathBuf,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -40,27 +67,57 @@ pub enum Clocks {
     Internal {
         clocks: ClockBank,
         audio_input: AudioInput,
+        /// The audio device the input listens to, if any.
+        audio_device: Option<String>,
         audio_controls: GroupControlMap<tunnels::audio::ControlMessage>,
+        /// Clocks slaved to MIDI clock, if configured.
+        midi_clock: Option<MidiClock>,
     },
 }
 
 impl Clocks {
+    /// Return where the clocks come from, for the journal.
+    pub fn source(&self) -> ClockSource {
+        match self {
+            Self::Service(_) => ClockSource::Service,
+            Self::Internal {
+                midi_clock: Some(_),
+                ..
+            } => ClockSource::MidiClock,
+            Self::Internal {
+                audio_device: Some(_),
+                ..
+            } => ClockSource::Audio,
+            Self::Internal { .. } => ClockSource::Internal,
+        }
+    }
+
     pub fn get(&self) -> SharedClockData {
         match self {
             Self::Service(service) => service.get(),
             Self::Internal {
                 clocks,
                 audio_input,
+                midi_clock,
                 ..
-            } => SharedClockData {
-                clock_bank: StaticClockBank(clocks.as_static()),
-                audio_envelope: audio_input.envelope(),
-            },
+            } => {
+                let mut clock_bank = StaticClockBank(clocks.as_static());
+                if let Some(midi_clock) = midi_clock {
+                    midi_clock.slave(&mut clock_bank);
+                }
+                SharedClockData {
+                    clock_bank,
+                    audio_envelope: audio_input.envelope(),
+                }
+            }
         }
     }
 
     /// Emit all current audio and clock state.
-    pub fn emit_state(&self, emitter: &mut Controller) {
+    pub fn emit_state<E>(&self, emitter: &mut E)
+    where
+        E: tunnels::audio::EmitStateChange + tunnels::clock_bank::EmitStateChange,
+    {
         let Self::Internal {
             clocks,
             audio_input,
@@ -78,56 +135,96 @@ impl Clocks {
         if let Clocks::Internal {
             clocks,
             audio_input,
+            midi_clock,
             ..
         } = self
         {
             audio_input.update_state(delta_t, controller);
             let audio_envelope = audio_input.envelope();
             clocks.update_state(delta_t, audio_envelope, controller);
+            if let Some(midi_clock) = midi_clock {
+                midi_clock.update();
+            }
         }
     }
 }
 
+/// The OSC group for show-wide actions.
+const META_GROUP: &str = "Meta";
+const REFRESH_UI: &str = "RefreshUI";
+const RELOAD_PATCH: &str = "ReloadPatch";
+const MIDI_LEARN: &str = "MidiLearn";
+
 const CONTROL_TIMEOUT: Duration = Duration::from_millis(1);
-const UPDATE_INTERVAL: Duration = Duration::from_millis(20);
+pub const UPDATE_INTERVAL: Duration = Duration::from_millis(20);
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(10);
 
 impl Show {
     pub fn new(cfg: Config, clocks: Clocks) -> Result<Self> {
-        let mut channels = Channels::new();
-        let mut patch = Patch::default();
-
         let controller = Controller::from_config(&cfg)?;
 
-        for fixture in cfg.fixtures.into_iter() {
-            patch.patch(&mut channels, fixture)?;
-        }
+        let (patch, channels) = build_patch(cfg.fixtures.clone())?;
 
         let master_controls = MasterControls::new();
         let initial_channel = channels.current_channel();
         let animation_ui_state = AnimationUIState::new(initial_channel);
 
+        let cues = match &cfg.cue_file {
+            Some(path) => CueList::load(path)?,
+            None => CueList::new(Vec::new()),
+        };
+
+        let dmx_merge = cfg.dmx_input.as_ref().map(DmxMerge::start).transpose()?;
+        if let Some(dmx_merge) = &dmx_merge {
+            dmx_merge.check_patch(&patch)?;
+        }
+
         let mut show = Self {
             controller,
             patch,
+            fixtures: cfg.fixtures,
             channels,
             master_controls,
             animation_ui_state,
+            presets: Presets::new(),
+            cues,
             clocks,
+            dmx_merge,
+            state_file: cfg.state_file,
+            record_file: cfg.record_file,
+            journal_file: cfg.journal_file,
+            journal: None,
+            update_count: 0,
+            replaying: false,
+            config_path: cfg.path,
         };
-        show.refresh_ui()?;
+        show.load_state();
+        show.refresh_ui(None)?;
         Ok(show)
     }
 
+    pub fn patch(&self) -> &Patch {
+        &self.patch
+    }
+
     /// Return the number of universes patched in the show.
     pub fn universe_count(&self) -> usize {
         self.patch.universe_count()
     }
 
-    /// Run the show forever in the current thread.
-    pub fn run(&mut self, dmx_ports: &mut [Box<dyn DmxPort>]) {
+    /// Run the show in the current thread until the stop flag is set.
+    pub fn run(&mut self, dmx_ports: &mut [Box<dyn DmxOutput>], stop: &AtomicBool) {
         let mut last_update = Instant::now();
+        let mut last_autosave = Instant::now();
         let mut dmx_buffers = vec![[0u8; 512]; dmx_ports.len()];
-        loop {
+        let mut merged_buffers = dmx_buffers.clone();
+        let mut recorder = self.create_recorder(dmx_ports.len());
+        self.journal = self.journal_file.as_ref().and_then(|path| {
+            JournalWriter::create(path, &self.journal_header())
+                .map_err(|err| error!("Unable to journal control messages: {err:#}."))
+                .ok()
+        });
+        while !stop.load(Ordering::Relaxed) {
             // Process a control event if one is pending.
             if let Err(err) = self.control(CONTROL_TIMEOUT) {
                 error!("A control error occurred: {err:#}.");
@@ -150,13 +247,260 @@ impl Show {
             // Render the state of the show.
             if should_render {
                 self.render(&mut dmx_buffers);
-                for (port, buffer) in dmx_ports.iter_mut().zip(&dmx_buffers) {
-                    if let Err(e) = port.write(buffer) {
-                        error!("DMX write error: {e:#}.");
+                record(&mut recorder, &dmx_buffers);
+                let output = match &mut self.dmx_merge {
+                    Some(dmx_merge) => {
+                        dmx_merge.merge(&dmx_buffers, &mut merged_buffers);
+                        &merged_buffers
                     }
+                    None => &dmx_buffers,
+                };
+                write_dmx(dmx_ports, output);
+            }
+
+            if last_autosave.elapsed() > AUTOSAVE_INTERVAL {
+                self.save_state();
+                last_autosave = Instant::now();
+            }
+        }
+        info!("Shutting down.");
+        close_outputs(dmx_ports, recorder);
+        self.save_state();
+    }
+
+    /// Replay a control journal until it ends or the stop flag is set.
+    ///
+    /// The show is first restored to its state at the start of the journal.
+    /// Updates then run on a simulated clock, and each journaled message is
+    /// handled after the same number of updates as it was originally, so the
+    /// show renders the same DMX as the session that was journaled. Every
+    /// update is rendered, paced at the provided multiple of real time; a
+    /// speed of zero replays as fast as possible.
+    ///
+    /// Clock state isn't journaled, so a journal of a show whose clocks came
+    /// from anywhere but control messages can't be replayed. The show must
+    /// be built with the journaled patch.
+    pub fn replay(
+        &mut self,
+        mut journal: JournalReader,
+        dmx_ports: &mut [Box<dyn DmxOutput>],
+        speed: f64,
+        stop: &AtomicBool,
+    ) -> Result<()> {
+        ensure!(
+            speed.is_finite() && speed >= 0.0,
+            "invalid replay speed {speed}"
+        );
+        let clock_source = journal.header().clock_source;
+        ensure!(
+            clock_source == ClockSource::Internal,
+            "the journaled show was driven by {clock_source}, which isn't journaled; \
+             only shows running internal clocks with no audio input or MIDI clock can be replayed"
+        );
+        journal.header().state.restore(
+            &mut self.patch,
+            &self.channels,
+            &mut self.animation_ui_state,
+            &mut self.master_controls,
+            &mut self.presets,
+        );
+        // Journaled MIDI messages are subject to the pickup state of devices
+        // that aren't connected now, so track it from the restored state.
+        self.controller
+            .track_midi_pickup(&journal.header().midi_devices);
+        self.refresh_ui(None)?;
+        let mut dmx_buffers = vec![[0u8; 512]; dmx_ports.len()];
+        let mut recorder = self.create_recorder(dmx_ports.len());
+        self.replaying = true;
+        let mut message_count = 0;
+        'replay: loop {
+            let entry = match journal.next_entry() {
+                Ok(Some(entry)) => entry,
+                Ok(None) => break,
+                Err(err) => {
+                    error!("Failed to read journal: {err:#}.");
+                    break;
+                }
+            };
+            while self.update_count < entry.update_count {
+                if stop.load(Ordering::Relaxed) {
+                    break 'replay;
                 }
+                self.replay_tick(&mut dmx_buffers, &mut recorder, dmx_ports);
+                if speed > 0.0 {
+                    std::thread::sleep(UPDATE_INTERVAL.div_f64(speed));
+                }
+            }
+            let result = match entry.msg {
+                JournalMessage::Control(msg) => self.handle_control_message(&msg),
+                JournalMessage::Patch(fixtures) => self.apply_patch(fixtures),
+            };
+            if let Err(err) = result {
+                error!(
+                    "A control error occurred at {:.3}s: {err:#}.",
+                    entry.elapsed.as_secs_f64()
+                );
             }
+            message_count += 1;
         }
+        // Run one more update so the effect of the final messages is output.
+        self.replay_tick(&mut dmx_buffers, &mut recorder, dmx_ports);
+        info!(
+            "Replayed {message_count} control messages over {} updates.",
+            self.update_count
+        );
+        close_outputs(dmx_ports, recorder);
+        Ok(())
+    }
+
+    /// Run a single update during replay, outputting the result.
+    fn replay_tick(
+        &mut self,
+        dmx_buffers: &mut [DmxBuffer],
+        recorder: &mut Option<Recorder>,
+        dmx_ports: &mut [Box<dyn DmxOutput>],
+    ) {
+        self.update(UPDATE_INTERVAL);
+        self.render(dmx_buffers);
+        record(recorder, dmx_buffers);
+        write_dmx(dmx_ports, dmx_buffers);
+    }
+
+    /// Start recording DMX, if a record file is configured.
+    fn create_recorder(&self, universe_count: usize) -> Option<Recorder> {
+        let path = self.record_file.as_ref()?;
+        Recorder::create(path, universe_count)
+            .map_err(|err| error!("Unable to record DMX: {err:#}."))
+            .ok()
+    }
+
+    /// Capture the current show state.
+    fn capture_state(&self) -> ShowState {
+        ShowState::capture(
+            &self.patch,
+            &self.channels,
+            &self.animation_ui_state,
+            &self.master_controls,
+            &self.presets,
+        )
+    }
+
+    /// Describe the current show, to start a journal.
+    fn journal_header(&self) -> JournalHeader {
+        JournalHeader {
+            clock_source: self.clocks.source(),
+            fixtures: self.fixtures.clone(),
+            midi_devices: self.controller.midi_devices(),
+            state: self.capture_state(),
+        }
+    }
+
+    /// Save the current show state, if a state file is configured.
+    fn save_state(&self) {
+        let Some(path) = &self.state_file else {
+            return;
+        };
+        if let Err(err) = self.capture_state().save(path) {
+            error!("Failed to save show state: {err:#}.");
+        }
+    }
+
+    /// Restore saved show state, if a state file is configured and exists.
+    fn load_state(&mut self) {
+        let Some(path) = &self.state_file else {
+            return;
+        };
+        match ShowState::load(path) {
+            Ok(Some(state)) => {
+                info!("Restoring show state from {}.", path.display());
+                state.restore(
+                    &mut self.patch,
+                    &self.channels,
+                    &mut self.animation_ui_state,
+                    &mut self.master_controls,
+                    &mut self.presets,
+                );
+            }
+            Ok(None) => (),
+            Err(err) => error!("Failed to load show state: {err:#}."),
+        }
+    }
+
+    /// Reload the patch from the config file, and journal it.
+    ///
+    /// While replaying, the journal provides the reloaded patch instead.
+    fn reload_patch(&mut self) -> Result<()> {
+        if self.replaying {
+            return Ok(());
+        }
+        let path = self.config_path.display().to_string();
+        let fixtures = Config::load(&path)?.fixtures;
+        self.apply_patch(fixtures)?;
+        info!("Reloaded patch from {path}.");
+        Ok(())
+    }
+
+    /// Repatch the show with the provided fixture groups, journaling the new
+    /// patch.
+    ///
+    /// State is carried over for every fixture group that is still patched.
+    /// If the new patch can't be built, the running patch is left untouched.
+    fn apply_patch(&mut self, fixtures: Vec<FixtureGroupConfig>) -> Result<()> {
+        let (mut patch, mut channels) =
+            build_patch(fixtures.clone()).context("reloaded patch is invalid")?;
+        // DMX ports are only assigned at startup.
+        ensure!(
+            patch.universe_count() <= self.patch.universe_count(),
+            "reloaded patch requires {} universes but only {} are assigned",
+            patch.universe_count(),
+            self.patch.universe_count()
+        );
+        if let Some(dmx_merge) = &self.dmx_merge {
+            dmx_merge.check_patch(&patch)?;
+        }
+
+        let mut animation_ui_state = AnimationUIState::new(channels.current_channel());
+        for group in patch.iter_mut() {
+            let key = group.key().clone();
+            let Ok(old_group) = self.patch.get(&key) else {
+                info!("Patched new fixture group {key}.");
+                continue;
+            };
+            GroupState::capture(old_group).restore(group);
+            if let (Some(old_channel), Some(channel)) = (
+                self.channels.channel_for_fixture(&key),
+                channels.channel_for_fixture(&key),
+            ) {
+                let n = self
+                    .animation_ui_state
+                    .animation_index_for_channel(old_channel);
+                animation_ui_state.set_current_animation(channel, n)?;
+            }
+        }
+        if let Some(channel) = self.channels.current_channel() {
+            channels.select_fixture(self.channels.group_by_channel(&self.patch, channel)?.key());
+        }
+        channels.set_bank(self.channels.bank());
+
+        self.presets.retain_patched(&patch);
+        self.patch = patch;
+        self.fixtures = fixtures;
+        if let Some(journal) = &mut self.journal {
+            if let Err(err) = journal.write_patch(self.update_count, &self.fixtures) {
+                error!("Stopped journaling control messages: {err:#}.");
+                self.journal = None;
+            }
+        }
+        self.channels = channels;
+        self.animation_ui_state = animation_ui_state;
+        // Newly patched groups haven't seen the master controls yet.
+        master_controls_changed(
+            &mut self.patch,
+            &self.channels,
+            &self.master_controls,
+            &self.controller.sender_with_metadata(None),
+        );
+        self.refresh_ui(None)
     }
 
     /// Handle at most one control message.
@@ -170,32 +514,81 @@ impl Show {
             }
         };
 
+        if let Some(journal) = &mut self.journal {
+            if let Err(err) = journal.write(self.update_count, &msg) {
+                error!("Stopped journaling control messages: {err:#}.");
+                self.journal = None;
+            }
+        }
+
+        self.handle_control_message(&msg)
+    }
+
+    /// Handle a single control message.
+    fn handle_control_message(&mut self, msg: &ControlMessage) -> Result<()> {
         match msg {
-            ControlMessage::Midi(msg) => self.handle_midi_message(&msg),
-            ControlMessage::Osc(msg) => self.handle_osc_message(&msg),
-            ControlMessage::Wled(msg) => self.handle_wled_response(&msg),
+            ControlMessage::Midi(msg) => self.handle_midi_message(msg),
+            ControlMessage::Osc(msg) => self.handle_osc_message(msg),
+            ControlMessage::OscClientRegistered(client_id) => {
+                self.refresh_ui(Some(&SurfaceId::Osc(*client_id)))
+            }
+            ControlMessage::OscClientExpired(client_id) => {
+                let surface = SurfaceId::Osc(*client_id);
+                self.channels.forget_surface(&surface);
+                self.animation_ui_state.forget_surface(&surface);
+                Ok(())
+            }
+            ControlMessage::Wled(msg) => self.handle_wled_response(msg),
+            ControlMessage::Http(req) => self.handle_http_request(req),
         }
     }
 
     /// Handle a single MIDI control message.
     fn handle_midi_message(&mut self, msg: &MidiControlMessage) -> Result<()> {
-        let sender = self.controller.sender_with_metadata(None);
-        let Some(channel_ctrl_msg) = msg.device.interpret(&msg.event) else {
+        if self.controller.learn_midi_control(msg) {
+            return Ok(());
+        }
+        let Some(channel_ctrl_msg) = self.controller.interpret_midi(msg) else {
             return Ok(());
         };
+        let surface = SurfaceId::Midi(msg.device);
+        let sender = self.controller.sender_with_metadata(Some(&surface));
         match channel_ctrl_msg {
-            ShowControlMessage::Channel(msg) => {
-                self.channels
-                    .control(&msg, &mut self.patch, &self.animation_ui_state, &sender)
+            ShowControlMessage::Channel(msg) => self.channels.control(
+                &msg,
+                Some(&surface),
+                &mut self.patch,
+                &self.animation_ui_state,
+                &sender,
+            ),
+            ShowControlMessage::Master(msg) => {
+                self.master_controls.control(&msg, &sender)?;
+                master_controls_changed(
+                    &mut self.patch,
+                    &self.channels,
+                    &self.master_controls,
+                    &sender,
+                );
+                Ok(())
+            }
+            ShowControlMessage::Cue(msg) => {
+                self.cues.control(
+                    &msg,
+                    &mut self.presets,
+                    &mut self.patch,
+                    &self.channels,
+                    &sender,
+                )?;
+                self.emit_animation_state_to_all_surfaces()
             }
-            ShowControlMessage::Master(msg) => self.master_controls.control(&msg, &sender),
             ShowControlMessage::Animation(msg) => {
-                let Some(channel) = self.channels.current_channel() else {
+                let Some(channel) = self.channels.selected_channel(Some(&surface)) else {
                     bail!("cannot handle animation control message because no channel is selected\n{msg:?}");
                 };
                 self.animation_ui_state.control(
                     msg,
                     channel,
+                    Some(&surface),
                     self.channels
                         .group_by_channel_mut(&mut self.patch, channel)?,
                     &ScopedControlEmitter {
@@ -204,36 +597,90 @@ impl Show {
                     },
                 )
             }
+            ShowControlMessage::Osc(msg) => self.handle_osc_control(&msg, surface),
         }
     }
 
     /// Handle a single OSC message.
-    fn handle_osc_message(&mut self, msg: &OscControlMessage) -> Result<()> {
-        let sender = self.controller.sender_with_metadata(Some(&msg.client_id));
+    pub fn handle_osc_message(&mut self, msg: &OscControlMessage) -> Result<()> {
+        // While learning MIDI mappings, the control a client touches is the
+        // target for the last MIDI control moved.
+        if msg.entity_type() != META_GROUP {
+            if let Err(err) = self.controller.learn_midi_target(msg.addr()) {
+                error!("Failed to learn MIDI mapping: {err:#}.");
+            }
+        }
+        self.handle_osc_control(msg, SurfaceId::Osc(msg.client_id))
+    }
+
+    /// Handle a single OSC control message sent by the provided surface.
+    fn handle_osc_control(&mut self, msg: &OscControlMessage, surface: SurfaceId) -> Result<()> {
+        let mut sender = self.controller.sender_with_metadata(Some(&surface));
+        sender.echo_addr = Some(msg.addr().to_string());
 
         match msg.entity_type() {
-            "Meta" => {
-                if msg.control() == "RefreshUI" {
+            META_GROUP => {
+                if msg.control() == REFRESH_UI {
                     if msg.get_bool()? {
-                        self.refresh_ui()?;
+                        self.refresh_ui(Some(&surface))?;
                     }
+                } else if msg.control() == RELOAD_PATCH {
+                    if msg.get_bool()? {
+                        self.reload_patch()?;
+                    }
+                } else if msg.control() == MIDI_LEARN {
+                    self.controller.set_midi_learn(msg.get_bool()?);
                 } else {
                     bail!("unknown Meta control {}", msg.control());
                 }
                 Ok(())
             }
-            crate::master::GROUP => self.master_controls.control_osc(msg, &sender),
-            crate::osc::channels::GROUP => {
-                self.channels
-                    .control_osc(msg, &mut self.patch, &self.animation_ui_state, &sender)
+            crate::master::GROUP => {
+                self.master_controls.control_osc(msg, &sender)?;
+                master_controls_changed(
+                    &mut self.patch,
+                    &self.channels,
+                    &self.master_controls,
+                    &sender,
+                );
+                Ok(())
             }
+            crate::osc::presets::GROUP => {
+                self.presets.control_osc(
+                    msg,
+                    Some(&surface),
+                    &mut self.patch,
+                    &self.channels,
+                    &sender,
+                )?;
+                // Recalled animations may have changed under the animation UI.
+                self.emit_animation_state_to_all_surfaces()
+            }
+            crate::osc::cues::GROUP => {
+                self.cues.control_osc(
+                    msg,
+                    &mut self.presets,
+                    &mut self.patch,
+                    &self.channels,
+                    &sender,
+                )?;
+                self.emit_animation_state_to_all_surfaces()
+            }
+            crate::osc::channels::GROUP => self.channels.control_osc(
+                msg,
+                Some(&surface),
+                &mut self.patch,
+                &self.animation_ui_state,
+                &sender,
+            ),
             crate::osc::animation::GROUP => {
-                let Some(channel) = self.channels.current_channel() else {
+                let Some(channel) = self.channels.selected_channel(Some(&surface)) else {
                     bail!("cannot handle animation control message because no channel is selected\n{msg:?}");
                 };
                 self.animation_ui_state.control_osc(
                     msg,
                     channel,
+                    Some(&surface),
                     self.channels
                         .group_by_channel_mut(&mut self.patch, channel)?,
                     &ScopedControlEmitter {
@@ -251,10 +698,11 @@ impl Show {
                 else {
                     bail!("cannot handle audio control message because no audio input is configured\n{msg:?}");
                 };
-                let Some((msg, _talkback)) = audio_controls.handle(msg)? else {
+                let Some((msg, talkback)) = audio_controls.handle(msg)? else {
                     return Ok(());
                 };
-                audio_input.control(msg, &mut self.controller);
+                sender.set_talkback(talkback);
+                audio_input.control(msg, &mut sender);
                 Ok(())
             }
             // Assume any other group is the name of a fixture.
@@ -272,7 +720,7 @@ impl Show {
                 self.patch.get_mut(&group_key)?.control(
                     msg,
                     ChannelStateEmitter::new(
-                        self.channels.channel_for_fixture(&group_key),
+                        self.channels.visible_channel_for_fixture(&group_key),
                         &sender,
                     ),
                 )
@@ -280,6 +728,95 @@ impl Show {
         }
     }
 
+    /// Handle a single request from the HTTP API, and reply to it.
+    ///
+    /// Control changes are handled as OSC messages, and the reply carries the
+    /// talkback they produce.
+    fn handle_http_request(&mut self, req: &HttpRequest) -> Result<()> {
+        let result = match &req.query {
+            Query::Patch => crate::http::patch_json(&self.patch, &self.channels),
+            Query::Groups => Ok(serde_json::to_value(self.capture_state().groups)?),
+            Query::Channels => crate::http::channels_json(&self.channels, &self.patch),
+            Query::Master => Ok(serde_json::to_value(self.capture_state().master)?),
+            Query::Namespace => self.osc_namespace(),
+            Query::Control(msg) => {
+                self.controller.capture_talkback();
+                let result = self.handle_osc_message(msg);
+                let talkback = self.controller.take_talkback();
+                result.map(|_| crate::http::talkback_json(talkback))
+            }
+        };
+        req.reply(&result);
+        result.map(|_| ())
+    }
+
+    /// Build the OSCQuery namespace from all of the current UI state.
+    fn osc_namespace(&self) -> Result<serde_json::Value> {
+        Ok(crate::oscquery::namespace(
+            &self.capture_ui_state()?,
+            &self.control_addrs(),
+        ))
+    }
+
+    /// Capture the OSC messages sent to refresh a UI.
+    pub fn capture_ui_state(&self) -> Result<Vec<CapturedMessage>> {
+        let mut capture = StateCapture::default();
+        for group in self.patch.iter() {
+            group.emit_state(ChannelStateEmitter::new(
+                self.channels.visible_channel_for_fixture(group.key()),
+                &capture,
+            ));
+        }
+        self.master_controls.emit_state(&capture);
+        self.channels.emit_state(false, None, &self.patch, &capture);
+        emit_current_animation_state(
+            &self.animation_ui_state,
+            &self.channels,
+            None,
+            &self.patch,
+            &capture,
+        )?;
+        self.presets.emit_state(&capture);
+        self.cues.emit_state(&capture);
+        self.clocks.emit_state(&mut capture);
+        Ok(capture.into_messages())
+    }
+
+    /// Return the address of every control registered outside of fixtures,
+    /// including those with no state.
+    /// Fixture controls all emit their state, so capturing the UI state
+    /// covers them.
+    pub fn control_addrs(&self) -> Vec<String> {
+        let mut controls = Vec::new();
+        let mut add = |group: &str, names: &mut dyn Iterator<Item = &str>| {
+            controls.extend(names.map(|name| format!("/{group}/{name}")));
+        };
+        add(
+            META_GROUP,
+            &mut [REFRESH_UI, RELOAD_PATCH, MIDI_LEARN].into_iter(),
+        );
+        add(
+            crate::osc::channels::GROUP,
+            &mut self.channels.control_names(),
+        );
+        add(
+            crate::osc::animation::GROUP,
+            &mut self.animation_ui_state.control_names(),
+        );
+        add(
+            crate::osc::presets::GROUP,
+            &mut self.presets.control_names(),
+        );
+        add(crate::osc::cues::GROUP, &mut self.cues.control_names());
+        if let Clocks::Internal { audio_controls, .. } = &self.clocks {
+            add(
+                crate::osc::audio::GROUP,
+                &mut audio_controls.control_names(),
+            );
+        }
+        controls
+    }
+
     /// Handle a single response from WLED.
     fn handle_wled_response(&mut self, _msg: &WledResponse) -> Result<()> {
         // TODO: decide how to map responses back
@@ -288,8 +825,24 @@ impl Show {
 
     /// Update the state of the show using the provided timestep.
     fn update(&mut self, delta_t: Duration) {
+        self.update_count += 1;
         self.clocks.update(delta_t, &mut self.controller);
         self.master_controls.update(delta_t);
+        let sender = self.controller.sender_with_metadata(None);
+        let cue_triggered = self.cues.update(
+            delta_t,
+            &mut self.presets,
+            &mut self.patch,
+            &self.channels,
+            &sender,
+        );
+        self.presets
+            .update(delta_t, &mut self.patch, &self.channels, &sender);
+        if cue_triggered {
+            if let Err(err) = self.emit_animation_state_to_all_surfaces() {
+                error!("Failed to emit animation state: {err:#}.");
+            }
+        }
         for fixture in self.patch.iter_mut() {
             fixture.update(&self.master_controls, delta_t, UnipolarFloat::ZERO);
         }
@@ -307,43 +860,160 @@ impl Show {
         }
     }
 
+    /// Emit the animation UI state to every control surface, after a recall
+    /// may have changed animations in any channel.
+    ///
+    /// Surfaces with their own channel selection are sent the shared page
+    /// along with everyone else, then their own page in its place.
+    fn emit_animation_state_to_all_surfaces(&mut self) -> Result<()> {
+        emit_current_animation_state(
+            &self.animation_ui_state,
+            &self.channels,
+            None,
+            &self.patch,
+            &self.controller.sender_with_metadata(None),
+        )?;
+        let surfaces: Vec<_> = self.channels.surfaces_with_selection().collect();
+        for surface in surfaces {
+            let sender = self.controller.sender_with_metadata(Some(&surface));
+            sender.set_talkback(TalkbackMode::SenderOnly);
+            emit_current_animation_state(
+                &self.animation_ui_state,
+                &self.channels,
+                Some(&surface),
+                &self.patch,
+                &sender,
+            )?;
+        }
+        Ok(())
+    }
+
     /// Send messages to refresh all UI state.
-    fn refresh_ui(&mut self) -> anyhow::Result<()> {
-        let emitter = &self.controller.sender_with_metadata(None);
+    /// If a control surface is provided, only that surface's UI is refreshed,
+    /// using its own channel and animator selection.
+    fn refresh_ui(&mut self, surface: Option<&SurfaceId>) -> anyhow::Result<()> {
+        let mut emitter = self.controller.sender_with_metadata(surface);
+        if surface.is_some() {
+            emitter.set_talkback(TalkbackMode::SenderOnly);
+        }
         for group in self.patch.iter() {
             group.emit_state(ChannelStateEmitter::new(
-                self.channels.channel_for_fixture(group.key()),
-                emitter,
+                self.channels.visible_channel_for_fixture(group.key()),
+                &emitter,
             ));
         }
 
-        self.master_controls.emit_state(emitter);
+        self.master_controls.emit_state(&emitter);
 
-        self.channels.emit_state(false, &self.patch, emitter);
+        self.channels
+            .emit_state(false, surface, &self.patch, &emitter);
 
-        if let Some(current_channel) = self.channels.current_channel() {
-            self.animation_ui_state.emit_state(
-                current_channel,
-                self.channels
-                    .group_by_channel(&self.patch, current_channel)?,
-                &ScopedControlEmitter {
-                    entity: crate::osc::animation::GROUP,
-                    emitter,
-                },
-            )?;
-        }
+        emit_current_animation_state(
+            &self.animation_ui_state,
+            &self.channels,
+            surface,
+            &self.patch,
+            &emitter,
+        )?;
+
+        self.presets.emit_state(&emitter);
 
-        self.clocks.emit_state(&mut self.controller);
+        self.cues.emit_state(&emitter);
+
+        self.clocks.emit_state(&mut emitter);
 
         Ok(())
     }
 }
 
+/// Record rendered DMX, if recording.
+/// Stop recording if the recording can't be written.
+fn record(recorder: &mut Option<Recorder>, dmx_buffers: &[DmxBuffer]) {
+    if let Some(r) = recorder {
+        if let Err(err) = r.record(dmx_buffers) {
+            error!("Stopped recording DMX: {err:#}.");
+            *recorder = None;
+        }
+    }
+}
+
+/// Write rendered DMX to each port.
+fn write_dmx(dmx_ports: &mut [Box<dyn DmxOutput>], dmx_buffers: &[DmxBuffer]) {
+    for (port, buffer) in dmx_ports.iter_mut().zip(dmx_buffers) {
+        if let Err(e) = port.write(buffer) {
+            error!("DMX write error: {e:#}.");
+        }
+    }
+}
+
+/// Close every port and finish the recording, if recording.
+fn close_outputs(dmx_ports: &mut [Box<dyn DmxOutput>], recorder: Option<Recorder>) {
+    for port in dmx_ports.iter_mut() {
+        port.close();
+    }
+    if let Some(recorder) = recorder {
+        if let Err(err) = recorder.finish() {
+            error!("Failed to finish DMX recording: {err:#}.");
+        }
+    }
+}
+
+/// Patch all of the provided fixture groups into a new patch.
+pub fn build_patch(fixtures: Vec<FixtureGroupConfig>) -> Result<(Patch, Channels)> {
+    let mut channels = Channels::new();
+    let mut patch = Patch::default();
+    for fixture in fixtures {
+        patch.patch(&mut channels, fixture)?;
+    }
+    Ok((patch, channels))
+}
+
+/// Notify every fixture group that the master controls have changed.
+fn master_controls_changed(
+    patch: &mut Patch,
+    channels: &Channels,
+    master_controls: &MasterControls,
+    emitter: &dyn EmitControlMessage,
+) {
+    for group in patch.iter_mut() {
+        let channel = channels.visible_channel_for_fixture(group.key());
+        group.master_controls_changed(master_controls, ChannelStateEmitter::new(channel, emitter));
+    }
+}
+
+/// Emit the animation UI state for the channel selected by the provided
+/// control surface.
+fn emit_current_animation_state(
+    animation_ui_state: &AnimationUIState,
+    channels: &Channels,
+    surface: Option<&SurfaceId>,
+    patch: &Patch,
+    emitter: &dyn EmitControlMessage,
+) -> Result<()> {
+    let Some(current_channel) = channels.selected_channel(surface) else {
+        return Ok(());
+    };
+    animation_ui_state.emit_state(
+        current_channel,
+        surface,
+        channels.group_by_channel(patch, current_channel)?,
+        &ScopedControlEmitter {
+            entity: crate::osc::animation::GROUP,
+            emitter,
+        },
+    )
+}
+
 /// Strongly-typed top-level show control messages.
-/// These cover all of the fixed control features, but not fixture-specific controls.
+/// These cover all of the fixed control features; fixture-specific controls are
+/// addressed by OSC.
 #[derive(Debug, Clone)]
 pub enum ShowControlMessage {
     Master(crate::master::ControlMessage),
     Channel(crate::channel::ControlMessage),
     Animation(crate::animation::ControlMessage),
+    Cue(crate::cue::ControlMessage),
+    /// A control addressed by OSC, such as a fixture control mapped onto a
+    /// generic MIDI device.
+    Osc(OscControlMessage),
 }
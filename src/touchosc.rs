@@ -0,0 +1,596 @@
+//! Generate TouchOSC layouts from the patch.
+//!
+//! Each fixture group gets its own page, holding a control for everything the
+//! group emits state for, addressed exactly as the group emits it. Controls
+//! are laid out in rows, in the order the fixture emits them:
+//! - faders for unipolar, bipolar and phase controls
+//! - toggles for bool controls
+//! - button grids for indexed selects
+//! - a column of labeled buttons for labeled selects
+//!
+//! Existing layouts can also be checked against the show, listing every
+//! address the layout sends that the show wouldn't handle, and every patched
+//! control that the layout never sends.
+//!
+//! A TouchOSC layout is a zip archive holding a single XML document, with
+//! names, labels and addresses base64-encoded.
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    fs::File,
+    io::{Read, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use lazy_static::lazy_static;
+use log::{info, warn};
+use regex::Regex;
+use rosc::{OscMessage, OscType};
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+use crate::{
+    channel::ChannelStateEmitter,
+    control::{CapturedMessage, StateCapture},
+    fixture::{FixtureGroup, Patch},
+    osc::{fixture_control_addr, ControlKind, OscClientId, OscControlMessage},
+    show::Show,
+};
+
+/// Size of the layout, for an iPad in portrait orientation.
+const PAGE_WIDTH: usize = 768;
+const PAGE_HEIGHT: usize = 1024;
+
+/// Size of a single button, and the width of a fader.
+const BUTTON: usize = 84;
+const FADER_HEIGHT: usize = 400;
+const LABEL_HEIGHT: usize = 25;
+const MARGIN: usize = 6;
+
+/// A single control to lay out.
+#[derive(Debug, PartialEq)]
+struct Widget {
+    /// The control name, relative to the group.
+    name: String,
+    kind: WidgetKind,
+}
+
+#[derive(Debug, PartialEq)]
+enum WidgetKind {
+    Fader {
+        bipolar: bool,
+    },
+    Toggle,
+    /// A radio-select grid of buttons, addressed by 1-based position.
+    Grid {
+        number_x: usize,
+        number_y: usize,
+    },
+    /// A radio-select column of buttons, each addressed by its label.
+    Buttons(Vec<String>),
+}
+
+impl WidgetKind {
+    /// The width and height of this widget, not including its label.
+    fn size(&self) -> (usize, usize) {
+        match self {
+            Self::Fader { .. } => (BUTTON, FADER_HEIGHT),
+            Self::Toggle => (BUTTON, BUTTON),
+            Self::Grid { number_x, number_y } => (BUTTON * number_x, BUTTON * number_y),
+            Self::Buttons(labels) => (BUTTON * 2, BUTTON * labels.len()),
+        }
+    }
+}
+
+/// Write a layout for every group in the patch to the provided path.
+pub fn write_layout(patch: &Patch, path: &Path) -> Result<()> {
+    let file = File::create(path).with_context(|| format!("creating layout {}", path.display()))?;
+    let mut zip = ZipWriter::new(file);
+    zip.start_file("index.xml", SimpleFileOptions::default())?;
+    zip.write_all(layout_xml(patch).as_bytes())?;
+    zip.finish()?;
+    info!("Wrote TouchOSC layout to {}.", path.display());
+    Ok(())
+}
+
+fn layout_xml(patch: &Patch) -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?><layout version="17" mode="1" orientation="vertical">"#,
+    );
+    for group in patch.iter() {
+        write_page(&mut xml, group);
+    }
+    xml.push_str("</layout>");
+    xml
+}
+
+/// Write a page holding every control for a group.
+fn write_page(xml: &mut String, group: &FixtureGroup) {
+    let key = group.key();
+    let title = match &key.group {
+        Some(name) => format!("{name} ({})", key.fixture),
+        None => key.fixture.to_string(),
+    };
+    let title = encode(&title);
+    write!(
+        xml,
+        r#"<tabpage name="{title}" scalef="0.0" scalet="1.0" li_t="{title}" li_c="gray" li_s="14" li_o="false" li_b="false" la_t="{title}" la_c="gray" la_s="14" la_o="false" la_b="false" >"#,
+    )
+    .unwrap();
+
+    let prefix = fixture_control_addr(key, "");
+    let mut names = NameGenerator::default();
+    let (mut x, mut y, mut row_height) = (MARGIN, MARGIN, 0);
+    for widget in group_widgets(group) {
+        let (w, h) = widget.kind.size();
+        if x + w > PAGE_WIDTH && x > MARGIN {
+            x = MARGIN;
+            y += row_height + MARGIN;
+            row_height = 0;
+        }
+        if y + LABEL_HEIGHT + h > PAGE_HEIGHT {
+            warn!("{key}: control {} doesn't fit on the page.", widget.name);
+        }
+        write_label(xml, &mut names, &widget.name, x, y, w);
+        write_widget(
+            xml,
+            &mut names,
+            &widget,
+            &format!("{prefix}{}", widget.name),
+            x,
+            y + LABEL_HEIGHT,
+        );
+        x += w + MARGIN;
+        row_height = row_height.max(LABEL_HEIGHT + h);
+    }
+    xml.push_str("</tabpage>");
+}
+
+fn write_widget(
+    xml: &mut String,
+    names: &mut NameGenerator,
+    widget: &Widget,
+    addr: &str,
+    x: usize,
+    y: usize,
+) {
+    let (w, h) = widget.kind.size();
+    match &widget.kind {
+        WidgetKind::Fader { bipolar } => {
+            let (scalef, centered) = if *bipolar {
+                ("-1.0", "true")
+            } else {
+                ("0.0", "false")
+            };
+            write!(
+                xml,
+                r#"<control name="{}" x="{x}" y="{y}" w="{w}" h="{h}" color="gray" scalef="{scalef}" scalet="1.0" osc_cs="{}" type="faderv" response="absolute" inverted="false" centered="{centered}" ></control>"#,
+                names.next("fader"),
+                encode(addr),
+            )
+        }
+        WidgetKind::Toggle => write!(
+            xml,
+            r#"<control name="{}" x="{x}" y="{y}" w="{w}" h="{h}" color="gray" scalef="0.0" scalet="1.0" osc_cs="{}" type="toggle" local_off="true" ></control>"#,
+            names.next("toggle"),
+            encode(addr),
+        ),
+        WidgetKind::Grid { number_x, number_y } => write!(
+            xml,
+            r#"<control name="{}" x="{x}" y="{y}" w="{w}" h="{h}" color="gray" scalef="0.0" scalet="1.0" osc_cs="{}" type="multitoggle" number_x="{number_x}" number_y="{number_y}" ex_mode="true" local_off="true" ></control>"#,
+            names.next("multitoggle"),
+            encode(addr),
+        ),
+        WidgetKind::Buttons(labels) => {
+            for (i, label) in labels.iter().enumerate() {
+                let y = y + i * BUTTON;
+                write!(
+                    xml,
+                    r#"<control name="{}" x="{x}" y="{y}" w="{w}" h="{BUTTON}" color="gray" scalef="0.0" scalet="1.0" osc_cs="{}" type="toggle" local_off="true" ></control>"#,
+                    names.next("toggle"),
+                    encode(&format!("{addr}/{label}")),
+                )
+                .unwrap();
+                write_label(xml, names, label, x, y + (BUTTON - LABEL_HEIGHT) / 2, w);
+            }
+            Ok(())
+        }
+    }
+    .unwrap();
+}
+
+fn write_label(
+    xml: &mut String,
+    names: &mut NameGenerator,
+    text: &str,
+    x: usize,
+    y: usize,
+    w: usize,
+) {
+    write!(
+        xml,
+        r#"<control name="{}" x="{x}" y="{y}" w="{w}" h="{LABEL_HEIGHT}" color="gray" type="labelh" text="{}" size="14" background="false" outline="false" ></control>"#,
+        names.next("label"),
+        encode(text),
+    )
+    .unwrap();
+}
+
+/// Generate unique control names, as TouchOSC does.
+#[derive(Default)]
+struct NameGenerator(usize);
+
+impl NameGenerator {
+    fn next(&mut self, kind: &str) -> String {
+        self.0 += 1;
+        encode(&format!("{kind}{}", self.0))
+    }
+}
+
+fn encode(s: &str) -> String {
+    BASE64_STANDARD.encode(s)
+}
+
+fn decode(s: &str) -> Result<String> {
+    let bytes = BASE64_STANDARD
+        .decode(s)
+        .with_context(|| format!("decoding \"{s}\""))?;
+    Ok(String::from_utf8(bytes)?)
+}
+
+/// Problems found by checking a layout against the show.
+#[derive(Default)]
+pub struct LintReport {
+    /// Addresses the layout sends that the show doesn't handle, along with
+    /// the error handling them produced.
+    pub unhandled: Vec<(String, String)>,
+    /// Addresses of patched controls that the layout never sends.
+    pub unsent: Vec<String>,
+}
+
+impl LintReport {
+    pub fn is_empty(&self) -> bool {
+        self.unhandled.is_empty() && self.unsent.is_empty()
+    }
+}
+
+/// Check the layout at the provided path against the show.
+///
+/// Addresses are checked against the controls the show has registered and the
+/// state it emits; nothing is sent to the show.
+pub fn lint(show: &Show, path: &Path) -> Result<LintReport> {
+    let sent = read_layout(path)?;
+    let state = show.capture_ui_state()?;
+    let mut report = check_addrs(
+        &sent,
+        state
+            .iter()
+            .map(|CapturedMessage { msg, .. }| msg.addr.clone())
+            .chain(show.control_addrs()),
+    );
+
+    let sent: HashSet<_> = sent.iter().map(String::as_str).collect();
+    for group in show.patch().iter() {
+        let capture = StateCapture::default();
+        group.emit_state(ChannelStateEmitter::new(None, &capture));
+        for CapturedMessage { msg, .. } in capture.into_messages() {
+            if matches!(msg.args.as_slice(), [OscType::String(_)]) {
+                continue;
+            }
+            if !sent.contains(msg.addr.as_str()) {
+                report.unsent.push(msg.addr);
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Check that every sent address reaches a known control.
+///
+/// Addresses are compared by group, entity and control, ignoring anything
+/// after the control such as a radio button index.
+fn check_addrs(sent: &[String], known: impl Iterator<Item = String>) -> LintReport {
+    let known: HashSet<_> = known.filter_map(|addr| control_key(&addr).ok()).collect();
+    let mut report = LintReport::default();
+    for addr in sent {
+        match control_key(addr) {
+            Ok(key) if known.contains(&key) => (),
+            Ok(_) => report
+                .unhandled
+                .push((addr.clone(), "no control handler matched".to_string())),
+            Err(err) => report.unhandled.push((addr.clone(), format!("{err:#}"))),
+        }
+    }
+    report
+}
+
+/// Parse the group, entity and control from an address.
+fn control_key(addr: &str) -> Result<(Option<String>, String, String)> {
+    let msg = OscControlMessage::new(
+        OscMessage {
+            addr: addr.to_string(),
+            args: vec![OscType::Float(1.0)],
+        },
+        OscClientId::internal(),
+    )?;
+    Ok((
+        msg.group().map(str::to_string),
+        msg.entity_type().to_string(),
+        msg.control().to_string(),
+    ))
+}
+
+/// Read every OSC address that the layout at the provided path sends.
+fn read_layout(path: &Path) -> Result<Vec<String>> {
+    let file = File::open(path).with_context(|| format!("opening layout {}", path.display()))?;
+    let mut zip = ZipArchive::new(file)?;
+    let mut xml = String::new();
+    zip.by_name("index.xml")?.read_to_string(&mut xml)?;
+    layout_addrs(&xml)
+}
+
+/// Parse every OSC address sent by the controls in a layout document.
+///
+/// Controls without an explicit address use TouchOSC's default of
+/// /{page}/{control}. Grids of buttons and faders send each element to its
+/// own address, suffixed with its 1-based position. Controls that only
+/// display values are ignored. The result contains no duplicates.
+fn layout_addrs(xml: &str) -> Result<Vec<String>> {
+    lazy_static! {
+        static ref TAG: Regex = Regex::new(r"<(tabpage|control)\s([^>]*)>").unwrap();
+        static ref ATTR: Regex = Regex::new(r#"(\w+)="([^"]*)""#).unwrap();
+    }
+    let mut addrs = Vec::new();
+    let mut seen = HashSet::new();
+    let mut page = String::new();
+    for tag in TAG.captures_iter(xml) {
+        let attrs: Vec<(&str, &str)> = ATTR
+            .captures_iter(&tag[2])
+            .map(|attr| {
+                let (_, [name, val]) = attr.extract();
+                (name, val)
+            })
+            .collect();
+        let attr = |name: &str| attrs.iter().find(|(n, _)| *n == name).map(|(_, v)| *v);
+        let count = |name: &str| -> Result<usize> {
+            attr(name)
+                .with_context(|| format!("control is missing {name}"))?
+                .parse()
+                .with_context(|| format!("invalid {name}"))
+        };
+        let name = decode(attr("name").unwrap_or_default())?;
+        if &tag[1] == "tabpage" {
+            page = name;
+            continue;
+        }
+        let kind = attr("type").unwrap_or_default();
+        if ["label", "led", "battery", "time"]
+            .iter()
+            .any(|display| kind.starts_with(display))
+        {
+            continue;
+        }
+        let addr = match attr("osc_cs") {
+            Some(addr) => decode(addr)?,
+            None => format!("/{page}/{name}"),
+        };
+        let control_addrs = match kind {
+            "multitoggle" | "multipush" => {
+                let (number_x, number_y) = (count("number_x")?, count("number_y")?);
+                (1..=number_x)
+                    .flat_map(|x| (1..=number_y).map(move |y| (x, y)))
+                    .map(|(x, y)| format!("{addr}/{x}/{y}"))
+                    .collect()
+            }
+            "multifaderv" | "multifaderh" => (1..=count("number")?)
+                .map(|i| format!("{addr}/{i}"))
+                .collect(),
+            _ => vec![addr],
+        };
+        for addr in control_addrs {
+            if seen.insert(addr.clone()) {
+                addrs.push(addr);
+            }
+        }
+    }
+    Ok(addrs)
+}
+
+/// Collect the controls of a group from the state it emits.
+fn group_widgets(group: &FixtureGroup) -> Vec<Widget> {
+    let capture = StateCapture::default();
+    group.emit_state(ChannelStateEmitter::new(None, &capture));
+    widgets(
+        &fixture_control_addr(group.key(), ""),
+        capture.into_messages(),
+    )
+}
+
+/// Interpret captured state as widgets, stripping the group address prefix.
+fn widgets(prefix: &str, msgs: Vec<CapturedMessage>) -> Vec<Widget> {
+    let mut widgets: Vec<Widget> = Vec::new();
+    for CapturedMessage { msg, kind } in msgs {
+        let Some(control) = msg.addr.strip_prefix(prefix) else {
+            continue;
+        };
+        // Text is only ever sent to labels, not received from controls.
+        if matches!(msg.args.as_slice(), [OscType::String(_)]) {
+            continue;
+        }
+        let kind = match kind {
+            Some(ControlKind::Select) => {
+                add_select_option(&mut widgets, control);
+                continue;
+            }
+            Some(ControlKind::Bool) => WidgetKind::Toggle,
+            Some(ControlKind::Bipolar) => WidgetKind::Fader { bipolar: true },
+            Some(ControlKind::Unipolar | ControlKind::Phase) | None => {
+                WidgetKind::Fader { bipolar: false }
+            }
+        };
+        widgets.push(Widget {
+            name: control.to_string(),
+            kind,
+        });
+    }
+    widgets
+}
+
+/// Add one option of a select control to its widget, creating it if needed.
+///
+/// Indexed selects are addressed as name/x/y, and labeled selects as
+/// name/label.
+fn add_select_option(widgets: &mut Vec<Widget>, control: &str) {
+    let (name, option) = control.split_once('/').unwrap_or((control, ""));
+    let position = option
+        .split_once('/')
+        .and_then(|(x, y)| Some((x.parse::<usize>().ok()?, y.parse::<usize>().ok()?)));
+    let existing = widgets.iter_mut().find(|w| w.name == name);
+    match (existing, position) {
+        (
+            Some(Widget {
+                kind: WidgetKind::Grid { number_x, number_y },
+                ..
+            }),
+            Some((x, y)),
+        ) => {
+            *number_x = (*number_x).max(x);
+            *number_y = (*number_y).max(y);
+        }
+        (
+            Some(Widget {
+                kind: WidgetKind::Buttons(labels),
+                ..
+            }),
+            None,
+        ) => labels.push(option.to_string()),
+        (_, Some((number_x, number_y))) => widgets.push(Widget {
+            name: name.to_string(),
+            kind: WidgetKind::Grid { number_x, number_y },
+        }),
+        (_, None) => widgets.push(Widget {
+            name: name.to_string(),
+            kind: WidgetKind::Buttons(vec![option.to_string()]),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rosc::OscMessage;
+
+    fn captured(addr: &str, kind: Option<ControlKind>) -> CapturedMessage {
+        CapturedMessage {
+            msg: OscMessage {
+                addr: addr.to_string(),
+                args: vec![OscType::Float(0.0)],
+            },
+            kind,
+        }
+    }
+
+    #[test]
+    fn test_widgets() {
+        use ControlKind::*;
+        let prefix = "/:left/Comet/";
+        let msgs = vec![
+            captured("/:left/Comet/Shutter", Some(Bool)),
+            captured("/:left/Comet/Speed", Some(Bipolar)),
+            captured("/:left/Comet/Dimmer", Some(Unipolar)),
+            captured("/:left/Comet/Macro/1/1", Some(Select)),
+            captured("/:left/Comet/Macro/1/2", Some(Select)),
+            captured("/:left/Comet/Macro/1/3", Some(Select)),
+            captured("/:left/Comet/Mode/Off", Some(Select)),
+            captured("/:left/Comet/Mode/Auto", Some(Select)),
+            captured("/:right/Comet/Dimmer", Some(Unipolar)),
+        ];
+        assert_eq!(
+            vec![
+                Widget {
+                    name: "Shutter".to_string(),
+                    kind: WidgetKind::Toggle,
+                },
+                Widget {
+                    name: "Speed".to_string(),
+                    kind: WidgetKind::Fader { bipolar: true },
+                },
+                Widget {
+                    name: "Dimmer".to_string(),
+                    kind: WidgetKind::Fader { bipolar: false },
+                },
+                Widget {
+                    name: "Macro".to_string(),
+                    kind: WidgetKind::Grid {
+                        number_x: 1,
+                        number_y: 3
+                    },
+                },
+                Widget {
+                    name: "Mode".to_string(),
+                    kind: WidgetKind::Buttons(vec!["Off".to_string(), "Auto".to_string()]),
+                },
+            ],
+            widgets(prefix, msgs)
+        );
+    }
+
+    #[test]
+    fn test_layout_addrs() {
+        let xml = format!(
+            r#"<layout version="17" mode="1" orientation="vertical">
+            <tabpage name="{page}" scalef="0.0" scalet="1.0" >
+            <control name="{fader}" x="0" y="0" w="84" h="400" color="gray" osc_cs="{dimmer}" type="faderv" ></control>
+            <control name="{grid}" x="0" y="0" w="84" h="168" color="gray" osc_cs="{macro_addr}" type="multitoggle" number_x="1" number_y="2" ></control>
+            <control name="{label}" x="0" y="0" w="84" h="25" color="gray" osc_cs="{dimmer}" type="labelh" ></control>
+            <control name="{toggle}" x="0" y="0" w="84" h="84" color="gray" type="toggle" ></control>
+            <control name="{fader}" x="0" y="0" w="84" h="400" color="gray" osc_cs="{dimmer}" type="faderv" ></control>
+            </tabpage></layout>"#,
+            page = encode("Comet"),
+            fader = encode("fader1"),
+            grid = encode("multitoggle1"),
+            label = encode("label1"),
+            toggle = encode("toggle1"),
+            dimmer = encode("/:left/Comet/Dimmer"),
+            macro_addr = encode("/:left/Comet/Macro"),
+        );
+        assert_eq!(
+            vec![
+                "/:left/Comet/Dimmer",
+                "/:left/Comet/Macro/1/1",
+                "/:left/Comet/Macro/1/2",
+                "/Comet/toggle1",
+            ],
+            layout_addrs(&xml).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_check_addrs() {
+        let sent: Vec<String> = [
+            "/:left/Comet/Dimmer",
+            "/:left/Comet/Macro/1/3",
+            "/Cues/Go",
+            "/:right/Comet/Dimmer",
+            "/Comet/toggle1",
+            "/nope",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        let known = ["/:left/Comet/Dimmer", "/:left/Comet/Macro/1/1", "/Cues/Go"]
+            .into_iter()
+            .map(String::from);
+        let report = check_addrs(&sent, known);
+        assert_eq!(
+            vec!["/:right/Comet/Dimmer", "/Comet/toggle1", "/nope"],
+            report
+                .unhandled
+                .iter()
+                .map(|(addr, _)| addr.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!("no control handler matched", report.unhandled[0].1);
+    }
+}
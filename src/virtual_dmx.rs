@@ -0,0 +1,200 @@
+//! A DMX output that isn't backed by hardware, for rehearsal and testing.
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Context, Result};
+use log::error;
+use serde::Deserialize;
+
+use crate::dmx::{DmxBuffer, DmxOutput, UniverseIdx};
+
+/// Where a virtual DMX output sends its frames.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VirtualDmxConfig {
+    /// Keep the most recent frame in memory.
+    Memory,
+    /// Print the addresses that changed in each frame.
+    Stdout,
+    /// Append every frame to a file, as a little-endian u16 universe index
+    /// followed by the 512 channel values.
+    File(PathBuf),
+}
+
+impl VirtualDmxConfig {
+    /// Parse a sink from the command line: "memory", "stdout", or a file path.
+    pub fn parse(arg: &str) -> Self {
+        match arg {
+            "memory" => Self::Memory,
+            "stdout" => Self::Stdout,
+            path => Self::File(PathBuf::from(path)),
+        }
+    }
+
+    /// Open the sink, creating the output file if there is one.
+    pub fn open(&self) -> Result<VirtualDmx> {
+        let file = match self {
+            Self::File(path) => Some(Arc::new(Mutex::new(create(path)?))),
+            _ => None,
+        };
+        Ok(VirtualDmx {
+            cfg: self.clone(),
+            file,
+        })
+    }
+}
+
+fn create(path: &Path) -> Result<BufWriter<File>> {
+    Ok(BufWriter::new(File::create(path).with_context(|| {
+        format!("creating virtual DMX file {}", path.display())
+    })?))
+}
+
+/// An open virtual DMX sink, which creates outputs for individual universes.
+/// Universes share a single file if writing to one.
+pub struct VirtualDmx {
+    cfg: VirtualDmxConfig,
+    file: Option<Arc<Mutex<BufWriter<File>>>>,
+}
+
+impl VirtualDmx {
+    pub fn output(&self, universe: UniverseIdx) -> VirtualDmxOutput {
+        match (&self.cfg, &self.file) {
+            (VirtualDmxConfig::File(_), Some(file)) => {
+                VirtualDmxOutput::new(universe, Sink::File(file.clone()))
+            }
+            (VirtualDmxConfig::Stdout, _) => VirtualDmxOutput::new(universe, Sink::Stdout),
+            _ => VirtualDmxOutput::memory(universe).0,
+        }
+    }
+}
+
+/// The most recent frame written to a memory sink, and how many frames have
+/// been written.
+#[derive(Default)]
+#[allow(unused)] // read back by tests
+pub struct MemoryFrame {
+    pub frame: Option<DmxBuffer>,
+    pub count: usize,
+}
+
+pub type MemorySink = Arc<Mutex<MemoryFrame>>;
+
+enum Sink {
+    Memory(MemorySink),
+    Stdout,
+    File(Arc<Mutex<BufWriter<File>>>),
+}
+
+/// A virtual output for a single universe.
+pub struct VirtualDmxOutput {
+    universe: UniverseIdx,
+    sink: Sink,
+    last_frame: DmxBuffer,
+    frame_count: usize,
+}
+
+impl VirtualDmxOutput {
+    fn new(universe: UniverseIdx, sink: Sink) -> Self {
+        Self {
+            universe,
+            sink,
+            last_frame: [0; 512],
+            frame_count: 0,
+        }
+    }
+
+    /// Create an output that keeps the most recent frame in memory.
+    /// Return a handle to read frames back.
+    pub fn memory(universe: UniverseIdx) -> (Self, MemorySink) {
+        let sink = MemorySink::default();
+        (Self::new(universe, Sink::Memory(sink.clone())), sink)
+    }
+
+    /// Describe the addresses that changed since the last frame.
+    /// Return None if nothing changed.
+    fn changes(&self, frame: &DmxBuffer) -> Option<String> {
+        let mut changes = String::new();
+        for (i, (old, new)) in self.last_frame.iter().zip(frame).enumerate() {
+            if old != new {
+                write!(changes, " {}={new}", i + 1).unwrap();
+            }
+        }
+        (!changes.is_empty()).then(|| {
+            format!(
+                "universe {} frame {}:{changes}",
+                self.universe, self.frame_count
+            )
+        })
+    }
+}
+
+impl DmxOutput for VirtualDmxOutput {
+    fn write(&mut self, frame: &DmxBuffer) -> Result<()> {
+        match &self.sink {
+            Sink::Memory(sink) => {
+                let mut sink = sink
+                    .lock()
+                    .map_err(|_| anyhow!("virtual DMX memory lock poisoned"))?;
+                sink.frame = Some(*frame);
+                sink.count += 1;
+            }
+            Sink::Stdout => {
+                if let Some(changes) = self.changes(frame) {
+                    println!("{changes}");
+                }
+            }
+            Sink::File(file) => {
+                let mut file = file
+                    .lock()
+                    .map_err(|_| anyhow!("virtual DMX file lock poisoned"))?;
+                file.write_all(&(self.universe as u16).to_le_bytes())?;
+                file.write_all(frame)?;
+            }
+        }
+        self.last_frame = *frame;
+        self.frame_count += 1;
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        if let Sink::File(file) = &self.sink {
+            let Ok(mut file) = file.lock() else {
+                return;
+            };
+            if let Err(err) = file.flush() {
+                error!("Failed to flush virtual DMX file: {err}.");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_memory_and_changes() {
+        let (mut output, sink) = VirtualDmxOutput::memory(1);
+        let mut frame = [0; 512];
+        assert!(output.changes(&frame).is_none());
+        frame[0] = 255;
+        frame[9] = 3;
+        assert_eq!(
+            "universe 1 frame 0: 1=255 10=3",
+            output.changes(&frame).unwrap()
+        );
+        output.write(&frame).unwrap();
+        output.write(&frame).unwrap();
+        assert!(output.changes(&frame).is_none());
+
+        let sink = sink.lock().unwrap();
+        assert_eq!(2, sink.count);
+        assert_eq!(Some(frame), sink.frame);
+    }
+}
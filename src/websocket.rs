@@ -0,0 +1,201 @@
+//! Stream show state to browser-based control surfaces over WebSockets.
+//!
+//! Every connected client receives each state change the show emits as a
+//! JSON text message, in one of these forms:
+//! - {"osc": {"addr": ..., "args": [...]}}: an OSC state change
+//! - {"channel": ...}: a channel state change, as sent to MIDI devices
+//! - {"master": ...}: a master state change, as sent to MIDI devices
+//!
+//! Clients control the show by sending {"addr": ..., "value": ...} messages,
+//! which are handled exactly like OSC control messages. Each client is
+//! identified by its address just like an OSC client, so talkback treats
+//! WebSocket and OSC clients alike. Each client is sent the current state of
+//! the whole show when it connects; send /Meta/RefreshUI to get it again.
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+use log::{error, info};
+use rosc::OscMessage;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tungstenite::{Error as WsError, Message, WebSocket};
+
+use crate::{
+    channel::StateChange as ChannelStateChange,
+    control::ControlMessage,
+    http::{osc_json, parse_control},
+    master::StateChange as MasterStateChange,
+    osc::{OscClientId, TalkbackMode},
+};
+
+/// How long each client thread waits for an incoming message before checking
+/// for outgoing messages.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A state change to send to WebSocket clients.
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateMessage {
+    Osc(Value),
+    Channel(ChannelStateChange),
+    Master(MasterStateChange),
+}
+
+impl From<&OscMessage> for StateMessage {
+    fn from(msg: &OscMessage) -> Self {
+        Self::Osc(osc_json(msg))
+    }
+}
+
+pub struct WebSocketResponse {
+    pub sender_id: Option<OscClientId>,
+    pub talkback: TalkbackMode,
+    pub msg: StateMessage,
+}
+
+/// A connected client, and the channel to its thread.
+struct Client {
+    id: OscClientId,
+    send: Sender<Arc<String>>,
+}
+
+type Clients = Arc<Mutex<Vec<Client>>>;
+
+pub struct WebSocketController {
+    send: Sender<WebSocketResponse>,
+}
+
+impl WebSocketController {
+    /// Start accepting WebSocket clients on the provided address.
+    pub fn run(addr: SocketAddr, send: Sender<ControlMessage>) -> Result<Self> {
+        let listener = TcpListener::bind(addr)
+            .with_context(|| format!("starting WebSocket server on {addr}"))?;
+        info!("Serving WebSockets on {addr}.");
+        let clients = Clients::default();
+
+        let accept_clients = clients.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        error!("WebSocket accept error: {err}.");
+                        continue;
+                    }
+                };
+                let clients = accept_clients.clone();
+                let send = send.clone();
+                thread::spawn(move || {
+                    if let Err(err) = run_client(stream, &clients, &send) {
+                        error!("WebSocket client error: {err:#}.");
+                    }
+                });
+            }
+        });
+
+        let (send_response, recv_response) = channel();
+        thread::spawn(move || broadcast(recv_response, &clients));
+        Ok(Self {
+            send: send_response,
+        })
+    }
+
+    pub fn send(&self, msg: WebSocketResponse) {
+        if self.send.send(msg).is_err() {
+            error!("WebSocket send channel is disconnected.");
+        }
+    }
+}
+
+/// Send every response to every client, respecting talkback.
+fn broadcast(recv: Receiver<WebSocketResponse>, clients: &Mutex<Vec<Client>>) {
+    for resp in recv {
+        let msg = match serde_json::to_string(&resp.msg) {
+            Ok(msg) => Arc::new(msg),
+            Err(err) => {
+                error!("Error encoding WebSocket message: {err}.");
+                continue;
+            }
+        };
+        let Ok(mut clients) = clients.lock() else {
+            error!("Failed to get WebSocket client lock.");
+            continue;
+        };
+        // Drop clients whose threads have exited.
+        clients.retain(|client| {
+            if !resp.talkback.sends_to(resp.sender_id.as_ref(), &client.id) {
+                return true;
+            }
+            client.send.send(msg.clone()).is_ok()
+        });
+    }
+    info!("WebSocket sender channel hung up, terminating sender thread.");
+}
+
+/// Register a client, then pass messages to and from it until it disconnects.
+fn run_client(
+    stream: TcpStream,
+    clients: &Mutex<Vec<Client>>,
+    send: &Sender<ControlMessage>,
+) -> Result<()> {
+    let id = OscClientId::new(stream.peer_addr()?);
+    let mut ws = tungstenite::accept(stream).context("WebSocket handshake")?;
+    ws.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+
+    let (send_outgoing, recv_outgoing) = channel();
+    clients.lock().unwrap().push(Client {
+        id,
+        send: send_outgoing,
+    });
+    info!("WebSocket client connected from {}.", id.addr());
+    // Like a new OSC client, a new WebSocket client is sent the whole show.
+    send.send(ControlMessage::OscClientRegistered(id))
+        .map_err(|_| anyhow!("show is not running"))?;
+
+    let result = serve(&mut ws, id, &recv_outgoing, send);
+    clients.lock().unwrap().retain(|client| client.id != id);
+    info!("WebSocket client at {} disconnected.", id.addr());
+    // Let the show forget the client's channel and animator selections.
+    // If the show isn't running there's nothing to forget.
+    let _ = send.send(ControlMessage::OscClientExpired(id));
+    result
+}
+
+fn serve(
+    ws: &mut WebSocket<TcpStream>,
+    id: OscClientId,
+    outgoing: &Receiver<Arc<String>>,
+    send: &Sender<ControlMessage>,
+) -> Result<()> {
+    loop {
+        match ws.read() {
+            Ok(Message::Text(text)) => match parse_control(&text, id) {
+                Ok(msg) => send
+                    .send(ControlMessage::Osc(msg))
+                    .map_err(|_| anyhow!("show is not running"))?,
+                Err(err) => {
+                    ws.send(Message::text(
+                        json!({ "error": format!("{err:#}") }).to_string(),
+                    ))?;
+                }
+            },
+            Ok(Message::Close(_)) | Err(WsError::ConnectionClosed) => return Ok(()),
+            Ok(_) => (),
+            Err(WsError::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(err) => return Err(err.into()),
+        }
+        for msg in outgoing.try_iter() {
+            ws.send(Message::text(msg.as_str()))?;
+        }
+    }
+}